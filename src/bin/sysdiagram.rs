@@ -1,377 +1,939 @@
+//! Command line interface for sysdiagrams
+//!
+//! Exit codes:
+//!
+//! - `0`: success
+//! - `1`: the input could not be read or parsed, or the output could not be written
+//! - `2`: `validate` found streams that are not reproduced when writing the diagram
+
 use anyhow::Context;
 use mapr::Mmap;
 use ms_oforms::controls::user_form::FormControl;
-use ms_oforms::properties::color::{OleColor, RgbColor};
-use ms_oforms::properties::{Position, Size};
-use std::io::Cursor;
-use std::path::PathBuf;
-use std::{fs::File, time::UNIX_EPOCH};
-use sysdiagram::dds::DdsPolylineEndType;
-use sysdiagram::dsref::DSRefSchemaContents;
-use sysdiagram::{get_settings, Control, Error, SiteInfo, SysDiagramFile};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+use sysdiagram::render::{
+    dbml::{write_dbml, DbmlOptions},
+    dot::{write_dot, DotOptions},
+    drawio::{write_drawio, DrawioOptions},
+    mermaid::write_mermaid,
+    plantuml::{write_plantuml, PlantUmlOptions},
+    svg::{write_svg, SvgOptions, Theme},
+};
+use sysdiagram::{
+    dds::{DdsPolylineEndType, DdsStream, DdsStreamCtrl},
+    decode_base64, decode_hex,
+    dsref::DsRefNode,
+    get_settings, parse_column_catalog, parse_sysdiagrams_csv, write_hexdump, write_sql_script,
+    ColumnCatalog, Control, DSRefSchemaContents, DiagramRecord, Error, ScriptOptions,
+    ScriptStatement, SiteInfo, SysDiagram, SysDiagramFile, Variant,
+};
+use uuid::Uuid;
+
+type Reader = SysDiagramFile<Cursor<Vec<u8>>>;
+
+/// Output format for the printed information
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// Human readable
+    Text,
+    Json,
+    Yaml,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
+            _ => Err(format!(
+                "unknown format {:?}, expected text, json or yaml",
+                s
+            )),
+        }
+    }
+}
+
+/// The document printed with `--format json` or `--format yaml`
+///
+/// Positions and sizes are in HIMETRIC (1/100 mm). Unknown bytes are hex strings.
+#[derive(Serialize)]
+struct Document<'a> {
+    /// Sizes of the form (i.e. the diagram surface)
+    form: FormInfo,
+    /// The CLSIDs of the controls, indexed by the `clsid_cache_index` of a site
+    site_classes: Vec<Uuid>,
+    /// All sites, in the order of the `o` stream
+    controls: Vec<ControlInfo<'a>>,
+    /// When the `DSREF-SCHEMA-CONTENTS` were written, in seconds since the UNIX epoch
+    dsref_time: u64,
+    /// The DSRef tree, the root is the connection and the first child the diagram
+    dsref: &'a DsRefNode,
+    /// The fields of the connection string in the root DSRef node
+    settings: BTreeMap<String, String>,
+    /// Flags of the `\3DdsStream`
+    dds_flags: u32,
+    /// Properties of the form from the `\3DdsStream`
+    dds_properties: &'a BTreeMap<String, Variant>,
+}
+
+#[derive(Serialize)]
+struct Extent {
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize)]
+struct FormInfo {
+    logical_size: Extent,
+    displayed_size: Extent,
+    scroll_left: i32,
+    scroll_top: i32,
+}
+
+#[derive(Serialize)]
+struct ControlInfo<'a> {
+    /// The site in the `f` stream (ID, position, tooltip)
+    site: &'a SiteInfo,
+    /// The control data, tagged with `SchGrid`, `Label`, `Polyline` or `Unknown`
+    control: &'a Control,
+    /// The entry of the `\3DdsStream` for the site
+    dds: Option<&'a DdsStreamCtrl>,
+}
+
+fn document<'a>(
+    form_control: &FormControl,
+    controls: &'a [(SiteInfo, Control)],
+    dds_stream: &'a DdsStream,
+    dsref_schema_contents: &'a DSRefSchemaContents,
+) -> Document<'a> {
+    let root = &dsref_schema_contents.root_node;
+    let settings = root
+        .name
+        .as_deref()
+        .and_then(|name| get_settings(name).ok())
+        .unwrap_or_default();
+    Document {
+        form: FormInfo {
+            logical_size: Extent {
+                width: form_control.logical_size.width,
+                height: form_control.logical_size.height,
+            },
+            displayed_size: Extent {
+                width: form_control.displayed_size.width,
+                height: form_control.displayed_size.height,
+            },
+            scroll_left: form_control.scroll_position.left,
+            scroll_top: form_control.scroll_position.top,
+        },
+        site_classes: form_control.site_classes.iter().map(|c| c.cls_id).collect(),
+        controls: controls
+            .iter()
+            .map(|(site, control)| ControlInfo {
+                site,
+                control,
+                dds: dds_stream.controls.iter().find(|c| c.id1 == site.id),
+            })
+            .collect(),
+        dsref_time: dsref_schema_contents.get_time(),
+        dsref: root,
+        settings: settings.into_iter().collect(),
+        dds_flags: dds_stream.header.flags,
+        dds_properties: &dds_stream.header.properties,
+    }
+}
 
 #[derive(argh::FromArgs)]
 /// parse a SSMS database diagram (sysdiagram)
 struct Options {
+    #[argh(switch)]
+    /// assume the input file is base64 encoded
+    base64: bool,
+
+    #[argh(switch)]
+    /// assume the input file is hex encoded (optional `0x` prefix)
+    hex: bool,
+
+    #[argh(subcommand)]
+    command: Command,
+}
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Info(InfoCommand),
+    Dump(DumpCommand),
+    Tables(TablesCommand),
+    Relationships(RelationshipsCommand),
+    Export(ExportCommand),
+    Validate(ValidateCommand),
+    Streams(StreamsCommand),
+    Extract(ExtractCommand),
+    Hexdump(HexdumpCommand),
+    Script(ScriptCommand),
+    Csv(CsvCommand),
+}
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "info")]
+/// print an overview of the diagram
+struct InfoCommand {
     /// path to the sysdiagram blob
     #[argh(positional)]
     file: PathBuf,
 
-    #[argh(switch)]
-    /// assume the file is base64 encoded
-    base64: bool,
+    #[argh(option, default = "Format::Text")]
+    /// output format: text, json (the full document) or yaml
+    format: Format,
+}
 
-    #[argh(switch)]
-    /// print relationships
-    relationships: bool,
+/// A stream that `dump` can print
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamName {
+    CompObj,
+    Form,
+    Objects,
+    DdsStream,
+    DsRef,
+}
 
-    #[argh(switch)]
-    /// print clsid table
-    classes: bool,
+impl FromStr for StreamName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compobj" => Ok(StreamName::CompObj),
+            "f" => Ok(StreamName::Form),
+            "o" => Ok(StreamName::Objects),
+            "dds" => Ok(StreamName::DdsStream),
+            "dsref" => Ok(StreamName::DsRef),
+            _ => Err(format!(
+                "unknown stream {:?}, expected compobj, f, o, dds or dsref",
+                s
+            )),
+        }
+    }
+}
 
-    #[argh(switch)]
-    /// print form
-    form: bool,
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "dump")]
+/// print the parsed content of one stream
+struct DumpCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
 
-    #[argh(switch)]
-    /// print \3DdsStream
-    dds_stream: bool,
+    /// the stream: compobj, f, o, dds or dsref
+    #[argh(positional)]
+    stream: StreamName,
 
-    #[argh(switch)]
-    /// print form size
-    size: bool,
+    #[argh(option, default = "Format::Text")]
+    /// output format: text, json or yaml (o, dds and dsref only)
+    format: Format,
+}
 
-    #[argh(switch)]
-    /// print cfb streams
-    streams: bool,
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "tables")]
+/// list the tables
+struct TablesCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
 
-    #[argh(switch)]
-    /// print connection string fields
-    settings: bool,
+    #[argh(option, default = "Format::Text")]
+    /// output format: text, json or yaml
+    format: Format,
+}
 
-    #[argh(switch)]
-    /// print DSRef
-    dsref: bool,
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "relationships")]
+/// list the relationships
+struct RelationshipsCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
+
+    #[argh(option, default = "Format::Text")]
+    /// output format: text, json or yaml
+    format: Format,
+}
+
+/// Target format of `export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Svg,
+    Dot,
+    Mermaid,
+    PlantUml,
+    Drawio,
+    Dbml,
+    Json,
+    Yaml,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "svg" => Ok(ExportFormat::Svg),
+            "dot" => Ok(ExportFormat::Dot),
+            "mermaid" => Ok(ExportFormat::Mermaid),
+            "plantuml" => Ok(ExportFormat::PlantUml),
+            "drawio" => Ok(ExportFormat::Drawio),
+            "dbml" => Ok(ExportFormat::Dbml),
+            "json" => Ok(ExportFormat::Json),
+            "yaml" => Ok(ExportFormat::Yaml),
+            _ => Err(format!(
+                "unknown format {:?}, expected svg, dot, mermaid, plantuml, drawio, dbml, json or yaml",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "export")]
+/// convert the diagram to another format
+struct ExportCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
+
+    #[argh(option)]
+    /// target format: svg, dot, mermaid, plantuml, drawio, dbml, json or yaml
+    to: ExportFormat,
+
+    #[argh(option, short = 'o')]
+    /// output file (default: stdout)
+    out: Option<PathBuf>,
+
+    #[argh(option)]
+    /// pipe-separated column list (schema|table|column|type|nullable|pk)
+    columns: Option<PathBuf>,
+
+    #[argh(option, default = "1.0")]
+    /// scale factor for the SVG size
+    scale: f32,
 
     #[argh(switch)]
-    /// print tables
-    tables: bool,
+    /// use a dark theme for the SVG
+    dark: bool,
 
     #[argh(switch)]
-    /// print labels
-    labels: bool,
+    /// enable SVG visual debug nodes
+    debug: bool,
 
     #[argh(switch)]
-    /// print \1CompObj info
-    comp_obj: bool,
+    /// pin the DOT nodes at their diagram positions (for `neato -n`)
+    pinned: bool,
+}
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "validate")]
+/// check that the diagram parses and is reproduced when written (exit code 2 if not)
+struct ValidateCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
+}
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "streams")]
+/// list the streams of the compound file
+struct StreamsCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
+}
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "extract")]
+/// write the raw bytes of the streams to a directory
+struct ExtractCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
+
+    #[argh(option, short = 'o', default = "PathBuf::from(\".\")")]
+    /// output directory
+    out: PathBuf,
+
+    #[argh(option, short = 's')]
+    /// a stream to extract, e.g. `f`, `o` or `DdsStream` (repeatable, default: all)
+    stream: Vec<String>,
 
     #[argh(switch)]
-    /// generate SVG
-    svg: bool,
+    /// also write the bytes of each site in `o` to `o.<id>.<clsid>.bin`
+    sites: bool,
+}
+
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "hexdump")]
+/// print the bytes of a stream with the fields they were parsed into (`?` marks unknown bytes)
+struct HexdumpCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
+
+    /// the stream, e.g. `f`, `o`, `DdsStream` or `DSREF-SCHEMA-CONTENTS`
+    #[argh(positional)]
+    stream: String,
 
     #[argh(switch)]
-    /// enable SVG visual debug nodes
-    debug: bool,
+    /// highlight unknown bytes with ANSI colors
+    color: bool,
 }
 
-fn color(r: OleColor) -> RgbColor {
-    match r {
-        OleColor::Default(d) | OleColor::RgbColor(d) => d,
-        OleColor::SystemPalette(p) => {
-            let color = p
-                .as_system_color()
-                .expect("expected well-known system palette index");
-            RgbColor::from(color)
+/// Statement emitted by `script`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StatementArg(ScriptStatement);
+
+impl FromStr for StatementArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insert" => Ok(StatementArg(ScriptStatement::Insert)),
+            "create" => Ok(StatementArg(ScriptStatement::CreateDiagram)),
+            "alter" => Ok(StatementArg(ScriptStatement::AlterDiagram)),
+            _ => Err(format!(
+                "unknown statement {:?}, expected insert, create or alter",
+                s
+            )),
         }
-        OleColor::PaletteEntry(e) => todo!("{:?}", e),
     }
 }
 
-fn load_database(opts: &Options) -> Result<(), anyhow::Error> {
-    // Load the database file
-    let file = File::open(&opts.file)
-        .with_context(|| format!("Failed to open input file '{}'", opts.file.display()))?;
-    let mmap = unsafe { Mmap::map(&file)? };
-    let buf: &[u8] = &mmap;
-    let cursor = Cursor::new(buf);
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "script")]
+/// print a T-SQL script that stores the diagram in `dbo.sysdiagrams`
+struct ScriptCommand {
+    /// path to the sysdiagram blob
+    #[argh(positional)]
+    file: PathBuf,
 
-    if opts.base64 {
-        unimplemented!("--base64 is unimplemented");
-    }
+    #[argh(option)]
+    /// name of the diagram (default: the name stored in the diagram)
+    name: Option<String>,
 
-    let mut reader = SysDiagramFile::open(cursor).map_err(Error::Cfb)?;
+    #[argh(option, default = "1")]
+    /// principal_id of the owner (default: 1, i.e. dbo)
+    principal_id: i32,
 
-    if opts.streams && !opts.svg {
-        let root = reader.root_entry();
-        let ctime = root.created().duration_since(UNIX_EPOCH);
-        let mtime = root.modified().duration_since(UNIX_EPOCH);
-        eprintln!("created: {:?}, modified: {:?}", ctime, mtime);
+    #[argh(option, default = "1")]
+    /// version of the diagram (default: 1)
+    version: i32,
 
-        eprintln!("Root CLSID: {}", root.clsid());
-        let entries = reader.read_root_storage();
+    #[argh(option, default = "StatementArg(ScriptStatement::Insert)")]
+    /// statement: insert, create (sp_creatediagram) or alter (sp_alterdiagram)
+    statement: StatementArg,
 
-        eprintln!("CFB Streams:");
-        for entry in entries {
-            println!("- {:?}: {}", entry.name(), entry.path().display());
-        }
-    }
+    #[argh(option, default = "4000")]
+    /// maximum number of bytes per 0x literal
+    chunk_size: usize,
 
-    let comp_obj = reader.root_comp_obj()?;
-    if opts.comp_obj && !opts.svg {
-        println!("{:?}", comp_obj);
-    }
+    #[argh(option, short = 'o')]
+    /// output file (default: stdout)
+    out: Option<PathBuf>,
+}
 
-    eprintln!("Parsing DSREF-SCHEMA-CONTENT");
-    let dsref_schema_contents = reader.dsref_schema_contents()?;
-    if opts.settings && !opts.svg {
-        if let Ok(settings) = get_settings(dsref_schema_contents.root_node.name.as_ref().unwrap()) {
-            for (key, value) in &settings {
-                println!("{:25}: {}", key, value);
-            }
-        } else {
-            eprintln!(
-                "Failed to parse connection string:\n{:?}",
-                dsref_schema_contents.root_node.name
-            );
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "csv")]
+/// extract all diagrams from a pipe-separated sysdiagrams export
+struct CsvCommand {
+    /// path to the sysdiagrams.csv file
+    #[argh(positional)]
+    file: PathBuf,
+
+    #[argh(option, short = 'o', default = "PathBuf::from(\".\")")]
+    /// output directory for the `<diagram_id>_<name>.sysdiagram` files
+    out: PathBuf,
+
+    #[argh(switch)]
+    /// only list the diagrams
+    list: bool,
+}
+
+fn extract_csv(cmd: &CsvCommand) -> Result<(), anyhow::Error> {
+    let input = std::fs::read(&cmd.file)
+        .with_context(|| format!("Failed to open input file '{}'", cmd.file.display()))?;
+    let records = parse_sysdiagrams_csv(&input)?;
+
+    for record in &records {
+        println!(
+            "{:>3} {:>3} {:?} {:?} ({} bytes)",
+            record.diagram_id,
+            record.principal_id,
+            record.version,
+            record.name,
+            record.definition.len()
+        );
+        if cmd.list {
+            continue;
+        }
+        // Make sure the file is a sysdiagram
+        if let Err(e) = record.open() {
+            eprintln!("Skipping diagram {:?}: {}", record.name, e);
+            continue;
         }
+        let path = cmd.out.join(record.file_name());
+        std::fs::write(&path, &record.definition)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
     }
-    if opts.dsref && !opts.svg {
-        println!("time: {}", dsref_schema_contents.get_time());
-        println!("{:#?}", dsref_schema_contents);
+    Ok(())
+}
+
+fn load_columns(path: Option<&Path>) -> Result<ColumnCatalog, anyhow::Error> {
+    match path {
+        Some(path) => {
+            let input = std::fs::read(path)
+                .with_context(|| format!("Failed to open columns file '{}'", path.display()))?;
+            Ok(parse_column_catalog(&input)?)
+        }
+        None => Ok(ColumnCatalog::default()),
     }
+}
 
-    let (form_control, controls, diagram) = reader.schema_form()?;
+/// Read the input file, decoding base64 or hex if requested
+fn read_input(opts: &Options, path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open input file '{}'", path.display()))?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    let buf: &[u8] = &mmap;
 
-    if opts.svg {
-        generate_svg(&dsref_schema_contents, &controls, &form_control, opts.debug);
-        return Ok(());
-    }
+    Ok(match (opts.base64, opts.hex) {
+        (true, true) => anyhow::bail!("--base64 and --hex are mutually exclusive"),
+        (true, false) => decode_base64(buf).context("Failed to decode base64")?,
+        (false, true) => decode_hex(buf).context("Failed to decode hex")?,
+        (false, false) => buf.to_vec(),
+    })
+}
+
+fn open(opts: &Options, path: &Path) -> Result<Reader, anyhow::Error> {
+    let data = read_input(opts, path)?;
+    Ok(SysDiagramFile::open(Cursor::new(data)).map_err(Error::Cfb)?)
+}
 
-    if opts.form {
-        println!("{:#?}", form_control);
+fn load_diagram(opts: &Options, path: &Path) -> Result<SysDiagram, anyhow::Error> {
+    Ok(open(opts, path)?.diagram()?)
+}
+
+/// Print a value as JSON or YAML
+fn print_structured<T: Serialize>(format: Format, value: &T) -> Result<(), anyhow::Error> {
+    let mut out = io::stdout().lock();
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, value)?;
+            writeln!(out)?;
+        }
+        Format::Yaml => serde_yaml::to_writer(&mut out, value)?,
+        Format::Text => unreachable!("text output is written by each command"),
     }
-    if opts.size {
-        println!("logical: {:?}", form_control.logical_size);
-        println!("displayed: {:?}", form_control.displayed_size);
-        println!("scroll: {:?}", form_control.scroll_position);
+    Ok(())
+}
+
+fn info(opts: &Options, cmd: &InfoCommand) -> Result<(), anyhow::Error> {
+    let mut reader = open(opts, &cmd.file)?;
+    let dsref_schema_contents = reader.dsref_schema_contents()?;
+    let (form_control, controls, dds_stream) = reader.schema_form()?;
+    if cmd.format != Format::Text {
+        let document = document(
+            &form_control,
+            &controls,
+            &dds_stream,
+            &dsref_schema_contents,
+        );
+        return print_structured(cmd.format, &document);
     }
-    if opts.classes {
-        for c in form_control.site_classes {
-            println!("- {:?}", c);
+
+    let count = |f: fn(&Control) -> bool| controls.iter().filter(|(_, c)| f(c)).count();
+    let name = dsref_schema_contents
+        .root_node
+        .children
+        .first()
+        .and_then(|n| n.name.as_deref());
+    println!("name:          {}", name.unwrap_or("-"));
+    println!("time:          {}", dsref_schema_contents.get_time());
+    println!("logical size:  {:?}", form_control.logical_size);
+    println!("displayed:     {:?}", form_control.displayed_size);
+    println!("scroll:        {:?}", form_control.scroll_position);
+    println!(
+        "tables:        {}",
+        count(|c| matches!(c, Control::SchGrid(_)))
+    );
+    println!(
+        "relationships: {}",
+        count(|c| matches!(c, Control::Polyline(_)))
+    );
+    println!(
+        "labels:        {}",
+        count(|c| matches!(c, Control::Label(_)))
+    );
+    println!(
+        "unknown:       {}",
+        count(|c| matches!(c, Control::Unknown { .. }))
+    );
+    if let Some(Ok(settings)) = dsref_schema_contents
+        .root_node
+        .name
+        .as_deref()
+        .map(get_settings)
+    {
+        let settings: BTreeMap<_, _> = settings.into_iter().collect();
+        for (key, value) in &settings {
+            println!("{:25}: {}", key, value);
         }
     }
+    Ok(())
+}
 
-    for (site, control) in controls.iter().filter(|(_, c)| match c {
-        Control::Label(_) => opts.labels,
-        Control::Polyline(_) => opts.relationships,
-        Control::SchGrid(_) => opts.tables,
-        _ => false,
-    }) {
-        println!();
-        println!("==> {:?}", site);
-        if let Some(dds_ctrl) = diagram.controls.iter().find(|c| c.id1 == site.id) {
-            println!("{:?}", dds_ctrl);
+fn dump(opts: &Options, cmd: &DumpCommand) -> Result<(), anyhow::Error> {
+    let mut reader = open(opts, &cmd.file)?;
+    let text = cmd.format == Format::Text;
+    match cmd.stream {
+        StreamName::CompObj if text => println!("{:#?}", reader.root_comp_obj()?),
+        StreamName::Form if text => println!("{:#?}", reader.form_stream()?),
+        StreamName::CompObj | StreamName::Form => {
+            anyhow::bail!("{:?} is only available as text", cmd.stream)
         }
-        match control {
-            Control::SchGrid(sch_grid) => {
-                println!("{:?}", sch_grid.extent);
-                println!("caption: {:?}", sch_grid.frame.caption);
-                for layout in &sch_grid.frame.layouts[..] {
-                    println!("- {:?}", layout);
+        StreamName::Objects => {
+            let (_, controls, _) = reader.schema_form()?;
+            if text {
+                for (site, control) in &controls {
+                    println!("==> {:?}", site);
+                    println!("{:#?}", control);
                 }
-                println!("{:?}", sch_grid.data_source);
-            }
-            Control::Label(label) => {
-                println!("{:?}", label);
+            } else {
+                print_structured(cmd.format, &controls)?;
             }
-            Control::Polyline(polyline) => {
-                println!("{:?}", polyline);
+        }
+        StreamName::DdsStream => {
+            let (_, _, dds_stream) = reader.schema_form()?;
+            if text {
+                println!("{:#?}", dds_stream);
+            } else {
+                print_structured(cmd.format, &dds_stream)?;
             }
-            Control::Unknown(_clsid) => {
-                // TODO?
+        }
+        StreamName::DsRef => {
+            let dsref_schema_contents = reader.dsref_schema_contents()?;
+            if text {
+                println!("time: {}", dsref_schema_contents.get_time());
+                println!("{:#?}", dsref_schema_contents);
+            } else {
+                print_structured(cmd.format, &dsref_schema_contents)?;
             }
         }
     }
+    Ok(())
+}
 
-    if opts.dds_stream {
-        println!("{:?}", diagram.header);
-        for ctrl in &diagram.controls {
-            println!("{:?}", ctrl);
-        }
-        println!("{:?}", diagram.numbers);
+/// An entry of `tables --format json`
+#[derive(Serialize)]
+struct TableInfo<'a> {
+    id: i32,
+    schema: &'a str,
+    table: &'a str,
+    caption: &'a str,
+    left: i32,
+    top: i32,
+    width: u32,
+    height: u32,
+    view: Option<String>,
+}
+
+fn tables(opts: &Options, cmd: &TablesCommand) -> Result<(), anyhow::Error> {
+    let diagram = load_diagram(opts, &cmd.file)?;
+    let tables: Vec<_> = diagram
+        .tables
+        .iter()
+        .map(|t| TableInfo {
+            id: t.id,
+            schema: &t.sch_grid.data_source.schema,
+            table: &t.sch_grid.data_source.table,
+            caption: &t.caption,
+            left: t.pos.left,
+            top: t.pos.top,
+            width: t.sch_grid.extent.width,
+            height: t.sch_grid.extent.height,
+            view: t.view.map(|v| format!("{:?}", v)),
+        })
+        .collect();
+    if cmd.format != Format::Text {
+        return print_structured(cmd.format, &tables);
     }
+    for t in &tables {
+        println!(
+            "{:>4} {}.{} at ({}, {}) size {}x{} {}",
+            t.id,
+            t.schema,
+            t.table,
+            t.left,
+            t.top,
+            t.width,
+            t.height,
+            t.view.as_deref().unwrap_or("-"),
+        );
+    }
+    Ok(())
+}
 
+/// An entry of `relationships --format json`
+#[derive(Serialize)]
+struct RelationshipInfo<'a> {
+    id: i32,
+    name: &'a str,
+    from: &'a str,
+    to: &'a str,
+    end_type_src: DdsPolylineEndType,
+    end_type_dest: DdsPolylineEndType,
+    /// `[left, top]` of every point of the line
+    points: Vec<[i32; 2]>,
+    labels: Vec<&'a str>,
+}
+
+fn relationships(opts: &Options, cmd: &RelationshipsCommand) -> Result<(), anyhow::Error> {
+    let diagram = load_diagram(opts, &cmd.file)?;
+    let relationships: Vec<_> = diagram
+        .relationships
+        .iter()
+        .map(|r| RelationshipInfo {
+            id: r.id,
+            name: &r.name,
+            from: &r.from,
+            to: &r.to,
+            end_type_src: r.control.end_type_src,
+            end_type_dest: r.control.end_type_dest,
+            points: r
+                .control
+                .positions
+                .iter()
+                .map(|p| [p.left, p.top])
+                .collect(),
+            labels: r.labels.iter().map(|a| a.label.text.as_str()).collect(),
+        })
+        .collect();
+    if cmd.format != Format::Text {
+        return print_structured(cmd.format, &relationships);
+    }
+    for r in &relationships {
+        println!(
+            "{:>4} {}: {} ({:?}) -> {} ({:?}), {} points",
+            r.id,
+            r.name,
+            r.from,
+            r.end_type_src,
+            r.to,
+            r.end_type_dest,
+            r.points.len(),
+        );
+    }
     Ok(())
 }
 
-fn generate_svg(
-    dsref_schema_contents: &DSRefSchemaContents,
-    controls: &[(SiteInfo, Control)],
-    form_control: &FormControl,
-    debug: bool,
-) {
-    let title = dsref_schema_contents.root_node.children[0]
-        .name
-        .as_deref()
-        .unwrap();
-    println!(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
-    println!(r#"<svg xmlns="http://www.w3.org/2000/svg""#);
-    println!(r#"    xmlns:xlink="http://www.w3.org/1999/xlink""#);
-    println!(r#"    version="1.1" baseProfile="full""#);
-    let min_x = controls.iter().map(|(s, _)| s.pos.left).min().unwrap() as f32 / 100.0;
-    let min_y = controls.iter().map(|(s, _)| s.pos.top).min().unwrap() as f32 / 100.0;
-    let (f_width, f_height) = size_himetric_to_mm(form_control.logical_size);
-    println!(r#"    width="{}mm" height="{}mm""#, f_width, f_height);
-    println!(
-        r#"    viewBox="{} {} {} {}""#,
-        min_x - 10.0,
-        min_y - 10.0,
-        f_width,
-        f_height
-    );
+fn export(opts: &Options, cmd: &ExportCommand) -> Result<(), anyhow::Error> {
+    let diagram = load_diagram(opts, &cmd.file)?;
+    let columns = load_columns(cmd.columns.as_deref())?;
+    let mut out: Box<dyn Write> = match &cmd.out {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("Failed to create '{}'", path.display()))?,
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    match cmd.to {
+        ExportFormat::Svg => {
+            let options = SvgOptions {
+                scale: cmd.scale,
+                theme: if cmd.dark {
+                    Theme::dark()
+                } else {
+                    Theme::light()
+                },
+                debug: cmd.debug,
+                columns,
+                ..SvgOptions::default()
+            };
+            write_svg(&mut out, &diagram, &options)?
+        }
+        ExportFormat::Dot => {
+            let options = DotOptions {
+                pinned: cmd.pinned,
+                columns,
+            };
+            write_dot(&mut out, &diagram, &options)?
+        }
+        ExportFormat::Mermaid => write_mermaid(&mut out, &diagram)?,
+        ExportFormat::PlantUml => write_plantuml(&mut out, &diagram, &PlantUmlOptions { columns })?,
+        ExportFormat::Drawio => write_drawio(&mut out, &diagram, &DrawioOptions { columns })?,
+        ExportFormat::Dbml => write_dbml(&mut out, &diagram, &DbmlOptions { columns })?,
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &diagram)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Yaml => serde_yaml::to_writer(&mut out, &diagram)?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The streams that [`validate`] compares
+const STREAMS: [&str; 7] = [
+    "/f",
+    "/o",
+    "/\x01CompObj",
+    "/\x03DdsStream",
+    "/DSREF-SCHEMA-CONTENTS",
+    "/Schema UDV Default",
+    "/Schema UDV Default Post V6",
+];
+
+/// Returns whether all streams are reproduced
+fn validate(opts: &Options, cmd: &ValidateCommand) -> Result<bool, anyhow::Error> {
+    let mut reader = open(opts, &cmd.file)?;
+    let diagram = reader.diagram()?;
     println!(
-        r#"    style="background-color: {}""#,
-        color(form_control.back_color)
+        "parsed {} tables, {} relationships, {} labels",
+        diagram.tables.len(),
+        diagram.relationships.len(),
+        diagram.labels.len()
     );
-    println!(">");
-    println!(r#"    <title>{}</title>"#, title);
-    println!(r#"    <desc>Beschreibung/Textalternative zum Inhalt.</desc>"#);
-    println!(r#"<circle cx="0" cy="0" r="4" fill="red" />"#);
-    for (site, control) in controls {
-        let (x, y) = pos_himetric_to_mm(&site.pos);
-        match control {
-            Control::SchGrid(sch_grid) => {
-                if debug {
-                    println!(r#"<circle cx="{}" cy="{}" r="2" fill="blue" />"#, x, y);
-                }
-                let (w, h) = size_himetric_to_mm(sch_grid.extent);
-                println!(
-                    r#"<rect x="{}" y="{}" width="{}" height="{}" stroke="{}" stroke-width="1" fill="none" />"#,
-                    x, y, w, h, "red"
-                );
-                let cols_layout = &sch_grid.frame.layouts[1];
-                let keys_layout = &sch_grid.frame.layouts[2];
-                if debug {
-                    let w2 = u_himetric_to_mm(cols_layout.widths[0]);
-                    let w3 = u_himetric_to_mm(cols_layout.widths[1]);
-
-                    let scale = 1.95;
-                    let y2 = y + (3.0 * scale);
-                    let x2 = x + w2 * scale;
-                    let x3 = x2 + w3 * scale;
-                    let h2 = 2.84 * scale * cols_layout.row_max as f32;
-                    println!(
-                        r#"<rect x="{}" y="{}" width="{}" height="{}" stroke="{}" stroke-width="0.5" fill="none" />"#,
-                        x2,
-                        y2,
-                        w3 * scale,
-                        h2,
-                        "purple"
-                    );
-
-                    let h3 = 2.84 * scale * cols_layout.row_min as f32;
-                    let y3 = y2 + h3;
-                    println!(
-                        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="0.5" fill="none" />"#,
-                        x2, y3, x3, y3, "pink"
-                    );
-                }
 
-                println!(
-                    r#"<text x="{}" y="{}" font-size="4" font-family="Tahoma">{} ({}/{}; {}/{})</text>"#,
-                    x + 2.0,
-                    y + 6.0,
-                    sch_grid.frame.caption,
-                    cols_layout.row_max,
-                    cols_layout.row_min,
-                    keys_layout.row_max,
-                    keys_layout.row_min,
-                );
-            }
-            Control::Label(label) => {
-                if debug {
-                    println!(r#"<circle cx="{}" cy="{}" r="2" fill="red" />"#, x, y);
-                }
-                let (width, height) = size_himetric_to_mm(label.size);
-                let bg_rgb = color(label.back_color);
-                let fg_rgb = color(label.fore_color);
-                println!(
-                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" />"#,
-                    x, y, width, height, bg_rgb,
-                );
-                println!(
-                    r#"<text font-family="{}" color="{}" font-size="{}" id="c{}" x="{}" y="{}">{}</text>"#,
-                    label.font.font_face,
-                    fg_rgb,
-                    8.25 * 0.35,
-                    site.id,
-                    x,
-                    y + height * 0.8,
-                    label.text
-                );
-            }
-            Control::Polyline(line) => {
-                if debug {
-                    println!(r#"<circle cx="{}" cy="{}" r="2" fill="green" />"#, x, y);
-                    for label in &line.labels {
-                        let (lx, ly) = pos_himetric_to_mm(&label.pos);
-                        println!(r#"<circle cx="{}" cy="{}" r="4" fill="cyan" />"#, lx, ly);
-                    }
-                }
-                print!(
-                    r#"<polyline stroke-width="1" id="c{}" fill="none" stroke="{}" points=""#,
-                    site.id,
-                    color(line.color),
-                );
-                fn cap_color(cap: DdsPolylineEndType) -> &'static str {
-                    match cap {
-                        DdsPolylineEndType::Many => "yellow",
-                        DdsPolylineEndType::Key => "orange",
-                        _ => "black",
-                    }
-                }
-                for p in &line.positions {
-                    let (x, y) = pos_himetric_to_mm(p);
-                    print!("{},{} ", x, y);
-                }
-                println!("\" />");
-                let color_src = cap_color(line.end_type_src);
-                let color_dest = cap_color(line.end_type_dest);
-
-                let (x_src, y_src) = pos_himetric_to_mm(line.positions.first().unwrap());
-                let (x_dest, y_dest) = pos_himetric_to_mm(line.positions.last().unwrap());
-                print!(
-                    r#"<circle cx="{}" cy="{}" r="2" fill="{}" />"#,
-                    x_src, y_src, color_src
-                );
-                print!(
-                    r#"<circle cx="{}" cy="{}" r="2" fill="{}" />"#,
-                    x_dest, y_dest, color_dest
-                );
-            }
-            Control::Unknown(_) => {}
+    let written = reader.streams()?.to_bytes()?;
+    let mut copy = SysDiagramFile::open(Cursor::new(written)).map_err(Error::Cfb)?;
+    let mut valid = true;
+    for path in STREAMS {
+        let expected = reader.read_stream(path)?;
+        let actual = copy.read_stream(path)?;
+        if expected == actual {
+            println!("ok       {:?}", path);
+        } else {
+            println!(
+                "differs  {:?} ({} bytes, written {} bytes)",
+                path,
+                expected.len(),
+                actual.len()
+            );
+            valid = false;
         }
     }
-    println!("</svg>");
+    Ok(valid)
 }
 
-fn himetric_to_mm(len: i32) -> f32 {
-    len as f32 / 100.0
+fn streams(opts: &Options, cmd: &StreamsCommand) -> Result<(), anyhow::Error> {
+    let reader = open(opts, &cmd.file)?;
+    let root = reader.root_entry();
+    let ctime = root.created().duration_since(UNIX_EPOCH);
+    let mtime = root.modified().duration_since(UNIX_EPOCH);
+    println!("created: {:?}, modified: {:?}", ctime, mtime);
+    println!("root CLSID: {}", root.clsid());
+    for entry in reader.read_root_storage() {
+        println!("- {:?}: {}", entry.name(), entry.path().display());
+    }
+    Ok(())
 }
 
-fn u_himetric_to_mm(len: u32) -> f32 {
-    len as f32 / 100.0
+fn extract(opts: &Options, cmd: &ExtractCommand) -> Result<(), anyhow::Error> {
+    let mut reader = open(opts, &cmd.file)?;
+    std::fs::create_dir_all(&cmd.out)
+        .with_context(|| format!("Failed to create '{}'", cmd.out.display()))?;
+    let names: Vec<&str> = cmd.stream.iter().map(String::as_str).collect();
+    let mut written = reader.extract_streams(&cmd.out, &names)?;
+    if cmd.sites {
+        written.extend(reader.extract_site_objects(&cmd.out)?);
+    }
+    for path in written {
+        println!("{}", path.display());
+    }
+    Ok(())
 }
 
-fn pos_himetric_to_mm(p: &Position) -> (f32, f32) {
-    (himetric_to_mm(p.left), himetric_to_mm(p.top))
+fn hexdump(opts: &Options, cmd: &HexdumpCommand) -> Result<(), anyhow::Error> {
+    let mut reader = open(opts, &cmd.file)?;
+    let (bytes, fields) = reader.annotated_stream(&cmd.stream)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write_hexdump(&mut out, &bytes, &fields, cmd.color)?;
+    let unknown: usize = fields
+        .iter()
+        .filter(|f| !f.known)
+        .map(|f| f.range.len())
+        .sum();
+    let covered: usize = fields.iter().map(|f| f.range.len()).sum();
+    writeln!(
+        out,
+        "{} bytes, {} unknown, {} unparsed",
+        bytes.len(),
+        unknown,
+        bytes.len().saturating_sub(covered)
+    )?;
+    Ok(())
 }
 
-fn size_himetric_to_mm(size: Size) -> (f32, f32) {
-    (u_himetric_to_mm(size.width), u_himetric_to_mm(size.height))
+fn script(opts: &Options, cmd: &ScriptCommand) -> Result<(), anyhow::Error> {
+    let definition = read_input(opts, &cmd.file)?;
+    let name = {
+        let mut reader = SysDiagramFile::open(Cursor::new(&definition[..])).map_err(Error::Cfb)?;
+        match &cmd.name {
+            Some(name) => name.clone(),
+            None => {
+                let dsref = reader.dsref_schema_contents()?;
+                let stored = dsref
+                    .root_node
+                    .children
+                    .first()
+                    .and_then(|n| n.name.clone());
+                stored.context("The diagram has no name, use --name")?
+            }
+        }
+    };
+    let record = DiagramRecord {
+        name,
+        principal_id: cmd.principal_id,
+        diagram_id: 0,
+        version: Some(cmd.version),
+        definition,
+    };
+    let options = ScriptOptions {
+        statement: cmd.statement.0,
+        chunk_size: cmd.chunk_size,
+    };
+    let mut out: Box<dyn Write> = match &cmd.out {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("Failed to create '{}'", path.display()))?,
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    write_sql_script(&mut out, &record, &options)?;
+    out.flush()?;
+    Ok(())
 }
 
 pub fn main() -> Result<(), anyhow::Error> {
     let opts: Options = argh::from_env();
-    load_database(&opts).with_context(|| "Loading sysdiagram failed!")
+    match &opts.command {
+        Command::Info(cmd) => info(&opts, cmd),
+        Command::Dump(cmd) => dump(&opts, cmd),
+        Command::Tables(cmd) => tables(&opts, cmd),
+        Command::Relationships(cmd) => relationships(&opts, cmd),
+        Command::Export(cmd) => export(&opts, cmd),
+        Command::Validate(cmd) => {
+            if !validate(&opts, cmd)? {
+                std::process::exit(2);
+            }
+            Ok(())
+        }
+        Command::Streams(cmd) => streams(&opts, cmd),
+        Command::Extract(cmd) => extract(&opts, cmd),
+        Command::Hexdump(cmd) => hexdump(&opts, cmd),
+        Command::Script(cmd) => script(&opts, cmd),
+        Command::Csv(cmd) => extract_csv(cmd).with_context(|| "Extracting diagrams failed!"),
+    }
 }
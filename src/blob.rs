@@ -0,0 +1,67 @@
+//! Text encodings of the `definition` column
+//!
+//! Diagrams are often exported as text instead of the raw binary blob. `FOR JSON` and
+//! `FOR XML` queries produce base64, while `SELECT definition` in SSMS and CSV exports
+//! produce `0x...` hex literals.
+
+use std::borrow::Cow;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::Error;
+
+fn strip_whitespace(input: &[u8]) -> Vec<u8> {
+    input
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect()
+}
+
+/// Decode a base64 blob, ignoring whitespace (e.g. line breaks)
+pub fn decode_base64(input: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(STANDARD.decode(strip_whitespace(input))?)
+}
+
+fn hex_digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decode a hex blob with an optional `0x` prefix, ignoring whitespace
+pub fn decode_hex(input: &[u8]) -> Result<Vec<u8>, Error> {
+    let digits = strip_whitespace(input);
+    let digits = match digits.as_slice() {
+        [b'0', b'x' | b'X', rest @ ..] => rest,
+        rest => rest,
+    };
+    if digits.len() % 2 != 0 {
+        return Err(Error::HexOddLength(digits.len()));
+    }
+    digits
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(i, pair)| match (hex_digit(pair[0]), hex_digit(pair[1])) {
+            (Some(hi), Some(lo)) => Ok(hi << 4 | lo),
+            _ => Err(Error::HexDigit(i * 2)),
+        })
+        .collect()
+}
+
+/// Decode a text export that is UTF-8 or UTF-16 with a byte order mark
+pub(crate) fn decode_text(input: &[u8]) -> Result<Cow<'_, str>, Error> {
+    let encoding = Encoding::for_bom(input).map_or(UTF_8, |(encoding, _)| encoding);
+    let (text, _, had_errors) = encoding.decode(input);
+    if had_errors {
+        return Err(Error::StringEncoding(format!(
+            "invalid {}",
+            encoding.name()
+        )));
+    }
+    Ok(text)
+}
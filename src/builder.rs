@@ -0,0 +1,674 @@
+//! # Creating diagrams
+//!
+//! A [`SysDiagramBuilder`] creates the streams for a new diagram from a list of tables
+//! and the foreign keys between them. Everything that SSMS does not derive from
+//! the database again when the diagram is opened (form and site properties, grid
+//! layouts, label fonts, ...) is filled in with the values from diagrams that SSMS
+//! saved itself. Fields that are not understood yet are set like in those diagrams.
+//!
+//! The columns of a table are not part of the diagram, so the size of a table is a
+//! guess that SSMS corrects when the table is resized.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    time::SystemTime,
+};
+
+use bstr::BString;
+use ms_oforms::properties::{
+    color::{parse_ole_color, OleColor},
+    font::{parse_std_font, StdFont},
+    Position, Size,
+};
+use nom::IResult;
+use num_traits::FromPrimitive;
+use uuid::Uuid;
+
+use crate::{
+    dds::{
+        DdsPolylineEndType, DdsStream, DdsStreamCtrl, DdsStreamHeader, Label, LabelFlags,
+        LabelJustification, LabelRef, Polyline,
+    },
+    dsref::{
+        system_time_to_windows_ticks, DSRefSchemaContents, DsRefNode, DsRefType, CLSID_DSREF_R2,
+        DATA_PROVIDER_FOR_SQL_SERVER, GUID_DSREF_PROPERTY_PROVIDER,
+    },
+    form::{FormStream, OleSite, SitePropMask},
+    layout::{label_position, layout_tables, polyline_site_pos, route, LayoutOptions, Rect},
+    mdtdb::{
+        DataSource, GridFrameWnd, GridSpec, PropViewColumn, SchGrid, TableView, TableViewMode,
+    },
+    render::ROW_HEIGHT,
+    writer::{
+        length_prefix, write_guid, write_size, write_u16, write_u32, write_u8, write_wstring_nt,
+    },
+    Control, Error, SysDiagramStreams, Variant,
+};
+
+/// A table to put on the diagram
+#[derive(Debug, Clone)]
+pub struct TableSpec {
+    pub schema: String,
+    pub name: String,
+    /// Position of the top-left corner on the diagram surface
+    pub pos: Position,
+    pub view: TableView,
+}
+
+impl TableSpec {
+    /// The name shown in the title bar of the table and in relationship tooltips
+    ///
+    /// SSMS adds the schema in parentheses unless it is `dbo`.
+    pub fn caption(&self) -> String {
+        table_caption(&self.schema, &self.name)
+    }
+
+    /// The name as `schema.name`
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", self.schema, self.name)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.caption() == name || self.qualified_name() == name
+    }
+}
+
+/// See [`TableSpec::caption`]
+pub(crate) fn table_caption(schema: &str, name: &str) -> String {
+    if schema == "dbo" {
+        name.to_string()
+    } else {
+        format!("{} ({})", name, schema)
+    }
+}
+
+/// A foreign key relationship between two tables
+///
+/// `from` and `to` follow the order of the tooltip (see [`crate::Relationship`]),
+/// i.e. `from` is the referenced (primary key) table and `to` is the table that
+/// has the foreign key. Tables are found by name, caption or `schema.name`, and the
+/// name must not match more than one table.
+#[derive(Debug, Clone)]
+pub struct ForeignKeySpec {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Create the streams of a new sysdiagram
+///
+/// ```no_run
+/// # use ms_oforms::properties::Position;
+/// # use sysdiagram::{mdtdb::TableView, SysDiagramBuilder};
+/// let mut builder = SysDiagramBuilder::new("Data Source=.;Initial Catalog=Shop", "Orders");
+/// builder
+///     .table("dbo", "Customer", Position { left: 1000, top: 1000 }, TableView::Standard)
+///     .table("dbo", "Order", Position { left: 14000, top: 1000 }, TableView::Standard)
+///     .foreign_key("FK_Order_Customer", "Customer", "Order");
+/// let definition = builder.build()?.to_bytes()?;
+/// # Ok::<(), sysdiagram::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SysDiagramBuilder {
+    /// The name of the `DATABASE` DSRef node
+    pub connection_string: String,
+    /// The name of the `SCHEMADIAGRAM` DSRef node
+    pub name: String,
+    pub tables: Vec<TableSpec>,
+    pub foreign_keys: Vec<ForeignKeySpec>,
+    /// `FILETIME` of the DSRef, see [`DSRefSchemaContents::timestamp`]
+    pub timestamp: u64,
+}
+
+/// Form font and site class table (`SchGrid`, `Polyline`, `Label`) of the `f` stream
+const FORM_STREAM_DATA: [u8; 186] = [
+    0xDE, 0x80, 0x5B, 0x10, 0xF1, 0x95, 0xD0, 0x11, 0xB0, 0xA0, 0x00, 0xAA, //
+    0x00, 0xBD, 0xCB, 0x5C, 0x00, 0x00, 0x08, 0x00, 0x30, 0x00, 0x00, 0x00, //
+    0x00, 0x02, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x38, 0x00, 0x2B, 0x00, //
+    0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0xD9, 0xE6, 0xB0, 0xE9, 0x1C, 0x81, //
+    0xD0, 0x11, 0xAD, 0x51, 0x00, 0xA0, 0xC9, 0x0F, 0x57, 0x39, 0xF4, 0x3B, //
+    0x7F, 0x84, 0x7F, 0x61, 0xC7, 0x43, 0x85, 0x35, 0x29, 0x86, 0xE1, 0xD5, //
+    0x52, 0xF8, 0xA0, 0x32, 0x7D, 0xB2, 0xD8, 0x62, 0x95, 0x42, 0x8D, 0x98, //
+    0x27, 0x3C, 0x25, 0xA2, 0xDA, 0x2D, 0x00, 0x00, 0x2C, 0x00, 0x43, 0x20, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x53, 0x44, //
+    0x4D, 0xD2, 0x01, 0x1F, 0xD1, 0x11, 0x8E, 0x63, 0x00, 0x60, 0x97, 0xD2, //
+    0xDF, 0x48, 0x34, 0xC9, 0xD2, 0x77, 0x79, 0x77, 0xD8, 0x11, 0x90, 0x70, //
+    0x00, 0x06, 0x5B, 0x84, 0x0D, 0x9C, 0x00, 0x00, 0x2C, 0x00, 0x43, 0x20, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x51, 0x44, //
+    0x4D, 0xD2, 0x01, 0x1F, 0xD1, 0x11, 0x8E, 0x63, 0x00, 0x60, 0x97, 0xD2, //
+    0xDF, 0x48, 0x34, 0xC9, 0xD2, 0x77, 0x79, 0x77, 0xD8, 0x11, 0x90, 0x70, //
+    0x00, 0x06, 0x5B, 0x84, 0x0D, 0x9C, //
+];
+
+/// Indices into the site class table in [`FORM_STREAM_DATA`]
+const CLASS_SCHGRID: u16 = 0x8000;
+const CLASS_POLYLINE: u16 = 0x8001;
+const CLASS_LABEL: u16 = 0x8002;
+
+/// `BackColor`, `NextAvailableID`, `ScrollBars`, `DisplayedSize`, `LogicalSize`,
+/// `Font`, `ShapeCookie` and `DrawBuffer`
+const FORM_PROP_MASK: u32 = 0x0C10_0E0A;
+const DISPLAYED_SIZE: Size = Size {
+    width: 32570,
+    height: 22569,
+};
+
+/// The content of the `Schema UDV Default` stream in every known diagram
+const SCHEMA_UDV_DEFAULT: [u8; 22] = [
+    0x01, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x0B, 0x00,
+    0x00, 0x00, 0x4E, 0x61, 0xBC, 0x00,
+];
+/// The `Schema UDV Default Post V6` stream is the same without the last field
+const SCHEMA_UDV_DEFAULT_POST_V6: [u8; 18] = [
+    0x01, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x0B, 0x00,
+    0x00, 0x00,
+];
+
+/// Height of the title bar of a table
+const HEADER_HEIGHT: u32 = 794;
+/// Number of columns assumed for [`TableView::ColumnNames`]
+const DEFAULT_ROWS: u32 = 10;
+
+/// Width of the icon and name columns of a table
+const NAME_WIDTHS: [u32; 2] = [284, 1980];
+
+/// Approximate width of one character of a label in Tahoma 8.25pt
+const LABEL_CHAR_WIDTH: u32 = 155;
+const LABEL_PADDING: u32 = 300;
+const LABEL_HEIGHT: u32 = 344;
+
+fn grid_spec(hidden: u32, v1: u32, size: (u32, u32), rows: (u32, u32), widths: &[u32]) -> GridSpec {
+    GridSpec {
+        hidden,
+        v1,
+        size: Size {
+            width: size.0,
+            height: size.1,
+        },
+        v2: 0,
+        row_max: rows.0,
+        row_min: rows.1,
+        col_max: widths.len() as u32,
+        col_min: widths.len() as u32,
+        widths: widths.to_vec(),
+    }
+}
+
+/// The grid layouts that SSMS uses for a new table
+fn default_layouts() -> [GridSpec; 5] {
+    let column_names = HEADER_HEIGHT + DEFAULT_ROWS * ROW_HEIGHT;
+    let keys = HEADER_HEIGHT + 2 * ROW_HEIGHT;
+    let standard = [
+        284, 1980, 1620, 720, 900, 630, 1080, 1350, 810, 1350, 1710, 1170,
+    ];
+    [
+        GridSpec {
+            // only the first seven columns are visible by default
+            col_min: 7,
+            ..grid_spec(0, 0, (9155, 8202), (301, 13), &standard)
+        },
+        grid_spec(
+            0,
+            1,
+            (4339, column_names),
+            (DEFAULT_ROWS, DEFAULT_ROWS),
+            &NAME_WIDTHS,
+        ),
+        grid_spec(0, 1, (4339, keys), (2, 2), &NAME_WIDTHS),
+        grid_spec(1, 0, (4339, HEADER_HEIGHT), (0, 0), &NAME_WIDTHS),
+        grid_spec(0, 0, (11060, 8202), (0, 13), &[284, 1980, 2340, 1440]),
+    ]
+}
+
+/// The `TableViewMode:N` property of a new table
+fn table_view_mode(view: TableView, spec: &GridSpec) -> TableViewMode {
+    let columns = match view {
+        TableView::Standard => view
+            .columns()
+            .iter()
+            .map(|&c| (c, spec.widths[c as usize + 1]))
+            .collect(),
+        TableView::Custom => view
+            .columns()
+            .iter()
+            .copied()
+            .zip(spec.widths[1..].iter().copied())
+            .collect(),
+        _ => vec![(PropViewColumn::ColumnName, spec.widths[1])],
+    };
+    TableViewMode {
+        icon_width: spec.widths[0],
+        columns,
+    }
+}
+
+/// Inverse of `parse_ole_color`, for a `OLE_COLOR` value
+fn ole_color(value: u32) -> Result<OleColor, Error> {
+    let bytes = value.to_le_bytes();
+    let result: IResult<&[u8], OleColor> = parse_ole_color(&bytes);
+    Ok(result?.1)
+}
+
+/// The font of relationship labels, Tahoma 8.25pt
+fn label_font() -> Result<StdFont, Error> {
+    let mut bytes = vec![0x01];
+    bytes.extend_from_slice(&0u16.to_le_bytes()); // charset
+    bytes.push(0); // flags
+    bytes.extend_from_slice(&400u16.to_le_bytes()); // weight
+    bytes.extend_from_slice(&82500u32.to_le_bytes()); // height
+    bytes.push(6);
+    bytes.extend_from_slice(b"Tahoma");
+    let result: IResult<&[u8], StdFont> = parse_std_font(&bytes);
+    Ok(result?.1)
+}
+
+/// The `\1CompObj` stream, see [\[MS-OLEDS\] 2.3.8](https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-oleds/359f8d69-de6a-4b22-a5f5-5b3e3f1b2a85)
+fn write_comp_obj<W: Write>(w: &mut W) -> io::Result<()> {
+    write_u16(w, 0x0001)?; // version
+    write_u16(w, 0xFFFE)?; // byte order
+    write_u32(w, 0x0000_0A03)?;
+    write_u32(w, 0xFFFF_FFFF)?;
+    write_guid(w, &Uuid::nil())?;
+    for text in [&b"Microsoft DDS Form 2.0\0"[..], &b"Embedded Object\0"[..]] {
+        write_u32(w, length_prefix(text.len())?)?;
+        w.write_all(text)?;
+    }
+    write_u32(w, 0)?; // Reserved1
+    write_u32(w, 0x71B2_39F4)?; // UnicodeMarker
+    write_u32(w, 0)?; // UnicodeUserType
+    write_u32(w, 0)?; // UnicodeClipboardFormat
+    write_u32(w, 0) // Reserved2
+}
+
+/// The `FormControl` properties and site class table of the `f` stream
+fn write_form_header<W: Write>(w: &mut W, next_id: i32, logical_size: &Size) -> io::Result<()> {
+    let mut data = Vec::new();
+    write_u32(&mut data, FORM_PROP_MASK)?;
+    write_u32(&mut data, 0x8000_0005)?; // BackColor: window background
+    write_u32(&mut data, next_id as u32)?; // NextAvailableID
+    write_u8(&mut data, 0x0F)?; // ScrollBars
+    write_u8(&mut data, 0x00)?;
+    write_u16(&mut data, 0xFFFF)?; // Font
+    write_u32(&mut data, next_id as u32)?; // ShapeCookie
+    write_u32(&mut data, 32000)?; // DrawBuffer
+    write_size(&mut data, &DISPLAYED_SIZE)?;
+    write_size(&mut data, logical_size)?;
+
+    write_u8(w, 0)?; // minor version
+    write_u8(w, 4)?; // major version
+    write_u16(w, length_prefix(data.len())?)?;
+    w.write_all(&data)?;
+    w.write_all(&FORM_STREAM_DATA)
+}
+
+fn site(id: i32, class: u16, name: &str, pos: Position, tooltip: Option<String>) -> OleSite {
+    let mut mask = SitePropMask::NAME
+        | SitePropMask::ID
+        | SitePropMask::OBJECT_STREAM_SIZE
+        | SitePropMask::CLSID_CACHE_INDEX
+        | SitePropMask::POSITION;
+    let bit_flags = match &tooltip {
+        Some(_) => {
+            mask |= SitePropMask::CONTROL_TIP_TEXT;
+            0
+        }
+        None => {
+            mask |= SitePropMask::BIT_FLAGS;
+            0x31
+        }
+    };
+    OleSite {
+        depth: 0,
+        mask,
+        name: name.to_string(),
+        id,
+        bit_flags,
+        object_stream_size: 0,
+        clsid_cache_index: class,
+        pos,
+        tooltip: tooltip.unwrap_or_default(),
+        _name_pad: BString::default(),
+        _tooltip_pad: BString::default(),
+    }
+}
+
+fn dds_ctrl(
+    id: i32,
+    parent_id: i32,
+    a1: Vec<u8>,
+    a3: u32,
+    properties: BTreeMap<String, Variant>,
+) -> DdsStreamCtrl {
+    DdsStreamCtrl {
+        id1: id,
+        id2: id,
+        parent_id,
+        _a1: BString::from(a1),
+        _a2: BString::from(vec![0; 8]),
+        _a3: a3,
+        _a4: u8::from(parent_id > 0),
+        properties,
+    }
+}
+
+fn sch_grid(table: &TableSpec) -> SchGrid {
+    let layouts = default_layouts();
+    let caption = table.caption();
+    // SSMS keeps the caption in a buffer of 138 UTF-16 code units
+    let used = 2 * (caption.encode_utf16().count() + 1);
+    SchGrid {
+        extent: layouts[table.view as usize].size,
+        frame: GridFrameWnd {
+            caption,
+            _caption_pad: BString::from(vec![0; 276usize.saturating_sub(used)]),
+            layouts: Box::new(layouts),
+        },
+        data_source: DataSource {
+            _cd3: 1,
+            _cd4: 1,
+            column_selection: (0..=10).collect(),
+            table: table.name.clone(),
+            schema: table.schema.clone(),
+        },
+    }
+}
+
+/// The site, control and DDS control of a new table with the given site `id`
+pub(crate) fn new_table(id: i32, table: &TableSpec) -> (OleSite, SchGrid, DdsStreamCtrl) {
+    let sch_grid = sch_grid(table);
+    let mut properties = BTreeMap::new();
+    let active = (table.view as u32).to_string();
+    properties.insert(String::from("ActiveTableViewMode"), Variant::BStr(active));
+    for (n, spec) in sch_grid.frame.layouts.iter().enumerate() {
+        if let Some(view) = TableView::from_usize(n) {
+            let mode = table_view_mode(view, spec).to_string();
+            properties.insert(format!("TableViewMode:{}", n), Variant::BStr(mode));
+        }
+    }
+    let dds_ctrl = dds_ctrl(id, 0, Vec::new(), 0x2D0, properties);
+    let tooltip = Some(table.caption());
+    let site = site(id, CLASS_SCHGRID, "SchGrid", table.pos, tooltip);
+    (site, sch_grid, dds_ctrl)
+}
+
+/// The DSRef `TABLE` node of a table, without [`DsRefType::HASNEXTSIBLING`]
+pub(crate) fn dsref_table_node(table: &TableSpec) -> DsRefNode {
+    DsRefNode {
+        flags: DsRefType::TABLE | DsRefType::HASNAME | DsRefType::HASOWNER,
+        extended_type: None,
+        name: Some(table.name.clone()),
+        owner: Some(table.schema.clone()),
+        children: Vec::new(),
+        properties: None,
+    }
+}
+
+impl SysDiagramBuilder {
+    /// Start an empty diagram, stamped with the current time
+    pub fn new(connection_string: &str, name: &str) -> Self {
+        Self {
+            connection_string: connection_string.to_string(),
+            name: name.to_string(),
+            tables: Vec::new(),
+            foreign_keys: Vec::new(),
+            timestamp: system_time_to_windows_ticks(SystemTime::now()),
+        }
+    }
+
+    /// Add a table
+    pub fn table(&mut self, schema: &str, name: &str, pos: Position, view: TableView) -> &mut Self {
+        self.tables.push(TableSpec {
+            schema: schema.to_string(),
+            name: name.to_string(),
+            pos,
+            view,
+        });
+        self
+    }
+
+    /// Add a relationship from the referenced table to the table with the foreign key
+    pub fn foreign_key(&mut self, name: &str, from: &str, to: &str) -> &mut Self {
+        self.foreign_keys.push(ForeignKeySpec {
+            name: name.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        self
+    }
+
+    /// Set the time of the DSRef
+    pub fn timestamp(&mut self, time: SystemTime) -> &mut Self {
+        self.timestamp = system_time_to_windows_ticks(time);
+        self
+    }
+
+    fn table_index(&self, name: &str) -> Result<usize, Error> {
+        let mut matches = self
+            .tables
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.matches(name));
+        match (matches.next(), matches.next()) {
+            (Some((index, _)), None) => Ok(index),
+            (Some(_), Some(_)) => Err(Error::AmbiguousTable(name.to_string())),
+            (None, _) => Err(Error::UnknownTable(name.to_string())),
+        }
+    }
+
+    fn dsref_schema_contents(&self) -> DSRefSchemaContents {
+        let count = self.tables.len();
+        let tables = self
+            .tables
+            .iter()
+            .enumerate()
+            .map(|(i, table)| {
+                let mut node = dsref_table_node(table);
+                if i + 1 < count {
+                    node.flags |= DsRefType::HASNEXTSIBLING;
+                }
+                node
+            })
+            .collect::<Vec<_>>();
+
+        let mut flags = DsRefType::SCHEMADIAGRAM | DsRefType::HASNAME;
+        if !tables.is_empty() {
+            flags |= DsRefType::HASFIRSTCHILD;
+        }
+        let diagram = DsRefNode {
+            flags,
+            extended_type: None,
+            name: Some(self.name.clone()),
+            owner: None,
+            children: tables,
+            properties: None,
+        };
+
+        let provider = DATA_PROVIDER_FOR_SQL_SERVER.braced().to_string();
+        let mut properties = BTreeMap::new();
+        properties.insert(
+            GUID_DSREF_PROPERTY_PROVIDER,
+            Variant::BStr(provider.to_uppercase()),
+        );
+        DSRefSchemaContents {
+            clsid: CLSID_DSREF_R2,
+            len: 0,
+            a: 2,
+            timestamp: self.timestamp,
+            b: 0x202,
+            root_node: DsRefNode {
+                flags: DsRefType::DATABASE
+                    | DsRefType::DATASOURCEROOT
+                    | DsRefType::EXTENDED
+                    | DsRefType::HASFIRSTCHILD
+                    | DsRefType::HASNAME
+                    | DsRefType::HASPROP,
+                extended_type: Some(Uuid::nil()),
+                name: Some(self.connection_string.clone()),
+                owner: None,
+                children: vec![diagram],
+                properties: Some(properties),
+            },
+        }
+    }
+
+    /// Place all tables with [`layout_tables`], replacing their positions
+    pub fn layout(&mut self, options: &LayoutOptions) -> Result<(), Error> {
+        let layouts = default_layouts();
+        let sizes: Vec<Size> = self
+            .tables
+            .iter()
+            .map(|t| layouts[t.view as usize].size)
+            .collect();
+        let mut edges = Vec::with_capacity(self.foreign_keys.len());
+        for fk in &self.foreign_keys {
+            edges.push((self.table_index(&fk.from)?, self.table_index(&fk.to)?));
+        }
+        let positions = layout_tables(&sizes, &edges, options);
+        for (table, pos) in self.tables.iter_mut().zip(positions) {
+            table.pos = pos;
+        }
+        Ok(())
+    }
+
+    /// Create all streams of the diagram
+    ///
+    /// Tables get the IDs `1..=n` in order, followed by a polyline and a label for
+    /// each foreign key. Lines are routed around the tables with [`route`].
+    pub fn build(&self) -> Result<SysDiagramStreams, Error> {
+        for (i, table) in self.tables.iter().enumerate() {
+            let qualified = table.qualified_name();
+            if self.tables[..i]
+                .iter()
+                .any(|t| t.qualified_name() == qualified)
+            {
+                return Err(Error::DuplicateTable(qualified));
+            }
+        }
+
+        let mut sites = Vec::new();
+        let mut controls = Vec::new();
+        let mut dds_controls = Vec::new();
+        let mut numbers = Vec::new();
+        let mut rects = Vec::new();
+
+        for (i, table) in self.tables.iter().enumerate() {
+            let (site, sch_grid, dds_ctrl) = new_table(i as i32 + 1, table);
+            rects.push(Rect::new(&table.pos, &sch_grid.extent));
+            sites.push(site);
+            controls.push(Control::SchGrid(sch_grid));
+            dds_controls.push(dds_ctrl);
+        }
+
+        let mut next_id = self.tables.len() as i32;
+        for fk in &self.foreign_keys {
+            let from = self.table_index(&fk.from)?;
+            let to = self.table_index(&fk.to)?;
+            let (polyline_id, label_id) = (next_id + 1, next_id + 2);
+            next_id += 2;
+
+            let positions = route(&rects[from], &rects[to], &rects);
+            let chars = fk.name.chars().count() as u32;
+            let size = Size {
+                width: LABEL_CHAR_WIDTH * chars + LABEL_PADDING,
+                height: LABEL_HEIGHT,
+            };
+            let label_pos = label_position(&positions, &size);
+            let site_pos = polyline_site_pos(&positions);
+            let tooltip = format!(
+                "Relationship '{}' between '{}' and '{}'\0",
+                fk.name,
+                self.tables[from].caption(),
+                self.tables[to].caption()
+            );
+            sites.push(site(
+                polyline_id,
+                CLASS_POLYLINE,
+                "Control",
+                site_pos,
+                Some(tooltip),
+            ));
+            controls.push(Control::Polyline(Polyline {
+                _d1: 11,
+                positions,
+                end_type_src: DdsPolylineEndType::Many,
+                end_type_dest: DdsPolylineEndType::Key,
+                color: ole_color(0x00C8_D0D4)?,
+                _x1: BString::from(vec![0; 16]),
+                labels: vec![LabelRef {
+                    id: label_id as u32,
+                    _x2: 0,
+                    pos: label_pos,
+                    size,
+                }],
+                _d7: 0x32,
+                _rest: BString::from(vec![0, 0, 0, 1, 0]),
+            }));
+            let mut a1 = vec![1, 0, 0, 0, 1, 0, 0, 0];
+            write_wstring_nt(&mut a1, &self.tables[to].schema)?;
+            write_wstring_nt(&mut a1, &fk.name)?;
+            dds_controls.push(dds_ctrl(polyline_id, 0, a1, 0x2C4, BTreeMap::new()));
+
+            sites.push(site(label_id, CLASS_LABEL, "Control", label_pos, None));
+            controls.push(Control::Label(Label {
+                _d1: 512,
+                size,
+                _d2: BString::from(vec![2, 0, 0, 0, 0, 0]),
+                back_color: ole_color(0x8000_0005)?,
+                fore_color: ole_color(0x8000_0008)?,
+                justification: LabelJustification::Center,
+                _d3: 0,
+                flags: LabelFlags::READ_ONLY | LabelFlags::AUTO_SIZE | LabelFlags::WORD_WRAP,
+                font: label_font()?,
+                text: fk.name.clone(),
+            }));
+            let a1 = vec![1, 0, 0, 0, 0, 0, 0, 0];
+            dds_controls.push(dds_ctrl(label_id, polyline_id, a1, 0xFAD, BTreeMap::new()));
+
+            // The meaning of the last two numbers is unknown
+            numbers.extend([polyline_id as u32, from as u32 + 1, to as u32 + 1, 0, 0]);
+        }
+
+        let right = rects.iter().map(|r| r.right).max().unwrap_or_default();
+        let bottom = rects.iter().map(|r| r.bottom).max().unwrap_or_default();
+        let logical_size = Size {
+            width: DISPLAYED_SIZE.width.max(right.max(0) as u32),
+            height: DISPLAYED_SIZE.height.max(bottom.max(0) as u32),
+        };
+        let mut header = Vec::new();
+        write_form_header(&mut header, next_id, &logical_size)?;
+
+        let mut dds_properties = BTreeMap::new();
+        dds_properties.insert(String::from("sch_labels_visible"), Variant::Bool(false));
+
+        let mut comp_obj = Vec::new();
+        write_comp_obj(&mut comp_obj)?;
+
+        Ok(SysDiagramStreams {
+            comp_obj: BString::from(comp_obj),
+            form: FormStream {
+                header: BString::from(header),
+                sites,
+                trailer: BString::default(),
+            },
+            controls,
+            dds_stream: DdsStream {
+                header: DdsStreamHeader {
+                    _a1: (0, 0),
+                    properties: dds_properties,
+                    flags: 0x1E,
+                    _a7: (0, 0),
+                    _a8: (0, 0),
+                    _a11: 100,
+                    _a12: BString::from(vec![0; 22]),
+                },
+                controls: dds_controls,
+                numbers,
+            },
+            dsref_schema_contents: self.dsref_schema_contents(),
+            schema_udv_default: BString::from(&SCHEMA_UDV_DEFAULT[..]),
+            schema_udv_default_post_v6: BString::from(&SCHEMA_UDV_DEFAULT_POST_V6[..]),
+            container: None,
+        })
+    }
+}
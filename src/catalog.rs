@@ -0,0 +1,116 @@
+//! Column metadata for tables
+//!
+//! A sysdiagram only references tables by schema and name (see [`crate::mdtdb::DataSource`]),
+//! the column definitions live in the database itself. A [`ColumnCatalog`] supplies them,
+//! e.g. from a query like
+//!
+//! ```sql
+//! SELECT c.TABLE_SCHEMA, c.TABLE_NAME, c.COLUMN_NAME,
+//!        c.DATA_TYPE + CASE
+//!            WHEN c.DATA_TYPE IN ('char', 'varchar', 'nchar', 'nvarchar', 'binary', 'varbinary')
+//!                THEN '(' + CASE c.CHARACTER_MAXIMUM_LENGTH WHEN -1 THEN 'max'
+//!                    ELSE CAST(c.CHARACTER_MAXIMUM_LENGTH AS varchar) END + ')'
+//!            WHEN c.DATA_TYPE IN ('decimal', 'numeric')
+//!                THEN '(' + CAST(c.NUMERIC_PRECISION AS varchar) + ', '
+//!                    + CAST(c.NUMERIC_SCALE AS varchar) + ')'
+//!            ELSE '' END AS DATA_TYPE,
+//!        c.IS_NULLABLE,
+//!        CASE WHEN k.COLUMN_NAME IS NULL THEN 0 ELSE 1 END AS IS_PRIMARY_KEY
+//! FROM INFORMATION_SCHEMA.COLUMNS c
+//! LEFT JOIN INFORMATION_SCHEMA.KEY_COLUMN_USAGE k
+//!   ON OBJECTPROPERTY(OBJECT_ID(k.CONSTRAINT_SCHEMA + '.' + k.CONSTRAINT_NAME), 'IsPrimaryKey') = 1
+//!  AND k.TABLE_SCHEMA = c.TABLE_SCHEMA AND k.TABLE_NAME = c.TABLE_NAME AND k.COLUMN_NAME = c.COLUMN_NAME
+//! ORDER BY c.TABLE_SCHEMA, c.TABLE_NAME, c.ORDINAL_POSITION
+//! ```
+//!
+//! exported with `|` as the field separator.
+
+use std::collections::BTreeMap;
+
+use crate::{blob::decode_text, Error, Table};
+
+/// A column of a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    /// The (condensed) data type, e.g. `nvarchar(50)`
+    pub data_type: String,
+    pub nullable: bool,
+    /// Whether the column is part of the primary key
+    pub primary_key: bool,
+}
+
+/// Columns by schema and table name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnCatalog {
+    tables: BTreeMap<(String, String), Vec<Column>>,
+}
+
+impl ColumnCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a column to a table
+    pub fn push(&mut self, schema: &str, table: &str, column: Column) {
+        self.tables
+            .entry((schema.to_string(), table.to_string()))
+            .or_default()
+            .push(column);
+    }
+
+    /// Get the columns of a table
+    pub fn get(&self, schema: &str, table: &str) -> Option<&[Column]> {
+        self.tables
+            .get(&(schema.to_string(), table.to_string()))
+            .map(Vec::as_slice)
+    }
+
+    /// Get the columns of the data source of a diagram table
+    pub fn for_table(&self, table: &Table) -> Option<&[Column]> {
+        let data_source = &table.sch_grid.data_source;
+        self.get(&data_source.schema, &data_source.table)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+}
+
+fn parse_flag(field: &str) -> Result<bool, Error> {
+    match field.trim().to_ascii_lowercase().as_str() {
+        "1" | "yes" | "true" => Ok(true),
+        "0" | "no" | "false" | "" => Ok(false),
+        _ => Err(Error::InvalidFlag(field.to_string())),
+    }
+}
+
+/// Parse a pipe-separated column list
+///
+/// Each line has the fields `schema|table|column|data_type|nullable|primary_key`,
+/// where the flags are `1`/`0`, `YES`/`NO` or `true`/`false`. Empty lines, a header
+/// row (`TABLE_SCHEMA|TABLE_NAME|...`) and the dashed line that `sqlcmd` puts below it
+/// are skipped.
+pub fn parse_column_catalog(input: &[u8]) -> Result<ColumnCatalog, Error> {
+    let text = decode_text(input)?;
+    let mut catalog = ColumnCatalog::new();
+    for (index, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split('|').collect();
+        let is_rule = |f: &str| f.trim().chars().all(|c| c == '-');
+        if fields.iter().all(|f| is_rule(f)) || fields[0].trim() == "TABLE_SCHEMA" {
+            continue;
+        }
+        let at_line = |e: Error| Error::Record(index + 1, Box::new(e));
+        let [schema, table, name, data_type, nullable, primary_key] = fields[..] else {
+            return Err(at_line(Error::FieldCount(6, fields.len())));
+        };
+        let column = Column {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable: parse_flag(nullable).map_err(at_line)?,
+            primary_key: parse_flag(primary_key).map_err(at_line)?,
+        };
+        catalog.push(schema, table, column);
+    }
+    Ok(catalog)
+}
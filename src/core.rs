@@ -1,50 +1,211 @@
 #![allow(clippy::upper_case_acronyms)]
 //! # Data definitions for sysdiagrams
-use ms_oforms::properties::Position;
+use bstr::BString;
+use ms_oforms::properties::{color::OleColor, Position};
+use num_traits::FromPrimitive;
 use uuid::Uuid;
 
 use crate::{
-    dds::{Label, Polyline},
-    mdtdb::SchGrid,
-    DSRefSchemaContents,
+    dds::{DdsStream, Label, Polyline},
+    mdtdb::{SchGrid, TableView, TableViewMode},
+    parse_relationship, DSRefSchemaContents, Variant,
 };
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SiteInfo {
     pub id: i32,
     pub depth: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::PositionDef"))]
     pub pos: Position,
     pub tooltip: String,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Control {
     SchGrid(SchGrid),
     Label(Label),
     Polyline(Polyline),
-    Unknown(Uuid),
+    /// A control of another class, kept as persisted in the `o` stream
+    Unknown {
+        clsid: Uuid,
+        #[cfg_attr(feature = "serde", serde(with = "crate::remote::hex"))]
+        data: BString,
+    },
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Table {
     pub id: i32,
+    /// Position of the top-left corner on the diagram surface
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::PositionDef"))]
+    pub pos: Position,
     pub sch_grid: SchGrid,
     pub caption: String,
+    /// The `ActiveTableViewMode` from the `\3DdsStream`
+    pub view: Option<TableView>,
+    /// The columns of the active view, from the `\3DdsStream`
+    pub view_mode: Option<TableViewMode>,
+}
+
+impl Table {
+    /// The `schema.table` name from the [`DataSource`][crate::mdtdb::DataSource]
+    pub fn qualified_name(&self) -> String {
+        let data_source = &self.sch_grid.data_source;
+        format!("{}.{}", data_source.schema, data_source.table)
+    }
+}
+
+/// A text label, usually attached to a [`Relationship`]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Annotation {
+    pub id: i32,
+    /// Position of the top-left corner on the diagram surface
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::PositionDef"))]
+    pub pos: Position,
+    pub label: Label,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Relationship {
     pub id: i32,
     pub control: Polyline,
     pub caption: String,
+    /// Caption of the referenced table
+    ///
+    /// This, [`Relationship::to`] and [`Relationship::name`] are empty if the
+    /// caption is not of the form `Relationship 'name' between 'from' and 'to'`,
+    /// e.g. in a localized or edited diagram.
     pub from: String,
+    /// Caption of the table with the foreign key
     pub to: String,
     pub name: String,
+    /// The labels referenced from [`Polyline::labels`]
+    pub labels: Vec<Annotation>,
+}
+
+pub(crate) fn active_table_view(dds_stream: &DdsStream, id: i32) -> Option<TableView> {
+    let ctrl = dds_stream.controls.iter().find(|c| c.id1 == id)?;
+    match ctrl.properties.get("ActiveTableViewMode")? {
+        Variant::BStr(mode) => mode.parse().ok().and_then(TableView::from_u32),
+        _ => None,
+    }
+}
+
+/// The `TableViewMode:N` property of a table for the given `view`
+pub(crate) fn table_view_mode(
+    dds_stream: &DdsStream,
+    id: i32,
+    view: TableView,
+) -> Option<TableViewMode> {
+    let ctrl = dds_stream.controls.iter().find(|c| c.id1 == id)?;
+    let key = format!("TableViewMode:{}", view as u32);
+    match ctrl.properties.get(&key)? {
+        Variant::BStr(mode) => TableViewMode::parse(mode),
+        _ => None,
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SysDiagram {
     pub tables: Vec<Table>,
     pub relationships: Vec<Relationship>,
+    /// Labels that are not referenced by any relationship
+    pub labels: Vec<Annotation>,
     pub dsref_schema_contents: DSRefSchemaContents,
+    /// The `BackColor` of the form, i.e. the background of the diagram
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::ole_color"))]
+    pub back_color: OleColor,
+}
+
+impl SysDiagram {
+    /// Link the controls of a schema form into tables and relationships
+    pub fn from_controls(
+        controls: Vec<(SiteInfo, Control)>,
+        dds_stream: &DdsStream,
+        dsref_schema_contents: DSRefSchemaContents,
+        back_color: OleColor,
+    ) -> Self {
+        let mut tables = Vec::new();
+        let mut relationships = Vec::new();
+        let mut labels = Vec::new();
+
+        for (site, control) in controls {
+            match control {
+                Control::SchGrid(sch_grid) => {
+                    let view = active_table_view(dds_stream, site.id);
+                    tables.push(Table {
+                        view,
+                        view_mode: view.and_then(|v| table_view_mode(dds_stream, site.id, v)),
+                        id: site.id,
+                        pos: site.pos,
+                        sch_grid,
+                        caption: site.tooltip,
+                    })
+                }
+                Control::Polyline(control) => {
+                    let (name, from, to) = match parse_relationship(&site.tooltip) {
+                        Ok((_, names)) => names,
+                        Err(_) => Default::default(),
+                    };
+                    relationships.push(Relationship {
+                        id: site.id,
+                        control,
+                        caption: site.tooltip,
+                        from,
+                        to,
+                        name,
+                        labels: Vec::new(),
+                    })
+                }
+                Control::Label(label) => labels.push(Annotation {
+                    id: site.id,
+                    pos: site.pos,
+                    label,
+                }),
+                Control::Unknown { .. } => {}
+            }
+        }
+
+        for relationship in &mut relationships {
+            for label_ref in &relationship.control.labels {
+                let index = labels.iter().position(|l| l.id as u32 == label_ref.id);
+                if let Some(index) = index {
+                    relationship.labels.push(labels.remove(index));
+                }
+            }
+        }
+
+        Self {
+            tables,
+            relationships,
+            labels,
+            dsref_schema_contents,
+            back_color,
+        }
+    }
+
+    /// The name of the diagram, as stored in the `SCHEMADIAGRAM` DSRef node
+    pub fn name(&self) -> Option<&str> {
+        self.dsref_schema_contents
+            .root_node
+            .children
+            .first()
+            .and_then(|n| n.name.as_deref())
+    }
+
+    /// Find a table by its site ID
+    pub fn table(&self, id: i32) -> Option<&Table> {
+        self.tables.iter().find(|t| t.id == id)
+    }
+
+    /// Find a table by its name (i.e. caption)
+    pub fn table_by_name(&self, name: &str) -> Option<&Table> {
+        self.tables.iter().find(|t| t.caption == name)
+    }
 }
@@ -10,6 +10,8 @@
 //! - <http://sqlsoundings.blogspot.com/2011/10/ssis-word-wrapping-annotations-using.html>
 //! - <https://www.sqlservercentral.com/articles/hidden-ssis-features-word-wrapping-your-annotations-and-more>
 
+pub mod xml;
+
 use bitflags::bitflags;
 use bstr::BString;
 use ms_oforms::properties::{
@@ -28,12 +30,23 @@ use nom::{
 };
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use std::{borrow::Cow, collections::BTreeMap};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    convert::TryFrom,
+    io::{self, Write},
+};
 use uuid::{uuid, Uuid};
 
 use crate::{
-    dtyp::{parse_variant, Variant},
+    dtyp::{parse_variant, write_variant, Variant},
+    hexdump::Layout,
     parse_u16_wstring, parse_u32_bytes_wstring_nt,
+    writer::{
+        write_i32, write_ole_color, write_position, write_size, write_std_font, write_u16,
+        write_u16_wstring, write_u32, write_u32_bytes_wstring_nt, write_u8,
+    },
+    Error,
 };
 
 /// Microsoft DT PolyLine Control 2 (ProgID `MSDTPolylineControl.2`)
@@ -59,6 +72,7 @@ pub const TYPELIB_DDS_FORM: Uuid = uuid!("105b80d0-95f1-11d0-b0a0-00aa00bdcb5c")
 pub const CLSID_DDS2_FORM_PACKAGE: Uuid = uuid!("105b80d5-95f1-11d0-b0a0-00aa00bdcb5c");
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DdsPolylineEndType {
     Many = 0,
     LittleNub = 1,
@@ -82,11 +96,32 @@ pub enum DdsPolylineEndType {
     Custom = 99,
 }
 
+impl DdsPolylineEndType {
+    /// The "many" side of a relationship, including the cascade variants
+    pub fn is_many(self) -> bool {
+        matches!(
+            self,
+            Self::Many | Self::ManyDelete | Self::ManyUpdate | Self::ManyUpdateDelete
+        )
+    }
+
+    /// The key side of a relationship, including the cascade variants
+    pub fn is_key(self) -> bool {
+        matches!(
+            self,
+            Self::Key | Self::KeyDelete | Self::KeyUpdate | Self::KeyUpdateDelete
+        )
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LabelRef {
     pub id: u32,
     pub(crate) _x2: u32, // 0
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::PositionDef"))]
     pub pos: Position,
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::SizeDef"))]
     pub size: Size,
 }
 
@@ -94,34 +129,46 @@ pub struct LabelRef {
 ///
 /// See also: <https://wutils.com/com-dll/constants/constants-MSDDS.htm>
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Polyline {
     pub(crate) _d1: u16, // 11 ? dpetDiamondArrow ?
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::positions"))]
     pub positions: Vec<Position>,
     pub end_type_src: DdsPolylineEndType,  // 0 (dpetMany ?)
     pub end_type_dest: DdsPolylineEndType, // 2 (dlotConnector ?, dbvUIActiveVisible ? dpcetsRect ? dpcetcsLineColor ? dpetKey ?)
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::ole_color"))]
     pub color: OleColor,
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::hex"))]
     pub(crate) _x1: BString, // (16) GUID NIL?, Color Black?
     pub labels: Vec<LabelRef>,
-    pub(crate) _d7: u8,        // 0b0011_1111 flags ??
+    pub(crate) _d7: u8, // 0b0011_1111 flags ??
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::hex"))]
     pub(crate) _rest: BString, // "\0\0\0\x01\0"
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Label {
     pub(crate) _d1: u32, // 0x02 = label pos type?
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::SizeDef"))]
     pub size: Size,
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::hex"))]
     pub(crate) _d2: BString, // 0x02 = label pos type?
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::ole_color"))]
     pub back_color: OleColor,
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::ole_color"))]
     pub fore_color: OleColor,
     pub justification: LabelJustification,
     pub(crate) _d3: u16,
     pub flags: LabelFlags,
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::std_font"))]
     pub font: StdFont,
     pub text: String,
 }
 
 bitflags! {
     #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct LabelFlags: u16 {
         const READ_ONLY = 0b000001;
         const ALIGN_TOP = 0b000010; // vertical center = off
@@ -136,6 +183,7 @@ bitflags! {
 ///
 /// See: <https://wutils.com/com-dll/constants/constants-DDSLibrary.htm>
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LabelJustification {
     Left = 0,
     Center = 1,
@@ -187,7 +235,10 @@ where
     ))
 }
 
-fn parse_label_ref(input: &[u8]) -> IResult<&[u8], LabelRef> {
+fn parse_label_ref<'a, E>(input: &'a [u8]) -> IResult<&'a [u8], LabelRef, E>
+where
+    E: ParseError<&'a [u8]>,
+{
     let (input, id) = le_u32(input)?;
     let (input, _x2) = le_u32(input)?;
     let (input, pos) = Position::parse(input)?;
@@ -204,7 +255,12 @@ fn parse_label_ref(input: &[u8]) -> IResult<&[u8], LabelRef> {
 // - <https://wutils.com/com-dll/constants/constants-DEDesignerExtensibility.htm>
 // - <https://wutils.com/com-dll/constants/constants-VBDataView.htm>
 // - <https://wutils.com/com-dll/constants/constants-VBDataViewSupport.htm>
-pub fn parse_polyline(input: &[u8]) -> IResult<&[u8], Polyline> {
+pub fn parse_polyline<'a, E>(input: &'a [u8]) -> IResult<&'a [u8], Polyline, E>
+where
+    E: ParseError<&'a [u8]>,
+    E: FromExternalError<&'a [u8], Cow<'static, str>>,
+    E: FromExternalError<&'a [u8], u32>,
+{
     let (input, pos_count) = le_u16(input)?;
     let (input, _d1) = le_u16(input)?;
     let (input, positions) = count(Position::parse, usize::from(pos_count))(input)?;
@@ -234,14 +290,16 @@ pub fn parse_polyline(input: &[u8]) -> IResult<&[u8], Polyline> {
     ))
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DdsStream {
     pub header: DdsStreamHeader,
     pub controls: Vec<DdsStreamCtrl>,
     pub numbers: Vec<u32>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DdsStreamHeader {
     pub(crate) _a1: (i32, i32),
     pub properties: BTreeMap<String, Variant>,
@@ -249,21 +307,28 @@ pub struct DdsStreamHeader {
     pub(crate) _a7: (i32, i32),
     pub(crate) _a8: (i32, i32),
     pub(crate) _a11: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::hex"))]
     pub(crate) _a12: BString,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DdsStreamCtrl {
     pub id1: i32, // logical?
     pub id2: i32, // physical?
     pub parent_id: i32,
-    pub len: u32,
+    /// Written with its length
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::hex"))]
     pub(crate) _a1: BString,
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::hex"))]
+    pub(crate) _a2: BString,
+    pub(crate) _a3: u32,
+    pub(crate) _a4: u8, // only present for labels
     pub properties: BTreeMap<String, Variant>,
 }
 
-pub fn parse_dds_stream(input: &[u8], ctrl_count: usize) -> IResult<&[u8], DdsStream> {
-    let (input, header) = parse_dds_stream_header(input).unwrap();
+pub fn parse_dds_stream(input: &[u8], ctrl_count: usize) -> IResult<&[u8], DdsStream, Error> {
+    let (input, header) = parse_dds_stream_header(input)?;
     let (input, controls) = count(parse_dds_stream_ctrl, ctrl_count)(input)?;
     let (input, numbers) = parse_dds_stream_trailer(input)?;
     Ok((
@@ -276,7 +341,7 @@ pub fn parse_dds_stream(input: &[u8], ctrl_count: usize) -> IResult<&[u8], DdsSt
     ))
 }
 
-pub fn parse_dds_stream_header(input: &[u8]) -> IResult<&[u8], DdsStreamHeader> {
+pub fn parse_dds_stream_header(input: &[u8]) -> IResult<&[u8], DdsStreamHeader, Error> {
     let (input, _) = tag([12, 0, 0, 0])(input)?;
     let (input, _a1) = pair(le_i32, le_i32)(input)?;
     let (input, properties) = parse_properties(input)?;
@@ -299,18 +364,18 @@ pub fn parse_dds_stream_header(input: &[u8]) -> IResult<&[u8], DdsStreamHeader>
     ))
 }
 
-pub fn parse_dds_stream_trailer(input: &[u8]) -> IResult<&[u8], Vec<u32>> {
+pub fn parse_dds_stream_trailer(input: &[u8]) -> IResult<&[u8], Vec<u32>, Error> {
     length_count(le_u32, le_u32)(input)
 }
 
-pub fn parse_dds_stream_ctrl(input: &[u8]) -> IResult<&[u8], DdsStreamCtrl> {
+pub fn parse_dds_stream_ctrl(input: &[u8]) -> IResult<&[u8], DdsStreamCtrl, Error> {
     let (input, (id1, id2, parent_id, len)) = tuple((le_i32, le_i32, le_i32, le_u32))(input)?;
     let (input, _a1) = map(take(len), BString::from)(input)?;
-    let (input, _a2) = take(8usize)(input)?;
+    let (input, _a2) = map(take(8usize), BString::from)(input)?;
     let (input, _a3) = le_u32(input)?;
 
     // This is a weird but necessary case for labels
-    let (input, _) = if parent_id > 0 {
+    let (input, _a4) = if parent_id > 0 {
         le_u8(input)?
     } else {
         (input, 0)
@@ -324,14 +389,16 @@ pub fn parse_dds_stream_ctrl(input: &[u8]) -> IResult<&[u8], DdsStreamCtrl> {
             id1,
             id2,
             parent_id,
-            len,
             _a1,
+            _a2,
+            _a3,
+            _a4,
             properties,
         },
     ))
 }
 
-fn parse_properties(input: &[u8]) -> IResult<&[u8], BTreeMap<String, Variant>> {
+fn parse_properties(input: &[u8]) -> IResult<&[u8], BTreeMap<String, Variant>, Error> {
     let mut properties = BTreeMap::new();
     let (input, prop_count) = le_u16(input)?;
     let mut _i = input;
@@ -346,3 +413,220 @@ fn parse_properties(input: &[u8]) -> IResult<&[u8], BTreeMap<String, Variant>> {
     let input = _i;
     Ok((input, properties))
 }
+
+fn write_label_ref<W: Write>(w: &mut W, label_ref: &LabelRef) -> io::Result<()> {
+    write_u32(w, label_ref.id)?;
+    write_u32(w, label_ref._x2)?;
+    write_position(w, &label_ref.pos)?;
+    write_size(w, &label_ref.size)
+}
+
+/// Inverse of [`parse_label`]
+pub fn write_label<W: Write>(w: &mut W, label: &Label) -> io::Result<()> {
+    write_u32(w, label._d1)?;
+    write_size(w, &label.size)?;
+    w.write_all(&label._d2)?;
+    write_ole_color(w, &label.back_color)?;
+    write_ole_color(w, &label.fore_color)?;
+    write_u16(w, label.justification as u16)?;
+    write_u16(w, label._d3)?;
+    write_u16(w, label.flags.bits())?;
+    write_std_font(w, &label.font)?;
+    write_u16_wstring(w, &label.text)
+}
+
+/// Inverse of [`parse_polyline`]
+pub fn write_polyline<W: Write>(w: &mut W, polyline: &Polyline) -> Result<(), Error> {
+    let position_count = u16::try_from(polyline.positions.len()).map_err(Error::BufTooLong)?;
+    let label_count = u32::try_from(polyline.labels.len()).map_err(Error::BufTooLong)?;
+    write_u16(w, position_count)?;
+    write_u16(w, polyline._d1)?;
+    for pos in &polyline.positions {
+        write_position(w, pos)?;
+    }
+    write_u32(w, polyline.end_type_src as u32)?;
+    write_u32(w, polyline.end_type_dest as u32)?;
+    write_ole_color(w, &polyline.color)?;
+    w.write_all(&polyline._x1)?;
+    write_u32(w, label_count)?;
+    for label_ref in &polyline.labels {
+        write_label_ref(w, label_ref)?;
+    }
+    write_u8(w, polyline._d7)?;
+    w.write_all(&polyline._rest)?;
+    Ok(())
+}
+
+/// Inverse of [`parse_dds_stream`]
+pub fn write_dds_stream<W: Write>(w: &mut W, stream: &DdsStream) -> Result<(), Error> {
+    write_dds_stream_header(w, &stream.header)?;
+    for ctrl in &stream.controls {
+        write_dds_stream_ctrl(w, ctrl)?;
+    }
+    write_dds_stream_trailer(w, &stream.numbers)
+}
+
+pub fn write_dds_stream_header<W: Write>(w: &mut W, header: &DdsStreamHeader) -> Result<(), Error> {
+    write_u32(w, 12)?;
+    write_i32(w, header._a1.0)?;
+    write_i32(w, header._a1.1)?;
+    write_properties(w, &header.properties)?;
+    write_u32(w, header.flags)?;
+    write_i32(w, header._a7.0)?;
+    write_i32(w, header._a7.1)?;
+    write_i32(w, header._a8.0)?;
+    write_i32(w, header._a8.1)?;
+    write_u32(w, header._a11)?;
+    w.write_all(&header._a12)?;
+    Ok(())
+}
+
+pub fn write_dds_stream_trailer<W: Write>(w: &mut W, numbers: &[u32]) -> Result<(), Error> {
+    let count = u32::try_from(numbers.len()).map_err(Error::BufTooLong)?;
+    write_u32(w, count)?;
+    for number in numbers {
+        write_u32(w, *number)?;
+    }
+    Ok(())
+}
+
+pub fn write_dds_stream_ctrl<W: Write>(w: &mut W, ctrl: &DdsStreamCtrl) -> Result<(), Error> {
+    write_i32(w, ctrl.id1)?;
+    write_i32(w, ctrl.id2)?;
+    write_i32(w, ctrl.parent_id)?;
+    let len = u32::try_from(ctrl._a1.len()).map_err(Error::BufTooLong)?;
+    write_u32(w, len)?;
+    w.write_all(&ctrl._a1)?;
+    w.write_all(&ctrl._a2)?;
+    write_u32(w, ctrl._a3)?;
+    if ctrl.parent_id > 0 {
+        write_u8(w, ctrl._a4)?;
+    }
+    write_properties(w, &ctrl.properties)
+}
+
+fn write_properties<W: Write>(
+    w: &mut W,
+    properties: &BTreeMap<String, Variant>,
+) -> Result<(), Error> {
+    let count = u16::try_from(properties.len()).map_err(Error::BufTooLong)?;
+    write_u16(w, count)?;
+    for (key, value) in properties {
+        write_u32_bytes_wstring_nt(w, key)?;
+        write_u32(w, 1)?;
+        write_variant(w, value)?;
+    }
+    Ok(())
+}
+
+/// Annotated version of [`write_label`]
+pub(crate) fn annotate_label(l: &mut Layout, label: &Label) -> Result<(), Error> {
+    l.unknown("_d1", |w| write_u32(w, label._d1))?;
+    l.known("size", |w| write_size(w, &label.size))?;
+    l.unknown("_d2", |w| w.write_all(&label._d2))?;
+    l.known("back_color", |w| write_ole_color(w, &label.back_color))?;
+    l.known("fore_color", |w| write_ole_color(w, &label.fore_color))?;
+    l.known("justification", |w| {
+        write_u16(w, label.justification as u16)
+    })?;
+    l.unknown("_d3", |w| write_u16(w, label._d3))?;
+    l.known("flags", |w| write_u16(w, label.flags.bits()))?;
+    l.known("font", |w| write_std_font(w, &label.font))?;
+    l.known("text", |w| write_u16_wstring(w, &label.text))
+}
+
+/// Annotated version of [`write_polyline`]
+pub(crate) fn annotate_polyline(l: &mut Layout, polyline: &Polyline) -> Result<(), Error> {
+    let position_count = u16::try_from(polyline.positions.len()).map_err(Error::BufTooLong)?;
+    let label_count = u32::try_from(polyline.labels.len()).map_err(Error::BufTooLong)?;
+    l.known("positions.len", |w| write_u16(w, position_count))?;
+    l.unknown("_d1", |w| write_u16(w, polyline._d1))?;
+    for (i, pos) in polyline.positions.iter().enumerate() {
+        l.known(&format!("positions[{}]", i), |w| write_position(w, pos))?;
+    }
+    l.known("end_type_src", |w| {
+        write_u32(w, polyline.end_type_src as u32)
+    })?;
+    l.known("end_type_dest", |w| {
+        write_u32(w, polyline.end_type_dest as u32)
+    })?;
+    l.known("color", |w| write_ole_color(w, &polyline.color))?;
+    l.unknown("_x1", |w| w.write_all(&polyline._x1))?;
+    l.known("labels.len", |w| write_u32(w, label_count))?;
+    for (i, label_ref) in polyline.labels.iter().enumerate() {
+        l.group(&format!("labels[{}]", i), |l| {
+            l.known("id", |w| write_u32(w, label_ref.id))?;
+            l.unknown("_x2", |w| write_u32(w, label_ref._x2))?;
+            l.known("pos", |w| write_position(w, &label_ref.pos))?;
+            l.known("size", |w| write_size(w, &label_ref.size))
+        })?;
+    }
+    l.unknown("_d7", |w| write_u8(w, polyline._d7))?;
+    l.unknown("_rest", |w| w.write_all(&polyline._rest))
+}
+
+fn annotate_properties(
+    l: &mut Layout,
+    properties: &BTreeMap<String, Variant>,
+) -> Result<(), Error> {
+    let count = u16::try_from(properties.len()).map_err(Error::BufTooLong)?;
+    l.known("properties.len", |w| write_u16(w, count))?;
+    for (key, value) in properties {
+        l.group(key, |l| {
+            l.known("key", |w| write_u32_bytes_wstring_nt(w, key))?;
+            l.unknown("_count", |w| write_u32(w, 1))?;
+            l.known("value", |w| write_variant(w, value))
+        })?;
+    }
+    Ok(())
+}
+
+/// Annotated version of [`write_dds_stream`]
+pub(crate) fn annotate_dds_stream(l: &mut Layout, stream: &DdsStream) -> Result<(), Error> {
+    let header = &stream.header;
+    l.group("header", |l| {
+        l.unknown("_magic", |w| write_u32(w, 12))?;
+        l.unknown("_a1", |w| {
+            write_i32(w, header._a1.0)?;
+            write_i32(w, header._a1.1)
+        })?;
+        annotate_properties(l, &header.properties)?;
+        l.known("flags", |w| write_u32(w, header.flags))?;
+        l.unknown("_a7", |w| {
+            write_i32(w, header._a7.0)?;
+            write_i32(w, header._a7.1)
+        })?;
+        l.unknown("_a8", |w| {
+            write_i32(w, header._a8.0)?;
+            write_i32(w, header._a8.1)
+        })?;
+        l.unknown("_a11", |w| write_u32(w, header._a11))?;
+        l.unknown("_a12", |w| w.write_all(&header._a12))
+    })?;
+    for (i, ctrl) in stream.controls.iter().enumerate() {
+        l.group(&format!("controls[{}]", i), |l| {
+            l.known("id1", |w| write_i32(w, ctrl.id1))?;
+            l.known("id2", |w| write_i32(w, ctrl.id2))?;
+            l.known("parent_id", |w| write_i32(w, ctrl.parent_id))?;
+            let len = u32::try_from(ctrl._a1.len()).map_err(Error::BufTooLong)?;
+            l.unknown("_a1", |w| {
+                write_u32(w, len)?;
+                w.write_all(&ctrl._a1)
+            })?;
+            l.unknown("_a2", |w| w.write_all(&ctrl._a2))?;
+            l.unknown("_a3", |w| write_u32(w, ctrl._a3))?;
+            if ctrl.parent_id > 0 {
+                l.unknown("_a4", |w| write_u8(w, ctrl._a4))?;
+            }
+            annotate_properties(l, &ctrl.properties)
+        })?;
+    }
+    l.group("numbers", |l| {
+        let count = u32::try_from(stream.numbers.len()).map_err(Error::BufTooLong)?;
+        l.known("len", |w| write_u32(w, count))?;
+        for (i, number) in stream.numbers.iter().enumerate() {
+            l.unknown(&format!("[{}]", i), |w| write_u32(w, *number))?;
+        }
+        Ok(())
+    })
+}
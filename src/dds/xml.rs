@@ -0,0 +1,549 @@
+//! # DDS XML layouts
+//!
+//! Outside of sysdiagrams, the design surface persists its layout as a `<dds>` XML
+//! document, e.g. in the `DiagramLayout` annotations of SSAS databases and SSIS packages.
+//! The structure mirrors the binary format:
+//!
+//! - `<diagram>` has the properties of the form (scroll position, zoom, grid, font)
+//! - every `<ddscontrol>` is one site, with its position and size in HIMETRIC
+//! - the `<layoutobject>` of a control has a property dictionary (like the `\3DdsStream`)
+//! - lines have a `<polyline>` with the end types and a `<connector>` with the points
+//!
+//! ```xml
+//! <dds xmlns="">
+//!   <diagram version="7" nextobject="2" scale="100" zoom="100" ... />
+//!   <ddscontrol controlprogid="MSDDS.Polyline" left="2875" top="7199" ...>
+//!     <control><ddsxmlobj><polyline endtypedst="6" endtypesrc="3" ... /></ddsxmlobj></control>
+//!     <layoutobject><ddsxmlobj><property name="Virtual" value="0" vartype="11" /></ddsxmlobj></layoutobject>
+//!     <connector sourceid="3" destid="2" ...><point x="3274" y="7698" />...</connector>
+//!   </ddscontrol>
+//! </dds>
+//! ```
+//!
+//! These documents are embedded in other files, which [`extract_layouts`] can find:
+//!
+//! - SSAS (ASSL scripts, `.dim`, `.cube`, `.dsv`): the `Value` of an `Annotation`
+//!   named [`DIAGRAM_LAYOUT_ANNOTATION`] on the owning object, or one `Diagram` per layout
+//!   in the [`DSV_LAYOUTS_ANNOTATION`] of a `DataSourceView`
+//! - SSIS (`.dtsx` before SQL Server 2012): the escaped value of a `DTS:PackageVariable`
+//!   in the [`DTS_DESIGNER_NAMESPACE`], which names the owning executable by its `DTSID`
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use bstr::BString;
+use ms_oforms::common::VarType;
+use ms_oforms::properties::{
+    font::{parse_std_font, StdFont},
+    Position, Size,
+};
+use nom::IResult;
+use num_traits::FromPrimitive;
+use roxmltree::{Document, Node};
+use uuid::Uuid;
+
+use super::DdsPolylineEndType;
+use crate::{decode_hex, Error, Variant};
+
+/// ProgID of relationship lines
+pub const PROGID_POLYLINE: &str = "MSDDS.Polyline";
+
+/// Name of the SSAS annotation that holds a `<dds>` layout
+pub const DIAGRAM_LAYOUT_ANNOTATION: &str =
+    "http://schemas.microsoft.com/DataWarehouse/Designer/1.0:DiagramLayout";
+
+/// Name of the annotation on an SSAS `DataSourceView` that holds its named diagrams
+pub const DSV_LAYOUTS_ANNOTATION: &str =
+    "http://schemas.microsoft.com/DataWarehouse/Designer/1.0:Layouts";
+
+/// `Namespace` of the SSIS package variables that hold designer layouts
+pub const DTS_DESIGNER_NAMESPACE: &str = "dts-designer-1.0";
+
+/// A `<dds>` document
+#[derive(Debug)]
+pub struct DdsDocument {
+    pub diagram: DdsDiagram,
+    pub controls: Vec<DdsControl>,
+}
+
+/// The `<diagram>` element
+#[derive(Debug)]
+pub struct DdsDiagram {
+    pub version: u32,
+    /// The next free `logicalid`
+    pub next_object: i32,
+    /// ProgID of the layout manager, e.g. `MSDDS.Rectilinear`
+    pub default_layout: String,
+    /// ProgID of the line router, e.g. `MSDDS.Rectilinear`
+    pub default_line_route: String,
+    /// Zoom in percent
+    pub zoom: u32,
+    pub scale: u32,
+    pub scroll: Position,
+    pub grid: Size,
+    pub margin: Size,
+    /// The `x` and `y` attributes
+    pub size: Size,
+    /// `COLORREF` of the background (`0x00BBGGRR`)
+    pub back_color: u32,
+    pub font: Option<StdFont>,
+    /// All attributes, including the ones above
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// A `<ddscontrol>` element, i.e. one shape or line on the diagram
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DdsControl {
+    /// e.g. `DdsShapes.DdsObjectManagedBridge.2` for tables or [`PROGID_POLYLINE`]
+    pub prog_id: String,
+    pub tooltip: Option<String>,
+    /// Position of the top-left corner
+    pub pos: Position,
+    pub size: Size,
+    pub logical_id: i32,
+    /// The ID that [`Connector::source_id`] and [`Connector::dest_id`] refer to
+    pub control_id: i32,
+    pub master_id: i32,
+    pub visible: bool,
+    pub is_annotation: bool,
+    /// Binary persisted control (`<ddsxmlobjectstreaminitwrapper>`)
+    pub init_data: Option<BString>,
+    /// The `<polyline>` inside `<control>`, for lines
+    pub polyline: Option<XmlPolyline>,
+    /// The properties of the `<layoutobject>`
+    pub properties: BTreeMap<String, XmlProperty>,
+    pub connector: Option<Connector>,
+    /// All attributes, including the ones above
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl DdsControl {
+    /// The `LogicalObject` property, e.g. the data source view table (`dbo_DimDate`)
+    pub fn logical_object(&self) -> Option<&str> {
+        self.properties
+            .get("LogicalObject")
+            .map(|p| p.value.as_str())
+    }
+}
+
+/// A `<property>` of a `<layoutobject>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlProperty {
+    /// The value as a string
+    pub value: String,
+    /// The `VARTYPE` of the value
+    pub vartype: u16,
+}
+
+impl XmlProperty {
+    /// Convert to a [`Variant`], if the type is supported
+    pub fn to_variant(&self) -> Option<Variant> {
+        let value = self.value.as_str();
+        let variant = match VarType::from_bits(self.vartype)? {
+            VarType::EMPTY => Variant::Empty,
+            VarType::NULL => Variant::Null,
+            VarType::I1 => Variant::I1(value.parse().ok()?),
+            VarType::I2 => Variant::I2(value.parse().ok()?),
+            VarType::I4 => Variant::I4(value.parse().ok()?),
+            VarType::I8 => Variant::I8(value.parse().ok()?),
+            VarType::UI1 => Variant::Ui1(value.parse().ok()?),
+            VarType::UI2 => Variant::Ui2(value.parse().ok()?),
+            VarType::UI4 => Variant::Ui4(value.parse().ok()?),
+            VarType::UI8 => Variant::Ui8(value.parse().ok()?),
+            VarType::R4 => Variant::R4(value.parse().ok()?),
+            VarType::R8 => Variant::R8(value.parse().ok()?),
+            VarType::CY => Variant::Cy((value.parse::<f64>().ok()? * 10_000.0).round() as i64),
+            VarType::DATE => Variant::Date(value.parse().ok()?),
+            VarType::BSTR => Variant::BStr(self.value.clone()),
+            VarType::ERROR => Variant::Error(value.parse::<i32>().ok()? as u32),
+            VarType::BOOL => Variant::Bool(value.parse::<i16>().ok()? != 0),
+            VarType::CLSID => Variant::Clsid(Uuid::parse_str(value).ok()?),
+            _ => return None,
+        };
+        Some(variant)
+    }
+}
+
+/// The `<polyline>` element, the XML version of [`super::Polyline`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlPolyline {
+    pub end_type_src: DdsPolylineEndType,
+    pub end_type_dest: DdsPolylineEndType,
+    pub user_color: u32,
+    pub line_style: u32,
+    pub line_render: u32,
+    pub adorns_visible: bool,
+}
+
+/// The `<connector>` element of a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connector {
+    /// ProgID of the line router, e.g. `MSDDS.Rectilinear`
+    pub line_route_style: String,
+    /// `controlid` of the source shape
+    pub source_id: i32,
+    /// `controlid` of the destination shape
+    pub dest_id: i32,
+    pub source_attach_point: i32,
+    pub dest_attach_point: i32,
+    pub points: Vec<Position>,
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn attr<T: FromStr>(node: Node, name: &'static str) -> Result<T, Error> {
+    let value = node
+        .attribute(name)
+        .ok_or(Error::XmlMissingAttribute(name))?;
+    value
+        .parse()
+        .map_err(|_| Error::XmlAttribute(name, value.to_string()))
+}
+
+fn opt_attr<T: FromStr>(node: Node, name: &'static str) -> Result<Option<T>, Error> {
+    match node.attribute(name) {
+        Some(_) => attr(node, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn flag(node: Node, name: &'static str) -> Result<bool, Error> {
+    Ok(opt_attr::<i32>(node, name)?.map_or(false, |v| v != 0))
+}
+
+fn attributes(node: Node) -> BTreeMap<String, String> {
+    node.attributes()
+        .map(|a| (a.name().to_string(), a.value().to_string()))
+        .collect()
+}
+
+fn end_type(node: Node, name: &'static str) -> Result<DdsPolylineEndType, Error> {
+    let value: u32 = attr(node, name)?;
+    DdsPolylineEndType::from_u32(value).ok_or_else(|| Error::XmlAttribute(name, value.to_string()))
+}
+
+/// The `binary` of the `<ddsxmlobjectstreamwrapper>` (or `...initwrapper`) in `node`
+fn binary(node: Node, wrapper: &str) -> Result<Option<Vec<u8>>, Error> {
+    match child(node, wrapper).and_then(|n| n.attribute("binary")) {
+        Some(hex) => decode_hex(hex.as_bytes()).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn parse_diagram(node: Node) -> Result<DdsDiagram, Error> {
+    let font = match child(node, "font") {
+        Some(font) => match binary(font, "ddsxmlobjectstreamwrapper")? {
+            Some(bytes) => {
+                let result: IResult<&[u8], StdFont> = parse_std_font(&bytes);
+                let (_, font) = result?;
+                Some(font)
+            }
+            None => None,
+        },
+        None => None,
+    };
+    Ok(DdsDiagram {
+        version: attr(node, "version")?,
+        next_object: attr(node, "nextobject")?,
+        default_layout: attr(node, "defaultlayout")?,
+        default_line_route: attr(node, "defaultlineroute")?,
+        zoom: attr(node, "zoom")?,
+        scale: attr(node, "scale")?,
+        scroll: Position {
+            left: attr(node, "scrollleft")?,
+            top: attr(node, "scrolltop")?,
+        },
+        grid: Size {
+            width: attr(node, "gridx")?,
+            height: attr(node, "gridy")?,
+        },
+        margin: Size {
+            width: attr(node, "marginx")?,
+            height: attr(node, "marginy")?,
+        },
+        size: Size {
+            width: attr(node, "x")?,
+            height: attr(node, "y")?,
+        },
+        back_color: attr(node, "backcolor")?,
+        font,
+        attributes: attributes(node),
+    })
+}
+
+fn parse_polyline(node: Node) -> Result<XmlPolyline, Error> {
+    Ok(XmlPolyline {
+        end_type_src: end_type(node, "endtypesrc")?,
+        end_type_dest: end_type(node, "endtypedst")?,
+        user_color: attr(node, "usercolor")?,
+        line_style: attr(node, "linestyle")?,
+        line_render: attr(node, "linerender")?,
+        adorns_visible: flag(node, "adornsvisible")?,
+    })
+}
+
+fn parse_connector(node: Node) -> Result<Connector, Error> {
+    let points = node
+        .children()
+        .filter(|n| n.has_tag_name("point"))
+        .map(|p| {
+            Ok(Position {
+                left: attr(p, "x")?,
+                top: attr(p, "y")?,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(Connector {
+        line_route_style: attr(node, "lineroutestyle")?,
+        source_id: attr(node, "sourceid")?,
+        dest_id: attr(node, "destid")?,
+        source_attach_point: attr(node, "sourceattachpoint")?,
+        dest_attach_point: attr(node, "destattachpoint")?,
+        points,
+    })
+}
+
+fn parse_properties(node: Node) -> Result<BTreeMap<String, XmlProperty>, Error> {
+    let obj = match child(node, "ddsxmlobj") {
+        Some(obj) => obj,
+        None => return Ok(BTreeMap::new()),
+    };
+    obj.children()
+        .filter(|n| n.has_tag_name("property"))
+        .map(|p| {
+            let name: String = attr(p, "name")?;
+            let property = XmlProperty {
+                value: p.attribute("value").unwrap_or_default().to_string(),
+                vartype: attr(p, "vartype")?,
+            };
+            Ok((name, property))
+        })
+        .collect()
+}
+
+fn parse_control(node: Node) -> Result<DdsControl, Error> {
+    let control = child(node, "control");
+    let init_data = match control {
+        Some(control) => binary(control, "ddsxmlobjectstreaminitwrapper")?.map(BString::from),
+        None => None,
+    };
+    let polyline = control
+        .and_then(|c| child(c, "ddsxmlobj"))
+        .and_then(|o| child(o, "polyline"))
+        .map(parse_polyline)
+        .transpose()?;
+    let properties = match child(node, "layoutobject") {
+        Some(layout_object) => parse_properties(layout_object)?,
+        None => BTreeMap::new(),
+    };
+    let connector = child(node, "connector").map(parse_connector).transpose()?;
+
+    Ok(DdsControl {
+        prog_id: attr(node, "controlprogid")?,
+        tooltip: node.attribute("tooltip").map(str::to_string),
+        pos: Position {
+            left: attr(node, "left")?,
+            top: attr(node, "top")?,
+        },
+        size: Size {
+            width: attr(node, "width")?,
+            height: attr(node, "height")?,
+        },
+        logical_id: attr(node, "logicalid")?,
+        control_id: attr(node, "controlid")?,
+        master_id: attr(node, "masterid")?,
+        visible: flag(node, "visible")?,
+        is_annotation: flag(node, "isannotation")?,
+        init_data,
+        polyline,
+        properties,
+        connector,
+        attributes: attributes(node),
+    })
+}
+
+/// Parse a `<dds>` element
+pub(crate) fn parse_dds_node(node: Node) -> Result<DdsDocument, Error> {
+    if !node.has_tag_name("dds") {
+        return Err(Error::XmlMissingElement("dds"));
+    }
+    let diagram = child(node, "diagram").ok_or(Error::XmlMissingElement("diagram"))?;
+    Ok(DdsDocument {
+        diagram: parse_diagram(diagram)?,
+        controls: node
+            .children()
+            .filter(|n| n.has_tag_name("ddscontrol"))
+            .map(parse_control)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Parse a `<dds>` XML document
+pub fn parse_dds_xml(text: &str) -> Result<DdsDocument, Error> {
+    let document = Document::parse(text)?;
+    parse_dds_node(document.root_element())
+}
+
+impl DdsDocument {
+    /// Find a control by its `controlid`
+    pub fn control(&self, control_id: i32) -> Option<&DdsControl> {
+        self.controls.iter().find(|c| c.control_id == control_id)
+    }
+
+    /// The controls that are lines between other controls
+    pub fn connections(&self) -> impl Iterator<Item = (&DdsControl, &Connector)> {
+        self.controls
+            .iter()
+            .filter_map(|c| c.connector.as_ref().map(|connector| (c, connector)))
+    }
+}
+
+/// The object that an embedded layout belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutOwner {
+    /// The element name for SSAS (e.g. `Dimension`, `Cube`, `DataSourceView`),
+    /// the `ExecutableType` for SSIS (e.g. `SSIS.Package.2`)
+    pub kind: String,
+    /// The `ID` (SSAS) or `DTSID` (SSIS)
+    pub id: Option<String>,
+    /// The `Name` (SSAS) or `ObjectName` (SSIS)
+    pub name: Option<String>,
+}
+
+/// A `<dds>` layout found by [`extract_layouts`]
+#[derive(Debug)]
+pub struct EmbeddedLayout {
+    pub owner: LayoutOwner,
+    /// The designer element around the layout in SSIS, e.g. `DtsControlFlowDiagram`,
+    /// or the `Name` of the diagram in a `DataSourceView`
+    pub designer: Option<String>,
+    pub layout: DdsDocument,
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|n| n.text())
+}
+
+/// Find the `DTS:Property` with the given `DTS:Name`
+fn dts_property<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .filter(|n| n.has_tag_name("Property"))
+        .find(|n| {
+            n.attributes()
+                .any(|a| a.name() == "Name" && a.value() == name)
+        })
+        .map(|n| n.text().unwrap_or_default())
+}
+
+/// Find a `DTS` attribute (SSIS 2012+) or property (before)
+fn dts_value<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|a| a.name() == name)
+        .map(|a| a.value())
+        .or_else(|| dts_property(node, name))
+}
+
+fn ssas_layouts(document: &Document) -> Result<Vec<EmbeddedLayout>, Error> {
+    let mut layouts = Vec::new();
+    for annotation in document
+        .descendants()
+        .filter(|n| n.has_tag_name("Annotation"))
+    {
+        // `Annotation` > `Annotations` > owner
+        let owner = match annotation.parent_element().and_then(|n| n.parent_element()) {
+            Some(owner) => LayoutOwner {
+                kind: owner.tag_name().name().to_string(),
+                id: child_text(owner, "ID").map(str::to_string),
+                name: child_text(owner, "Name").map(str::to_string),
+            },
+            None => continue,
+        };
+        let value = child(annotation, "Value");
+        match child_text(annotation, "Name") {
+            Some(DIAGRAM_LAYOUT_ANNOTATION) => {
+                if let Some(dds) = value.and_then(|v| child(v, "dds")) {
+                    layouts.push(EmbeddedLayout {
+                        owner,
+                        designer: None,
+                        layout: parse_dds_node(dds)?,
+                    });
+                }
+            }
+            Some(DSV_LAYOUTS_ANNOTATION) => {
+                // `Layouts` > `Diagram` > `DiagramLayout` > `dds`
+                let diagrams = value
+                    .and_then(|v| child(v, "Layouts"))
+                    .into_iter()
+                    .flat_map(|n| n.children())
+                    .filter(|n| n.has_tag_name("Diagram"));
+                for diagram in diagrams {
+                    let dds = child(diagram, "DiagramLayout").and_then(|n| child(n, "dds"));
+                    if let Some(dds) = dds {
+                        layouts.push(EmbeddedLayout {
+                            owner: owner.clone(),
+                            designer: child_text(diagram, "Name").map(str::to_string),
+                            layout: parse_dds_node(dds)?,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(layouts)
+}
+
+fn ssis_owner(document: &Document, dtsid: &str) -> LayoutOwner {
+    let executable = document
+        .descendants()
+        .filter(|n| n.has_tag_name("Executable"))
+        .find(|n| dts_value(*n, "DTSID") == Some(dtsid));
+    LayoutOwner {
+        kind: executable
+            .and_then(|n| dts_value(n, "ExecutableType"))
+            .unwrap_or("Executable")
+            .to_string(),
+        id: Some(dtsid.to_string()),
+        name: executable
+            .and_then(|n| dts_value(n, "ObjectName"))
+            .map(str::to_string),
+    }
+}
+
+fn ssis_layouts(document: &Document) -> Result<Vec<EmbeddedLayout>, Error> {
+    let variables = document.descendants().filter(|n| {
+        n.has_tag_name("PackageVariable")
+            && dts_value(*n, "Namespace") == Some(DTS_DESIGNER_NAMESPACE)
+    });
+    let mut layouts = Vec::new();
+    for variable in variables {
+        let value = match dts_value(variable, "PackageVariableValue") {
+            Some(value) => value,
+            None => continue,
+        };
+        let owner = ssis_owner(
+            document,
+            dts_value(variable, "ObjectName").unwrap_or_default(),
+        );
+        let inner = Document::parse(value)?;
+        for dds in inner.descendants().filter(|n| n.has_tag_name("dds")) {
+            // e.g. `dwd:DtsControlFlowDiagram` > `dwd:Layout` > `dds`
+            let designer = dds.parent_element().and_then(|n| n.parent_element());
+            layouts.push(EmbeddedLayout {
+                owner: owner.clone(),
+                designer: designer.map(|n| n.tag_name().name().to_string()),
+                layout: parse_dds_node(dds)?,
+            });
+        }
+    }
+    Ok(layouts)
+}
+
+/// Find all `<dds>` layouts in an SSAS ASSL document or an SSIS package
+pub fn extract_layouts(text: &str) -> Result<Vec<EmbeddedLayout>, Error> {
+    let document = Document::parse(text)?;
+    let mut layouts = ssas_layouts(&document)?;
+    layouts.extend(ssis_layouts(&document)?);
+    Ok(layouts)
+}
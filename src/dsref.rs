@@ -63,8 +63,11 @@
 //! [`SetProperty`]: https://learn.microsoft.com/en-us/dotnet/api/microsoft.visualstudio.data.services.supportentities.interop.idsrefprovider.setproperty
 
 use crate::{
-    dtyp::{parse_variant, Variant},
+    dtyp::{parse_variant, write_variant, Variant},
+    hexdump::Layout,
     parse_u32_bytes_wstring_nt,
+    writer::{write_guid, write_u16, write_u32, write_u32_bytes_wstring_nt, write_u64},
+    Error,
 };
 use ms_oforms::common::parse_guid;
 use nom::{
@@ -74,7 +77,13 @@ use nom::{
     number::complete::{le_u16, le_u32, le_u64},
     IResult,
 };
-use std::{borrow::Cow, collections::BTreeMap};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    convert::TryFrom,
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use uuid::{uuid, Uuid};
 
 /// Microsoft Data Tools DSRef Object `{e9b0e6db-811c-11d0-ad51-00a0c90f5739}`
@@ -109,6 +118,7 @@ bitflags::bitflags! {
     ///
     /// See: <https://learn.microsoft.com/en-us/dotnet/api/microsoft.visualstudio.data.services.supportentities.interop.__dsreftype>
     #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct DsRefType: u32 {
         /// Specifies a collection.
         const COLLECTION = 1;
@@ -204,6 +214,7 @@ bitflags::bitflags! {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DsRefNode {
     pub flags: DsRefType,
     pub extended_type: Option<Uuid>,
@@ -219,6 +230,7 @@ pub struct DsRefNode {
 
 #[derive(Debug)]
 #[allow(dead_code)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DSRefSchemaContents {
     pub clsid: Uuid,
     pub(crate) len: usize,
@@ -236,6 +248,13 @@ fn windows_tick_to_unix_seconds(windows_ticks: u64) -> u64 {
     windows_ticks / WINDOWS_TICK - SEC_TO_UNIX_EPOCH
 }
 
+/// Inverse of [`DSRefSchemaContents::get_time`], with sub-second precision
+pub(crate) fn system_time_to_windows_ticks(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since_epoch.as_secs() + SEC_TO_UNIX_EPOCH) * WINDOWS_TICK
+        + u64::from(since_epoch.subsec_nanos()) / 100
+}
+
 impl DSRefSchemaContents {
     /// Get the timestamp as seconds from [`std::time::UNIX_EPOCH`]
     pub fn get_time(&self) -> u64 {
@@ -248,6 +267,7 @@ where
     E: ParseError<&'a [u8]>,
     E: ContextError<&'a [u8]>,
     E: FromExternalError<&'a [u8], Cow<'static, str>>,
+    E: FromExternalError<&'a [u8], Error>,
 {
     let (input, prop_count) = le_u32(input)?;
     let mut _i = input;
@@ -267,6 +287,7 @@ where
     E: ParseError<&'a [u8]>,
     E: ContextError<&'a [u8]>,
     E: FromExternalError<&'a [u8], Cow<'static, str>>,
+    E: FromExternalError<&'a [u8], Error>,
 {
     let (input, flags) = map_opt(le_u32, DsRefType::from_bits)(input)?;
     let (input, extended_type) = cond(flags.contains(DsRefType::EXTENDED), parse_guid)(input)?;
@@ -312,6 +333,7 @@ where
     E: ParseError<&'a [u8]>,
     E: ContextError<&'a [u8]>,
     E: FromExternalError<&'a [u8], Cow<'static, str>>,
+    E: FromExternalError<&'a [u8], Error>,
 {
     let (input, clsid) = parse_guid(input)?;
     let len = input.len();
@@ -332,3 +354,107 @@ where
         },
     ))
 }
+
+fn write_dsref_properties<W: Write>(
+    w: &mut W,
+    properties: &BTreeMap<Uuid, Variant>,
+) -> Result<(), Error> {
+    let count = u32::try_from(properties.len()).map_err(Error::BufTooLong)?;
+    write_u32(w, count)?;
+    for (property, value) in properties {
+        write_guid(w, property)?;
+        write_variant(w, value)?;
+    }
+    Ok(())
+}
+
+/// Inverse of [`parse_dsref_node`]
+///
+/// The optional parts of the node are written according to [`DsRefNode::flags`].
+pub fn write_dsref_node<W: Write>(w: &mut W, node: &DsRefNode) -> Result<(), Error> {
+    write_u32(w, node.flags.bits())?;
+    if node.flags.contains(DsRefType::EXTENDED) {
+        write_guid(w, &node.extended_type.unwrap_or_default())?;
+    }
+    if node.flags.contains(DsRefType::HASNAME) {
+        write_u32_bytes_wstring_nt(w, node.name.as_deref().unwrap_or_default())?;
+    }
+    if node.flags.contains(DsRefType::HASOWNER) {
+        write_u32_bytes_wstring_nt(w, node.owner.as_deref().unwrap_or_default())?;
+    }
+    if node.flags.contains(DsRefType::HASFIRSTCHILD) {
+        for child in &node.children {
+            write_dsref_node(w, child)?;
+        }
+    }
+    if node.flags.contains(DsRefType::HASPROP) {
+        let empty = BTreeMap::new();
+        write_dsref_properties(w, node.properties.as_ref().unwrap_or(&empty))?;
+    }
+    Ok(())
+}
+
+/// Inverse of [`parse_dsref_schema_contents`]
+pub fn write_dsref_schema_contents<W: Write>(
+    w: &mut W,
+    contents: &DSRefSchemaContents,
+) -> Result<(), Error> {
+    write_guid(w, &contents.clsid)?;
+    write_u16(w, 0)?; // version
+    write_u16(w, contents.a)?;
+    write_u64(w, contents.timestamp)?;
+    write_u32(w, contents.b)?;
+    write_dsref_node(w, &contents.root_node)
+}
+
+fn annotate_dsref_node(l: &mut Layout, node: &DsRefNode) -> Result<(), Error> {
+    l.known("flags", |w| write_u32(w, node.flags.bits()))?;
+    if node.flags.contains(DsRefType::EXTENDED) {
+        l.known("extended_type", |w| {
+            write_guid(w, &node.extended_type.unwrap_or_default())
+        })?;
+    }
+    if node.flags.contains(DsRefType::HASNAME) {
+        l.known("name", |w| {
+            write_u32_bytes_wstring_nt(w, node.name.as_deref().unwrap_or_default())
+        })?;
+    }
+    if node.flags.contains(DsRefType::HASOWNER) {
+        l.known("owner", |w| {
+            write_u32_bytes_wstring_nt(w, node.owner.as_deref().unwrap_or_default())
+        })?;
+    }
+    if node.flags.contains(DsRefType::HASFIRSTCHILD) {
+        for (i, child) in node.children.iter().enumerate() {
+            l.group(&format!("children[{}]", i), |l| {
+                annotate_dsref_node(l, child)
+            })?;
+        }
+    }
+    if node.flags.contains(DsRefType::HASPROP) {
+        let empty = BTreeMap::new();
+        let properties = node.properties.as_ref().unwrap_or(&empty);
+        let count = u32::try_from(properties.len()).map_err(Error::BufTooLong)?;
+        l.known("properties.len", |w| write_u32(w, count))?;
+        for (property, value) in properties {
+            l.known(&property.to_string(), |w| {
+                write_guid(w, property)?;
+                write_variant(w, value)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Annotated version of [`write_dsref_schema_contents`]
+pub(crate) fn annotate_dsref_schema_contents(
+    l: &mut Layout,
+    contents: &DSRefSchemaContents,
+) -> Result<(), Error> {
+    l.known("clsid", |w| write_guid(w, &contents.clsid))?;
+    l.known("version", |w| write_u16(w, 0))?;
+    l.unknown("a", |w| write_u16(w, contents.a))?;
+    l.known("timestamp", |w| write_u64(w, contents.timestamp))?;
+    l.unknown("b", |w| write_u32(w, contents.b))?;
+    l.group("root_node", |l| annotate_dsref_node(l, &contents.root_node))
+}
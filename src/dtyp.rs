@@ -1,30 +1,116 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
 
-use ms_oforms::common::VarType;
+use ms_oforms::common::{parse_guid, VarType};
 use nom::{
-    combinator::{map, map_opt},
-    error::{FromExternalError, ParseError},
-    number::complete::le_u16,
+    combinator::{map, map_opt, success},
+    error::{ErrorKind, FromExternalError, ParseError},
+    number::complete::{
+        le_f32, le_f64, le_i16, le_i32, le_i64, le_i8, le_u16, le_u32, le_u64, le_u8,
+    },
     IResult,
 };
+use uuid::Uuid;
 
-use crate::parse_u32_bytes_wstring_nt;
+use crate::{
+    parse_u32_bytes_wstring_nt,
+    writer::{write_guid, write_u16, write_u32_bytes_wstring_nt},
+    Error,
+};
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// A typed value, as in a `VARIANT` (see [MS-OAUT] 2.2.29.2)
+///
+/// On the wire, the `VARTYPE` is followed by the value without any padding.
+///
+/// [MS-OAUT]: https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-oaut
+#[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Variant {
+    /// `VT_EMPTY`, no value
+    Empty,
+    /// `VT_NULL`, no value
+    Null,
+    I1(i8),
+    I2(i16),
+    I4(i32),
+    I8(i64),
+    Ui1(u8),
+    Ui2(u16),
+    Ui4(u32),
+    Ui8(u64),
+    R4(f32),
+    R8(f64),
+    /// `VT_CY`, currency scaled by 10,000
+    Cy(i64),
+    /// `VT_DATE`, days since 1899-12-30
+    Date(f64),
     BStr(String),
+    /// `VT_ERROR`, an `HRESULT`
+    Error(u32),
     Bool(bool),
+    Clsid(Uuid),
+}
+
+impl Variant {
+    /// The `VARTYPE` of this value
+    pub fn var_type(&self) -> VarType {
+        match self {
+            Variant::Empty => VarType::EMPTY,
+            Variant::Null => VarType::NULL,
+            Variant::I1(_) => VarType::I1,
+            Variant::I2(_) => VarType::I2,
+            Variant::I4(_) => VarType::I4,
+            Variant::I8(_) => VarType::I8,
+            Variant::Ui1(_) => VarType::UI1,
+            Variant::Ui2(_) => VarType::UI2,
+            Variant::Ui4(_) => VarType::UI4,
+            Variant::Ui8(_) => VarType::UI8,
+            Variant::R4(_) => VarType::R4,
+            Variant::R8(_) => VarType::R8,
+            Variant::Cy(_) => VarType::CY,
+            Variant::Date(_) => VarType::DATE,
+            Variant::BStr(_) => VarType::BSTR,
+            Variant::Error(_) => VarType::ERROR,
+            Variant::Bool(_) => VarType::BOOL,
+            Variant::Clsid(_) => VarType::CLSID,
+        }
+    }
 }
 
 pub fn parse_variant<'a, E>(input: &'a [u8]) -> IResult<&'a [u8], Variant, E>
 where
     E: ParseError<&'a [u8]>,
     E: FromExternalError<&'a [u8], Cow<'static, str>>,
+    E: FromExternalError<&'a [u8], Error>,
 {
-    let (input, vt) = map_opt(le_u16, VarType::from_bits)(input)?;
+    let start = input;
+    let (input, bits) = le_u16(input)?;
+    // The length of the value is unknown for unsupported types
+    let unsupported = || {
+        let e = Error::UnsupportedVarType(bits);
+        nom::Err::Failure(E::from_external_error(start, ErrorKind::Switch, e))
+    };
+    let vt = VarType::from_bits(bits).ok_or_else(unsupported)?;
     let (input, value) = match vt {
+        VarType::EMPTY => success(Variant::Empty)(input),
+        VarType::NULL => success(Variant::Null)(input),
+        VarType::I1 => map(le_i8, Variant::I1)(input),
+        VarType::I2 => map(le_i16, Variant::I2)(input),
+        VarType::I4 => map(le_i32, Variant::I4)(input),
+        VarType::I8 => map(le_i64, Variant::I8)(input),
+        VarType::UI1 => map(le_u8, Variant::Ui1)(input),
+        VarType::UI2 => map(le_u16, Variant::Ui2)(input),
+        VarType::UI4 => map(le_u32, Variant::Ui4)(input),
+        VarType::UI8 => map(le_u64, Variant::Ui8)(input),
+        VarType::R4 => map(le_f32, Variant::R4)(input),
+        VarType::R8 => map(le_f64, Variant::R8)(input),
+        VarType::CY => map(le_i64, Variant::Cy)(input),
+        VarType::DATE => map(le_f64, Variant::Date)(input),
         VarType::BSTR => map(parse_u32_bytes_wstring_nt, Variant::BStr)(input),
+        VarType::ERROR => map(le_u32, Variant::Error)(input),
         VarType::BOOL => map(
             map_opt(le_u16, |v| match v {
                 0x0000 => Some(false),
@@ -33,7 +119,29 @@ where
             }),
             Variant::Bool,
         )(input),
-        _ => todo!("0x{:04x}", vt),
+        VarType::CLSID => map(parse_guid, Variant::Clsid)(input),
+        _ => Err(unsupported()),
     }?;
     Ok((input, value))
 }
+
+/// Inverse of [`parse_variant`]
+pub fn write_variant<W: Write>(w: &mut W, value: &Variant) -> io::Result<()> {
+    write_u16(w, value.var_type().bits())?;
+    match value {
+        Variant::Empty | Variant::Null => Ok(()),
+        Variant::I1(v) => w.write_all(&v.to_le_bytes()),
+        Variant::I2(v) => w.write_all(&v.to_le_bytes()),
+        Variant::I4(v) => w.write_all(&v.to_le_bytes()),
+        Variant::I8(v) | Variant::Cy(v) => w.write_all(&v.to_le_bytes()),
+        Variant::Ui1(v) => w.write_all(&v.to_le_bytes()),
+        Variant::Ui2(v) => w.write_all(&v.to_le_bytes()),
+        Variant::Ui4(v) | Variant::Error(v) => w.write_all(&v.to_le_bytes()),
+        Variant::Ui8(v) => w.write_all(&v.to_le_bytes()),
+        Variant::R4(v) => w.write_all(&v.to_le_bytes()),
+        Variant::R8(v) | Variant::Date(v) => w.write_all(&v.to_le_bytes()),
+        Variant::BStr(s) => write_u32_bytes_wstring_nt(w, s),
+        Variant::Bool(b) => write_u16(w, if *b { 0xFFFF } else { 0x0000 }),
+        Variant::Clsid(id) => write_guid(w, id),
+    }
+}
@@ -0,0 +1,403 @@
+//! # Editing diagrams
+//!
+//! Methods on [`SysDiagramStreams`] that add, remove, rename, move and resize the
+//! tables of an existing diagram. A table appears in several streams, which are kept
+//! consistent:
+//!
+//! - the site in the `f` stream, with the caption as its tooltip
+//! - the [`SchGrid`] control in the `o` stream, with the caption of its frame window
+//!   and the name and schema of its [`DataSource`](crate::mdtdb::DataSource)
+//! - the controls of the `\3DdsStream`, and the table IDs at its end
+//! - the `TABLE` node of the DSRef
+//! - the tooltips of the relationships, which refer to tables by caption
+//!
+//! Relationship lines are updated with [`update_relationships`] after a table moves.
+
+use ms_oforms::properties::{Position, Size};
+
+use crate::{
+    builder::{dsref_table_node, new_table, table_caption, TableSpec},
+    core::active_table_view,
+    dsref::{DSRefSchemaContents, DsRefNode, DsRefType},
+    layout::update_relationships,
+    mdtdb::{SchGrid, TableView},
+    parse_relationship, parse_wstring_nt,
+    writer::write_wstring_nt,
+    Control, Error, SysDiagramStreams,
+};
+
+/// Length of the fixed part of the DDS data of a polyline, before the schema
+const POLYLINE_DDS_PREFIX: usize = 8;
+
+fn is_table_named(sch_grid: &SchGrid, caption: &str, name: &str) -> bool {
+    let data_source = &sch_grid.data_source;
+    data_source.table == name
+        || caption == name
+        || format!("{}.{}", data_source.schema, data_source.table) == name
+}
+
+/// The `SCHEMADIAGRAM` node below the `DATABASE` root
+fn diagram_node(dsref: &mut DSRefSchemaContents) -> Option<&mut DsRefNode> {
+    dsref
+        .root_node
+        .children
+        .iter_mut()
+        .find(|node| node.flags.contains(DsRefType::SCHEMADIAGRAM))
+}
+
+/// The `TABLE` node of `schema.name` in the diagram
+fn table_node<'a>(
+    dsref: &'a mut DSRefSchemaContents,
+    schema: &str,
+    name: &str,
+) -> Option<&'a mut DsRefNode> {
+    diagram_node(dsref)?.children.iter_mut().find(|node| {
+        node.flags.contains(DsRefType::TABLE)
+            && node.name.as_deref() == Some(name)
+            && node.owner.as_deref() == Some(schema)
+    })
+}
+
+/// Set [`DsRefType::HASFIRSTCHILD`] and [`DsRefType::HASNEXTSIBLING`] after the
+/// children of `node` changed
+fn update_child_flags(node: &mut DsRefNode) {
+    node.flags
+        .set(DsRefType::HASFIRSTCHILD, !node.children.is_empty());
+    let count = node.children.len();
+    for (i, child) in node.children.iter_mut().enumerate() {
+        child.flags.set(DsRefType::HASNEXTSIBLING, i + 1 < count);
+    }
+}
+
+/// Replace the schema in the DDS data of a polyline, which is followed by the
+/// name of the foreign key
+fn replace_polyline_schema(data: &[u8], schema: &str) -> Result<Vec<u8>, Error> {
+    if data.len() < POLYLINE_DDS_PREFIX {
+        return Err(Error::PolylineDataTooShort(data.len()));
+    }
+    let (prefix, names) = data.split_at(POLYLINE_DDS_PREFIX);
+    let (rest, _) = parse_wstring_nt::<Error>(names)?;
+    let mut new = prefix.to_vec();
+    write_wstring_nt(&mut new, schema)?;
+    new.extend_from_slice(rest);
+    Ok(new)
+}
+
+impl SysDiagramStreams {
+    /// The site ID of a table, found by name, caption or `schema.name`
+    ///
+    /// Returns `None` if no table or more than one table has that name.
+    pub fn table_id(&self, name: &str) -> Option<i32> {
+        let (index, _) = self.table_index(name).ok()?;
+        Some(self.form.sites[index].id)
+    }
+
+    /// The index of the site of a table and its control
+    fn table_index(&self, name: &str) -> Result<(usize, &SchGrid), Error> {
+        let mut matches = self
+            .form
+            .sites
+            .iter()
+            .zip(&self.controls)
+            .enumerate()
+            .filter_map(|(index, (site, control))| match control {
+                Control::SchGrid(sch_grid) if is_table_named(sch_grid, &site.tooltip, name) => {
+                    Some((index, sch_grid))
+                }
+                _ => None,
+            });
+        match (matches.next(), matches.next()) {
+            (Some(found), None) => Ok(found),
+            (Some(_), Some(_)) => Err(Error::AmbiguousTable(name.to_string())),
+            (None, _) => Err(Error::UnknownTable(name.to_string())),
+        }
+    }
+
+    /// Like [`Self::table_index`], for changing the control
+    fn table_index_mut(&mut self, name: &str) -> Result<(usize, &mut SchGrid), Error> {
+        let mut matches = self
+            .form
+            .sites
+            .iter()
+            .zip(&mut self.controls)
+            .enumerate()
+            .filter_map(|(index, (site, control))| match control {
+                Control::SchGrid(sch_grid) if is_table_named(sch_grid, &site.tooltip, name) => {
+                    Some((index, sch_grid))
+                }
+                _ => None,
+            });
+        match (matches.next(), matches.next()) {
+            (Some(found), None) => Ok(found),
+            (Some(_), Some(_)) => Err(Error::AmbiguousTable(name.to_string())),
+            (None, _) => Err(Error::UnknownTable(name.to_string())),
+        }
+    }
+
+    /// Remove the sites with the given IDs with their controls
+    fn remove_sites(&mut self, ids: &[i32]) -> Result<(), Error> {
+        // polyline, from and to table of every relationship
+        let count = self.dds_stream.numbers.len();
+        if count % 5 != 0 {
+            return Err(Error::DdsNumberCount(count));
+        }
+        let (sites, controls) = self
+            .form
+            .sites
+            .drain(..)
+            .zip(self.controls.drain(..))
+            .filter(|(site, _)| !ids.contains(&site.id))
+            .unzip();
+        self.form.sites = sites;
+        self.controls = controls;
+        self.dds_stream
+            .controls
+            .retain(|ctrl| !ids.contains(&ctrl.id1));
+        self.dds_stream.numbers = self
+            .dds_stream
+            .numbers
+            .chunks(5)
+            .filter(|n| n[..3].iter().all(|&id| !ids.contains(&(id as i32))))
+            .flatten()
+            .copied()
+            .collect();
+        Ok(())
+    }
+
+    /// Add a table at `pos`, returning its site ID
+    ///
+    /// The table gets the size that SSMS uses for a new table in the given `view`.
+    pub fn add_table(
+        &mut self,
+        schema: &str,
+        name: &str,
+        pos: Position,
+        view: TableView,
+    ) -> Result<i32, Error> {
+        let qualified = format!("{}.{}", schema, name);
+        if !matches!(self.table_index(&qualified), Err(Error::UnknownTable(_))) {
+            return Err(Error::DuplicateTable(qualified));
+        }
+        let max_id = self.form.sites.iter().map(|s| s.id).max().unwrap_or(0);
+        let last_id = self.form.next_available_id().unwrap_or(0) as i32;
+        let id = last_id.max(max_id) + 1;
+
+        let table = TableSpec {
+            schema: schema.to_string(),
+            name: name.to_string(),
+            pos,
+            view,
+        };
+        let (mut site, sch_grid, dds_ctrl) = new_table(id, &table);
+        // Use the index of the class table that the other tables use
+        let class_index = self
+            .form
+            .sites
+            .iter()
+            .zip(&self.controls)
+            .find(|(_, control)| matches!(control, Control::SchGrid(_)))
+            .map(|(site, _)| site.clsid_cache_index);
+        if let Some(class_index) = class_index {
+            site.clsid_cache_index = class_index;
+        }
+        self.form.sites.push(site);
+        self.controls.push(Control::SchGrid(sch_grid));
+        self.dds_stream.controls.push(dds_ctrl);
+        self.form.set_next_available_id(id as u32);
+
+        if let Some(diagram) = diagram_node(&mut self.dsref_schema_contents) {
+            diagram.children.push(dsref_table_node(&table));
+            update_child_flags(diagram);
+        }
+        Ok(id)
+    }
+
+    /// Remove a table with its relationships and their labels
+    ///
+    /// Relationships with a tooltip that does not name their tables are kept.
+    pub fn remove_table(&mut self, name: &str) -> Result<(), Error> {
+        let (index, sch_grid) = self.table_index(name)?;
+        let data_source = sch_grid.data_source.clone();
+        let site = &self.form.sites[index];
+        let mut ids = vec![site.id];
+        for (other, control) in self.form.sites.iter().zip(&self.controls) {
+            if let Control::Polyline(polyline) = control {
+                let (_, (_, from, to)) = match parse_relationship(&other.tooltip) {
+                    Ok(names) => names,
+                    Err(_) => continue,
+                };
+                if from == site.tooltip || to == site.tooltip {
+                    ids.push(other.id);
+                    ids.extend(polyline.labels.iter().map(|l| l.id as i32));
+                }
+            }
+        }
+
+        self.remove_sites(&ids)?;
+        if let Some(diagram) = diagram_node(&mut self.dsref_schema_contents) {
+            diagram.children.retain(|node| {
+                node.name.as_deref() != Some(&data_source.table)
+                    || node.owner.as_deref() != Some(&data_source.schema)
+            });
+            update_child_flags(diagram);
+        }
+        Ok(())
+    }
+
+    /// Rename a table or move it to another schema
+    ///
+    /// This updates the caption of the table, which is also used in the tooltips of
+    /// its relationships. Nothing is changed if the DDS data of a relationship can't
+    /// be updated.
+    pub fn rename_table(&mut self, name: &str, schema: &str, new_name: &str) -> Result<(), Error> {
+        let (index, sch_grid) = self.table_index(name)?;
+        let qualified = format!("{}.{}", schema, new_name);
+        match self.table_index(&qualified) {
+            Ok((other, _)) if other == index => {}
+            Err(Error::UnknownTable(_)) => {}
+            _ => return Err(Error::DuplicateTable(qualified)),
+        }
+        let old_schema = sch_grid.data_source.schema.clone();
+        let old_name = sch_grid.data_source.table.clone();
+        let old_caption = self.form.sites[index].tooltip.clone();
+        let caption = table_caption(schema, new_name);
+
+        // The new tooltips and DDS data of the relationships, before anything changes
+        let mut renamed = Vec::new();
+        for (i, (site, control)) in self.form.sites.iter().zip(&self.controls).enumerate() {
+            if !matches!(control, Control::Polyline(_)) {
+                continue;
+            }
+            let (rest, (fk, from, to)) = match parse_relationship(&site.tooltip) {
+                Ok(names) => names,
+                Err(_) => continue,
+            };
+            if from != old_caption && to != old_caption {
+                continue;
+            }
+            let rename = |c: String| if c == old_caption { caption.clone() } else { c };
+            let tooltip = format!(
+                "Relationship '{}' between '{}' and '{}{}",
+                fk,
+                rename(from),
+                rename(to.clone()),
+                rest
+            );
+            // The DDS data has the schema of the table with the foreign key
+            let mut data = None;
+            if to == old_caption && schema != old_schema {
+                let controls = &self.dds_stream.controls;
+                if let Some(ctrl) = controls.iter().position(|c| c.id1 == site.id) {
+                    data = Some((ctrl, replace_polyline_schema(&controls[ctrl]._a1, schema)?));
+                }
+            }
+            renamed.push((i, tooltip, data));
+        }
+
+        let (_, sch_grid) = self.table_index_mut(name)?;
+        // Keep the size of the caption buffer
+        let frame = &mut sch_grid.frame;
+        let size = 2 * (frame.caption.encode_utf16().count() + 1) + frame._caption_pad.len();
+        let used = 2 * (caption.encode_utf16().count() + 1);
+        frame._caption_pad = vec![0; size.saturating_sub(used)].into();
+        frame.caption = caption.clone();
+        sch_grid.data_source.schema = schema.to_string();
+        sch_grid.data_source.table = new_name.to_string();
+        self.form.sites[index].tooltip = caption;
+
+        let node = table_node(&mut self.dsref_schema_contents, &old_schema, &old_name);
+        if let Some(node) = node {
+            node.name = Some(new_name.to_string());
+            node.owner = Some(schema.to_string());
+        }
+
+        for (i, tooltip, data) in renamed {
+            self.form.sites[i].tooltip = tooltip;
+            if let Some((ctrl, data)) = data {
+                self.dds_stream.controls[ctrl]._a1 = data.into();
+            }
+        }
+        Ok(())
+    }
+
+    /// Move a table and update the lines of its relationships
+    pub fn move_table(&mut self, name: &str, pos: Position) -> Result<(), Error> {
+        let (index, _) = self.table_index(name)?;
+        self.form.sites[index].pos = pos;
+        update_relationships(self);
+        Ok(())
+    }
+
+    /// Resize a table and update the lines of its relationships
+    ///
+    /// The size is also stored in the grid layout of the active [`TableView`].
+    pub fn resize_table(&mut self, name: &str, size: Size) -> Result<(), Error> {
+        let (index, _) = self.table_index(name)?;
+        let id = self.form.sites[index].id;
+        let view = active_table_view(&self.dds_stream, id);
+        let (_, sch_grid) = self.table_index_mut(name)?;
+        sch_grid.extent = size;
+        if let Some(view) = view {
+            sch_grid.frame.layouts[view as usize].size = size;
+        }
+        update_relationships(self);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::SysDiagramFile;
+
+    fn polyline_data(schema: &str, fk: &str) -> Vec<u8> {
+        let mut data = vec![1, 0, 0, 0, 2, 0, 0, 0];
+        write_wstring_nt(&mut data, schema).unwrap();
+        write_wstring_nt(&mut data, fk).unwrap();
+        data
+    }
+
+    #[test]
+    fn replace_schema_keeps_foreign_key() {
+        let data = polyline_data("dbo", "FK_DimCustomer_DimGeography");
+        let new = replace_polyline_schema(&data, "sales").unwrap();
+        assert_eq!(new, polyline_data("sales", "FK_DimCustomer_DimGeography"));
+    }
+
+    #[test]
+    fn replace_schema_in_short_data() {
+        match replace_polyline_schema(&[1, 0, 0, 0], "sales") {
+            Err(Error::PolylineDataTooShort(len)) => assert_eq!(len, 4),
+            other => panic!("expected too short DDS data, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rename_table_with_short_dds_data() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/Geography.sysdiagram");
+        let mut file = SysDiagramFile::open(fs::File::open(path).unwrap()).unwrap();
+        let mut streams = file.streams().unwrap();
+        let id = streams
+            .form
+            .sites
+            .iter()
+            .find(|s| s.tooltip.contains("'FK_DimGeography_DimSalesTerritory'"))
+            .unwrap()
+            .id;
+        for ctrl in &mut streams.dds_stream.controls {
+            if ctrl.id1 == id {
+                ctrl._a1 = vec![1, 0, 0, 0].into();
+            }
+        }
+        let sites = streams.form.sites.clone();
+
+        match streams.rename_table("DimGeography", "geo", "Location") {
+            Err(Error::PolylineDataTooShort(len)) => assert_eq!(len, 4),
+            other => panic!("expected too short DDS data, got {:?}", other),
+        }
+        assert_eq!(streams.form.sites, sites);
+        assert_eq!(streams.table_id("dbo.DimGeography"), Some(1));
+    }
+}
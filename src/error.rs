@@ -1,7 +1,9 @@
 //! IO functions
 use base64::DecodeError as Base64DecodeError;
 use displaydoc::Display;
-use nom::error::{ErrorKind, VerboseError, VerboseErrorKind};
+use nom::error::{
+    ContextError, ErrorKind, FromExternalError, ParseError, VerboseError, VerboseErrorKind,
+};
 use nom::InputLength;
 use std::borrow::Cow;
 use std::io::Error as IoError;
@@ -14,6 +16,18 @@ pub enum Error {
     NotImplemented,
     /// Could not decode base64 value
     Base64(#[from] Base64DecodeError),
+    /// Hex value has an odd number of digits ({0})
+    HexOddLength(usize),
+    /// Invalid hex digit at position {0}
+    HexDigit(usize),
+    /// Line {0}: {1}
+    Record(usize, Box<Error>),
+    /// Expected {0} fields, found {1}
+    FieldCount(usize, usize),
+    /// Invalid integer: {0}
+    InvalidInteger(std::num::ParseIntError),
+    /// Invalid flag {0:?}, expected 1/0, yes/no or true/false
+    InvalidFlag(String),
     /// CFB Error
     Cfb(#[from] IoError),
     /// Stream is too long
@@ -24,6 +38,34 @@ pub enum Error {
     BufTooLong(std::num::TryFromIntError),
     /// Missing a stream with the filename
     MissingStream(&'static str),
+    /// No stream named {0:?}
+    UnknownStream(String),
+    /// The annotated fields of stream {0:?} differ from its bytes at offset {1:#x}
+    AnnotationMismatch(String, usize),
+    /// Form has {0} sites, but there are {1} controls
+    SiteCount(usize, usize),
+    /// Site {0} uses a cached control class, which is not supported
+    CachedControl(i32),
+    /// Unsupported VARIANT type {0:#06x}
+    UnsupportedVarType(u16),
+    /// Unexpected {structure} version {found:?}, expected {expected:?}
+    UnexpectedVersion {
+        structure: &'static str,
+        expected: (u16, u16),
+        found: (u16, u16),
+    },
+    /// Invalid site property mask {0:#010x}
+    InvalidSitePropMask(u32),
+    /// No table named {0:?} in the diagram
+    UnknownTable(String),
+    /// More than one table in the diagram is named {0:?}, use `schema.name`
+    AmbiguousTable(String),
+    /// Table {0:?} is already in the diagram
+    DuplicateTable(String),
+    /// The DdsStream has {0} relationship numbers, which is not a multiple of 5
+    DdsNumberCount(usize),
+    /// The DDS data of a relationship has {0} bytes, too few to hold its schema
+    PolylineDataTooShort(usize),
     /// Parsing incomplete
     Incomplete,
     /// Nom parsing error: {0:?} at -{1}
@@ -36,6 +78,14 @@ pub enum Error {
     ParseFailureVerbose(Vec<(VerboseErrorKind, usize)>),
     /// String encoding error: {0:?}
     StringEncoding(String),
+    /// XML error: {0}
+    Xml(#[from] roxmltree::Error),
+    /// Missing XML element <{0}>
+    XmlMissingElement(&'static str),
+    /// Missing XML attribute {0}
+    XmlMissingAttribute(&'static str),
+    /// Invalid value {1:?} for XML attribute {0}
+    XmlAttribute(&'static str, String),
 }
 
 /// Result when loading a sysdiagram
@@ -81,6 +131,46 @@ impl<I: InputLength> From<nom::error::Error<I>> for Error {
     }
 }
 
+impl From<nom::Err<Error>> for Error {
+    fn from(e: nom::Err<Error>) -> Error {
+        match e {
+            nom::Err::Incomplete(_) => Error::Incomplete,
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        }
+    }
+}
+
+/// Allows parsers to report an [`Error`] with the details of what was wrong
+impl<I: InputLength> ParseError<I> for Error {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        Error::ParseError(kind, input.input_len())
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> ContextError<I> for Error {}
+
+impl<I> FromExternalError<I, Error> for Error {
+    fn from_external_error(_input: I, _kind: ErrorKind, e: Error) -> Self {
+        e
+    }
+}
+
+impl<I> FromExternalError<I, Cow<'static, str>> for Error {
+    fn from_external_error(_input: I, _kind: ErrorKind, e: Cow<'static, str>) -> Self {
+        Error::from(e)
+    }
+}
+
+impl<I: InputLength> FromExternalError<I, u32> for Error {
+    fn from_external_error(input: I, kind: ErrorKind, _e: u32) -> Self {
+        Error::ParseFailure(kind, input.input_len())
+    }
+}
+
 impl From<Cow<'_, str>> for Error {
     fn from(e: Cow<'_, str>) -> Self {
         Error::StringEncoding(String::from(e))
@@ -0,0 +1,485 @@
+//! # The `f` stream
+//!
+//! The `f` stream holds the [\[MS-OFORMS\]] `FormControl` of the DDS form. It is read
+//! through [`ms_oforms`], which can't write it back, so this module keeps a lossless
+//! copy of the parts that change when a diagram is edited: the list of [`OleSite`]s.
+//! Everything before the site data (form properties, font and the site class table)
+//! and anything after it is kept as-is, except for the `NextAvailableID` that is
+//! updated when a site is added.
+//!
+//! Only the site properties that appear in sysdiagrams are supported, i.e. `Name`, `ID`,
+//! `BitFlags`, `ObjectStreamSize`, `ClsidCacheIndex`, `Position` and `ControlTipText`.
+//!
+//! [\[MS-OFORMS\]]: https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-oforms
+
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+use bstr::BString;
+use ms_oforms::{common::parse_guid, properties::Position};
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::{cond, eof, map, recognize, rest},
+    error::{make_error, ErrorKind, ParseError},
+    multi::length_data,
+    number::complete::{le_i32, le_u16, le_u32, le_u8},
+    sequence::tuple,
+    IResult,
+};
+use uuid::{uuid, Uuid};
+
+use crate::{
+    hexdump::Layout,
+    parser::decode_utf16,
+    writer::{write_i32, write_position, write_u16, write_u32, write_u8},
+    Error, SiteInfo,
+};
+
+/// `StdFont` (ProgID `StdFont`)
+pub const CLSID_STDFONT: Uuid = uuid!("0be35203-8f91-11ce-9de3-00aa004bb851");
+
+bitflags::bitflags! {
+    /// Properties present in an `OleSiteConcreteControl`
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct SitePropMask: u32 {
+        const NAME = 0x0001;
+        const ID = 0x0004;
+        const BIT_FLAGS = 0x0010;
+        const OBJECT_STREAM_SIZE = 0x0020;
+        const CLSID_CACHE_INDEX = 0x0080;
+        const POSITION = 0x0100;
+        const CONTROL_TIP_TEXT = 0x0800;
+    }
+}
+
+const FORM_PROP_BACK_COLOR: u32 = 0x0000_0002;
+const FORM_PROP_FORE_COLOR: u32 = 0x0000_0004;
+const FORM_PROP_NEXT_AVAILABLE_ID: u32 = 0x0000_0008;
+const FORM_PROP_BOOLEAN_PROPERTIES: u32 = 0x0000_0040;
+const FORM_PROP_MOUSE_ICON: u32 = 0x0000_8000;
+const FORM_PROP_FONT: u32 = 0x0010_0000;
+const FORM_PROP_PICTURE: u32 = 0x0020_0000;
+const FORM_FLAG_DONTSAVECLASSTABLE: u32 = 0x0000_8000;
+
+const SITE_TYPE_OLE_SITE_CONCRETE: u8 = 0x01;
+
+/// The parsed `f` stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormStream {
+    /// `FormControl` properties, stream data and site class table
+    pub(crate) header: BString,
+    pub sites: Vec<OleSite>,
+    /// `DesignExData` and anything else after the sites
+    pub(crate) trailer: BString,
+}
+
+/// An `OleSiteConcreteControl`, i.e. one control on the form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OleSite {
+    /// Nesting depth of the site
+    pub depth: u8,
+    /// The properties present on the site ([`SitePropMask::POSITION`] is required)
+    pub mask: SitePropMask,
+    /// `SchGrid` for tables, `Control` otherwise
+    pub name: String,
+    pub id: i32,
+    pub bit_flags: u32,
+    /// Length of the persisted control in the `o` stream
+    pub object_stream_size: u32,
+    /// Index into the site class table (high bit set)
+    pub clsid_cache_index: u16,
+    pub pos: Position,
+    pub tooltip: String,
+    pub(crate) _name_pad: BString,
+    pub(crate) _tooltip_pad: BString,
+}
+
+impl FormStream {
+    /// Offset of the `NextAvailableID` property in the header
+    fn next_available_id_offset(&self) -> Option<usize> {
+        let mask = self.header.get(4..8)?;
+        let prop_mask = u32::from_le_bytes([mask[0], mask[1], mask[2], mask[3]]);
+        if prop_mask & FORM_PROP_NEXT_AVAILABLE_ID == 0 {
+            return None;
+        }
+        let offset = 4 * [FORM_PROP_BACK_COLOR, FORM_PROP_FORE_COLOR]
+            .iter()
+            .filter(|&&bit| prop_mask & bit != 0)
+            .count();
+        Some(8 + offset).filter(|o| o + 4 <= self.header.len())
+    }
+
+    /// The `NextAvailableID` of the form
+    ///
+    /// Despite the name, SSMS stores the highest ID that is in use.
+    pub fn next_available_id(&self) -> Option<u32> {
+        let offset = self.next_available_id_offset()?;
+        let bytes = &self.header[offset..offset + 4];
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Update the `NextAvailableID`, if the header has that property
+    pub(crate) fn set_next_available_id(&mut self, id: u32) {
+        if let Some(offset) = self.next_available_id_offset() {
+            self.header[offset..offset + 4].copy_from_slice(&id.to_le_bytes());
+        }
+    }
+}
+
+impl OleSite {
+    /// Get the [`SiteInfo`] as returned by [`crate::SysDiagramFile::schema_form`]
+    pub fn site_info(&self) -> SiteInfo {
+        SiteInfo {
+            id: self.id,
+            depth: self.depth,
+            pos: self.pos,
+            tooltip: self.tooltip.clone(),
+        }
+    }
+
+    /// Index of the class of this site in the site class table
+    pub fn class_index(&self) -> u16 {
+        self.clsid_cache_index & 0x7FFF
+    }
+}
+
+fn padding(offset: usize, align: usize) -> usize {
+    (align - offset % align) % align
+}
+
+fn align<'a, E: ParseError<&'a [u8]>>(
+    start: &'a [u8],
+    input: &'a [u8],
+    n: usize,
+) -> IResult<&'a [u8], &'a [u8], E> {
+    take(padding(start.len() - input.len(), n))(input)
+}
+
+fn parse_guid_and_picture(input: &[u8]) -> IResult<&[u8], &[u8], Error> {
+    recognize(tuple((
+        parse_guid,
+        tag(0x0000_746Cu32.to_le_bytes()),
+        length_data(le_u32),
+    )))(input)
+}
+
+fn parse_guid_and_font(input: &[u8]) -> IResult<&[u8], &[u8], Error> {
+    let (rest, clsid) = parse_guid(input)?;
+    let (rest, _) = if clsid == CLSID_STDFONT {
+        recognize(tuple((
+            le_u8,
+            le_u16,
+            le_u8,
+            le_u16,
+            le_u32,
+            length_data(le_u8),
+        )))(rest)?
+    } else {
+        // `TextProps` and the DDS font: version and a length-prefixed data block
+        recognize(tuple((le_u16, length_data(le_u16))))(rest)?
+    };
+    let len = input.len() - rest.len();
+    Ok((rest, &input[..len]))
+}
+
+fn parse_form_header(input: &[u8]) -> IResult<&[u8], BString, Error> {
+    let start = input;
+    let (input, (_version, cb_form)) = tuple((le_u16, le_u16))(input)?;
+    let (_, prop_mask) = le_u32(input)?;
+    let (input, data) = take(usize::from(cb_form))(input)?;
+
+    let offset = 4 * [
+        FORM_PROP_BACK_COLOR,
+        FORM_PROP_FORE_COLOR,
+        FORM_PROP_NEXT_AVAILABLE_ID,
+    ]
+    .iter()
+    .filter(|&&bit| prop_mask & bit != 0)
+    .count();
+    let boolean_properties = if prop_mask & FORM_PROP_BOOLEAN_PROPERTIES != 0 {
+        let (_, value) = le_u32(&data[(4 + offset).min(data.len())..])?;
+        value
+    } else {
+        0
+    };
+
+    let (input, _) = cond(
+        prop_mask & FORM_PROP_MOUSE_ICON != 0,
+        parse_guid_and_picture,
+    )(input)?;
+    let (input, _) = cond(prop_mask & FORM_PROP_FONT != 0, parse_guid_and_font)(input)?;
+    let (input, _) = cond(prop_mask & FORM_PROP_PICTURE != 0, parse_guid_and_picture)(input)?;
+
+    let mut input = input;
+    if boolean_properties & FORM_FLAG_DONTSAVECLASSTABLE == 0 {
+        let (rest, count) = le_u16(input)?;
+        input = rest;
+        for _ in 0..count {
+            let (rest, _site_class_info) = recognize(tuple((le_u16, length_data(le_u16))))(input)?;
+            input = rest;
+        }
+    }
+    let len = start.len() - input.len();
+    Ok((input, BString::from(&start[..len])))
+}
+
+fn parse_site_depths(input: &[u8], count: usize) -> IResult<&[u8], Vec<u8>, Error> {
+    let mut depths = Vec::with_capacity(count);
+    let mut input = input;
+    while depths.len() < count {
+        let (rest, (depth, type_or_count)) = tuple((le_u8, le_u8))(input)?;
+        let (rest, (site_type, n)) = if type_or_count & 0x80 != 0 {
+            let (rest, site_type) = le_u8(rest)?;
+            (rest, (site_type, usize::from(type_or_count & 0x7F)))
+        } else {
+            (rest, (type_or_count, 1))
+        };
+        if site_type != SITE_TYPE_OLE_SITE_CONCRETE {
+            return Err(nom::Err::Error(make_error(input, ErrorKind::Verify)));
+        }
+        depths.extend(std::iter::repeat(depth).take(n));
+        input = rest;
+    }
+    Ok((input, depths))
+}
+
+fn decode_site_string(bytes: &[u8], compressed: bool) -> Option<String> {
+    if compressed {
+        Some(bytes.iter().map(|&b| char::from(b)).collect())
+    } else {
+        decode_utf16(bytes)
+    }
+}
+
+fn parse_site_string<'a>(
+    start: &'a [u8],
+    input: &'a [u8],
+    flagged_len: u32,
+) -> IResult<&'a [u8], (String, BString), Error> {
+    let len = (flagged_len & 0x7FFF_FFFF) as usize;
+    let compressed = flagged_len & 0x8000_0000 != 0;
+    let (input, bytes) = take(len)(input)?;
+    let (input, pad) = align(start, input, 4)?;
+    let string = decode_site_string(bytes, compressed)
+        .ok_or_else(|| nom::Err::Error(make_error(bytes, ErrorKind::MapOpt)))?;
+    Ok((input, (string, BString::from(pad))))
+}
+
+fn parse_ole_site(depth: u8) -> impl Fn(&[u8]) -> IResult<&[u8], OleSite, Error> {
+    move |input: &[u8]| {
+        let (input, _version) = tag([0x00, 0x00])(input)?;
+        let (input, data) = length_data(le_u16)(input)?;
+        let (data, bits) = le_u32(data)?;
+        let mask = SitePropMask::from_bits(bits)
+            .filter(|m| m.contains(SitePropMask::POSITION))
+            .ok_or(nom::Err::Failure(Error::InvalidSitePropMask(bits)))?;
+        let start = data;
+        let has = |bit| mask.contains(bit);
+
+        let (data, name_len) = cond(has(SitePropMask::NAME), le_u32)(data)?;
+        let (data, id) = cond(has(SitePropMask::ID), le_i32)(data)?;
+        let (data, bit_flags) = cond(has(SitePropMask::BIT_FLAGS), le_u32)(data)?;
+        let (data, object_stream_size) = cond(has(SitePropMask::OBJECT_STREAM_SIZE), le_u32)(data)?;
+        let (data, clsid_cache_index) = cond(has(SitePropMask::CLSID_CACHE_INDEX), le_u16)(data)?;
+        let (data, tooltip_len) = if has(SitePropMask::CONTROL_TIP_TEXT) {
+            let (data, _) = align(start, data, 4)?;
+            map(le_u32, Some)(data)?
+        } else {
+            (data, None)
+        };
+        let (data, _) = align(start, data, 4)?;
+
+        let (data, (name, _name_pad)) = match name_len {
+            Some(len) => parse_site_string(start, data, len)?,
+            None => (data, (String::new(), BString::default())),
+        };
+        let (data, pos) = Position::parse(data)?;
+        let (data, (tooltip, _tooltip_pad)) = match tooltip_len {
+            Some(len) => parse_site_string(start, data, len)?,
+            None => (data, (String::new(), BString::default())),
+        };
+        let (_, _) = eof(data)?;
+
+        Ok((
+            input,
+            OleSite {
+                depth,
+                mask,
+                name,
+                id: id.unwrap_or_default(),
+                bit_flags: bit_flags.unwrap_or_default(),
+                object_stream_size: object_stream_size.unwrap_or_default(),
+                clsid_cache_index: clsid_cache_index.unwrap_or_default(),
+                pos,
+                tooltip,
+                _name_pad,
+                _tooltip_pad,
+            },
+        ))
+    }
+}
+
+/// Parse the `f` stream
+pub fn parse_form_stream(input: &[u8]) -> IResult<&[u8], FormStream, Error> {
+    let (input, header) = parse_form_header(input)?;
+    let (input, site_count) = le_u32(input)?;
+    let (input, site_data) = length_data(le_u32)(input)?;
+
+    let start = site_data;
+    let (data, depths) = parse_site_depths(site_data, site_count as usize)?;
+    let (mut data, _) = align(start, data, 4)?;
+    let mut sites = Vec::with_capacity(depths.len());
+    for depth in depths {
+        let (rest, site) = parse_ole_site(depth)(data)?;
+        sites.push(site);
+        data = rest;
+    }
+    let (_, _) = eof(data)?;
+
+    let (input, trailer) = map(rest, BString::from)(input)?;
+    Ok((
+        input,
+        FormStream {
+            header,
+            sites,
+            trailer,
+        },
+    ))
+}
+
+fn write_site_depths<W: Write>(w: &mut W, sites: &[OleSite]) -> io::Result<()> {
+    let mut i = 0;
+    while i < sites.len() {
+        let depth = sites[i].depth;
+        let run = sites[i..]
+            .iter()
+            .take(0x7F)
+            .take_while(|s| s.depth == depth)
+            .count();
+        write_u8(w, depth)?;
+        if run > 1 {
+            write_u8(w, 0x80 | run as u8)?;
+        }
+        write_u8(w, SITE_TYPE_OLE_SITE_CONCRETE)?;
+        i += run;
+    }
+    Ok(())
+}
+
+/// Encode a string as `CountOfBytesWithCompressionFlag` and the bytes
+///
+/// The count has 31 bits, the highest bit is the compression flag.
+fn encode_site_string(s: &str) -> Result<(u32, Vec<u8>), Error> {
+    if s.chars().all(|c| u32::from(c) <= 0xFF) {
+        let bytes: Vec<u8> = s.chars().map(|c| c as u8).collect();
+        let count = i32::try_from(bytes.len()).map_err(Error::BufTooLong)? as u32;
+        Ok((count | 0x8000_0000, bytes))
+    } else {
+        let bytes: Vec<u8> = s.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let count = i32::try_from(bytes.len()).map_err(Error::BufTooLong)? as u32;
+        Ok((count, bytes))
+    }
+}
+
+/// Write the string bytes and pad to a multiple of 4, reusing the old padding where possible
+fn write_site_string(buf: &mut Vec<u8>, start: usize, bytes: &[u8], pad: &[u8]) {
+    buf.extend_from_slice(bytes);
+    let n = padding(buf.len() - start, 4);
+    if pad.len() == n {
+        buf.extend_from_slice(pad);
+    } else {
+        buf.resize(buf.len() + n, 0);
+    }
+}
+
+fn write_ole_site<W: Write>(w: &mut W, site: &OleSite) -> Result<(), Error> {
+    let has = |bit| site.mask.contains(bit);
+    let (name_len, name) = encode_site_string(&site.name)?;
+    let (tooltip_len, tooltip) = encode_site_string(&site.tooltip)?;
+
+    let mut buf = Vec::new();
+    if has(SitePropMask::NAME) {
+        write_u32(&mut buf, name_len)?;
+    }
+    if has(SitePropMask::ID) {
+        write_i32(&mut buf, site.id)?;
+    }
+    if has(SitePropMask::BIT_FLAGS) {
+        write_u32(&mut buf, site.bit_flags)?;
+    }
+    if has(SitePropMask::OBJECT_STREAM_SIZE) {
+        write_u32(&mut buf, site.object_stream_size)?;
+    }
+    if has(SitePropMask::CLSID_CACHE_INDEX) {
+        write_u16(&mut buf, site.clsid_cache_index)?;
+    }
+    if has(SitePropMask::CONTROL_TIP_TEXT) {
+        buf.resize(buf.len() + padding(buf.len(), 4), 0);
+        write_u32(&mut buf, tooltip_len)?;
+    }
+    buf.resize(buf.len() + padding(buf.len(), 4), 0);
+
+    if has(SitePropMask::NAME) {
+        write_site_string(&mut buf, 0, &name, &site._name_pad);
+    }
+    write_position(&mut buf, &site.pos)?;
+    if has(SitePropMask::CONTROL_TIP_TEXT) {
+        write_site_string(&mut buf, 0, &tooltip, &site._tooltip_pad);
+    }
+
+    let len = u16::try_from(buf.len() + 4).map_err(Error::BufTooLong)?;
+    write_u16(w, 0)?; // version
+    write_u16(w, len)?;
+    write_u32(w, site.mask.bits())?;
+    w.write_all(&buf)?;
+    Ok(())
+}
+
+/// Inverse of [`parse_form_stream`]
+pub fn write_form_stream<W: Write>(w: &mut W, form: &FormStream) -> Result<(), Error> {
+    w.write_all(&form.header)?;
+
+    let mut buf = Vec::new();
+    write_site_depths(&mut buf, &form.sites)?;
+    buf.resize(buf.len() + padding(buf.len(), 4), 0);
+    for site in &form.sites {
+        write_ole_site(&mut buf, site)?;
+    }
+    let count = u32::try_from(form.sites.len()).map_err(Error::BufTooLong)?;
+    let len = u32::try_from(buf.len()).map_err(Error::BufTooLong)?;
+    write_u32(w, count)?;
+    write_u32(w, len)?;
+    w.write_all(&buf)?;
+
+    w.write_all(&form.trailer)?;
+    Ok(())
+}
+
+/// Annotated version of [`write_form_stream`]
+///
+/// The header is parsed by [`ms_oforms`] and each site is shown as a whole.
+pub(crate) fn annotate_form_stream(l: &mut Layout, form: &FormStream) -> Result<(), Error> {
+    let mut sites = Vec::with_capacity(form.sites.len());
+    for site in &form.sites {
+        let mut buf = Vec::new();
+        write_ole_site(&mut buf, site)?;
+        sites.push((site.id, buf));
+    }
+    l.known("header", |w| w.write_all(&form.header))?;
+    let count = u32::try_from(form.sites.len()).map_err(Error::BufTooLong)?;
+    l.known("sites.len", |w| write_u32(w, count))?;
+    l.length_value("sites.size", |l| {
+        let start = l.bytes().len();
+        l.known("depths", |w| write_site_depths(w, &form.sites))?;
+        let n = padding(l.bytes().len() - start, 4);
+        l.known("padding", |w| w.write_all(&[0; 4][..n]))?;
+        for (id, site) in &sites {
+            l.known(&format!("site {}", id), |w| w.write_all(site))?;
+        }
+        Ok(())
+    })?;
+    l.unknown("trailer", |w| w.write_all(&form.trailer))?;
+    Ok(())
+}
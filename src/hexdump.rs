@@ -0,0 +1,197 @@
+//! # Annotated hexdumps
+//!
+//! The [`Field`]s of a stream are found by writing the parsed structures again
+//! and recording the range of every write. Since the writers reproduce the
+//! streams byte for byte, the ranges match the original bytes, which
+//! [`annotated_stream`](crate::SysDiagramFile::annotated_stream) checks.
+
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+    ops::Range,
+};
+
+use crate::Error;
+
+/// A range of bytes in a stream and the field it was parsed into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub range: Range<usize>,
+    /// Path of the field, e.g. `site 3.labels[0].pos`
+    pub name: String,
+    /// Whether the meaning of the bytes is understood
+    pub known: bool,
+}
+
+/// Records the [`Field`]s while writing a structure
+#[derive(Debug, Default)]
+pub(crate) struct Layout {
+    buf: Vec<u8>,
+    fields: Vec<Field>,
+    path: Vec<String>,
+}
+
+impl Layout {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&mut self, name: &str, start: usize, known: bool) {
+        let mut path = self.path.join(".");
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(name);
+        self.fields.push(Field {
+            range: start..self.buf.len(),
+            name: path,
+            known,
+        });
+    }
+
+    /// Write a field that is understood
+    pub(crate) fn known<F>(&mut self, name: &str, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Vec<u8>) -> io::Result<()>,
+    {
+        let start = self.buf.len();
+        f(&mut self.buf)?;
+        self.push(name, start, true);
+        Ok(())
+    }
+
+    /// Write a field that is not (yet) understood
+    pub(crate) fn unknown<F>(&mut self, name: &str, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Vec<u8>) -> io::Result<()>,
+    {
+        let start = self.buf.len();
+        f(&mut self.buf)?;
+        self.push(name, start, false);
+        Ok(())
+    }
+
+    /// Prefix the names of all fields written in `f` with `name`
+    pub(crate) fn group<F>(&mut self, name: &str, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        self.path.push(name.to_string());
+        let result = f(self);
+        self.path.pop();
+        result
+    }
+
+    /// Write a `u32` length prefix followed by the fields written in `f`
+    ///
+    /// Mirrors [`crate::writer::write_length_value`]
+    pub(crate) fn length_value<F>(&mut self, name: &str, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        let start = self.buf.len();
+        self.buf.extend_from_slice(&[0; 4]);
+        self.push(name, start, true);
+        f(self)?;
+        let len = u32::try_from(self.buf.len() - start - 4).map_err(Error::BufTooLong)?;
+        self.buf[start..start + 4].copy_from_slice(&len.to_le_bytes());
+        Ok(())
+    }
+
+    /// The bytes written so far
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub(crate) fn into_fields(self) -> Vec<Field> {
+        self.fields
+    }
+}
+
+const BYTES_PER_LINE: usize = 16;
+
+fn write_line<W: Write>(
+    w: &mut W,
+    offset: usize,
+    bytes: &[u8],
+    known: bool,
+    color: bool,
+    name: &str,
+) -> io::Result<()> {
+    write!(w, "{:08x}  ", offset)?;
+    if color && !known {
+        write!(w, "\x1b[33m")?;
+    }
+    for b in bytes {
+        write!(w, "{:02x} ", b)?;
+    }
+    if color && !known {
+        write!(w, "\x1b[0m")?;
+    }
+    for _ in bytes.len()..BYTES_PER_LINE {
+        write!(w, "   ")?;
+    }
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+        .collect();
+    let marker = if known { ' ' } else { '?' };
+    writeln!(w, " |{:16}| {} {}", ascii, marker, name)
+}
+
+/// Write a hexdump of `bytes` with one line (or more) per field
+///
+/// Unknown fields and bytes that are not covered by any field are marked with `?`,
+/// and shown in yellow if `color` is set.
+pub fn write_hexdump<W: Write>(
+    w: &mut W,
+    bytes: &[u8],
+    fields: &[Field],
+    color: bool,
+) -> io::Result<()> {
+    let mut offset = 0;
+    let unparsed = Field {
+        range: 0..0,
+        name: String::from("(unparsed)"),
+        known: false,
+    };
+    let mut fields = fields.iter().peekable();
+    while offset < bytes.len() {
+        // Bytes before the next field, or after the last one, are unparsed
+        let field = match fields.peek() {
+            Some(field) if field.range.start <= offset => fields.next().unwrap(),
+            Some(field) => {
+                let end = field.range.start.min(bytes.len());
+                write_range(w, bytes, offset..end, &unparsed, color)?;
+                offset = end;
+                continue;
+            }
+            None => {
+                write_range(w, bytes, offset..bytes.len(), &unparsed, color)?;
+                break;
+            }
+        };
+        let end = field.range.end.min(bytes.len());
+        if end > offset {
+            write_range(w, bytes, offset..end, field, color)?;
+            offset = end;
+        }
+    }
+    Ok(())
+}
+
+fn write_range<W: Write>(
+    w: &mut W,
+    bytes: &[u8],
+    range: Range<usize>,
+    field: &Field,
+    color: bool,
+) -> io::Result<()> {
+    let mut name = field.name.as_str();
+    for (i, chunk) in bytes[range.clone()].chunks(BYTES_PER_LINE).enumerate() {
+        let offset = range.start + i * BYTES_PER_LINE;
+        write_line(w, offset, chunk, field.known, color, name)?;
+        name = "";
+    }
+    Ok(())
+}
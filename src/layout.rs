@@ -0,0 +1,939 @@
+//! # Automatic layout
+//!
+//! Diagrams that are created by a [`SysDiagramBuilder`](crate::SysDiagramBuilder) or
+//! reorganized after schema changes need positions for the tables and points for the
+//! relationship lines.
+//!
+//! - [`layout_tables`] places the tables, either in layers along the foreign keys
+//!   ([`LayoutMode::Layered`], a Sugiyama-style layout) or in a grid ([`LayoutMode::Grid`]).
+//! - [`route`] draws a rectilinear line between the edges of two tables that goes
+//!   around all other tables, like the `MSDDS.Rectilinear` line router that the
+//!   [DDS XML](crate::dds::xml) refers to.
+//!
+//! [`apply_layout`] and [`route_relationships`] do the same for the sites of a
+//! [`SysDiagramStreams`]. When tables were moved, [`update_relationships`] fixes
+//! the lines with [`reroute`], keeping the bend points of the existing lines where
+//! they are still valid. All coordinates are HIMETRIC.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+use ms_oforms::properties::{Position, Size};
+
+use crate::{form::OleSite, parse_relationship, Control, SysDiagramStreams};
+
+/// A rectangle on the diagram surface
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    /// The rectangle of a control at `pos` with `size`
+    pub fn new(pos: &Position, size: &Size) -> Self {
+        Self {
+            left: pos.left,
+            top: pos.top,
+            right: pos.left + size.width as i32,
+            bottom: pos.top + size.height as i32,
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        ((self.left + self.right) / 2, (self.top + self.bottom) / 2)
+    }
+
+    /// Whether the rectangles share more than an edge
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.left < other.right
+            && other.left < self.right
+            && self.top < other.bottom
+            && other.top < self.bottom
+    }
+
+    fn inflate(&self, margin: i32) -> Self {
+        Self {
+            left: self.left - margin,
+            top: self.top - margin,
+            right: self.right + margin,
+            bottom: self.bottom + margin,
+        }
+    }
+}
+
+/// How [`layout_tables`] arranges the tables
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LayoutMode {
+    /// Referenced tables above the tables that reference them
+    Layered,
+    /// Rows of tables in the given order, `columns` per row (default: a square)
+    Grid { columns: Option<usize> },
+}
+
+/// Options for [`layout_tables`]
+#[derive(Debug, Clone)]
+pub struct LayoutOptions {
+    pub mode: LayoutMode,
+    /// Top-left corner of the first table
+    pub origin: Position,
+    /// Horizontal space between two tables
+    pub column_spacing: u32,
+    /// Vertical space between two layers or rows
+    pub row_spacing: u32,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            mode: LayoutMode::Layered,
+            origin: Position {
+                left: 1000,
+                top: 1000,
+            },
+            column_spacing: 2500,
+            row_spacing: 2500,
+        }
+    }
+}
+
+/// Space kept between a line and the tables it passes
+const ROUTE_MARGIN: i32 = 300;
+/// Length that a bend in a line is worth, to prefer straight lines
+const BEND_COST: i64 = 2000;
+/// Sweeps of the barycenter heuristic when ordering a layer
+const ORDER_SWEEPS: usize = 4;
+
+/// Distance of a label from its line
+const LABEL_OFFSET: i32 = 175;
+/// Offset of the site of a polyline from the top-left of its points
+const POLYLINE_SITE_OFFSET: (i32, i32) = (300, 407);
+
+fn point(left: i32, top: i32) -> Position {
+    Position { left, top }
+}
+
+/// Place the tables of the given `sizes`, returning the top-left corner of each
+///
+/// An edge `(from, to)` is a relationship from the referenced table `from` to the
+/// table `to` with the foreign key, as in [`crate::Relationship`].
+pub fn layout_tables(
+    sizes: &[Size],
+    edges: &[(usize, usize)],
+    options: &LayoutOptions,
+) -> Vec<Position> {
+    match options.mode {
+        LayoutMode::Layered => layered(sizes, edges, options),
+        LayoutMode::Grid { columns } => {
+            let columns = columns.unwrap_or_else(|| (sizes.len() as f64).sqrt().ceil() as usize);
+            grid(sizes, columns.max(1), options)
+        }
+    }
+}
+
+fn grid(sizes: &[Size], columns: usize, options: &LayoutOptions) -> Vec<Position> {
+    let mut widths = vec![0; columns];
+    let mut heights = vec![0; sizes.len().div_ceil(columns)];
+    for (i, size) in sizes.iter().enumerate() {
+        widths[i % columns] = widths[i % columns].max(size.width);
+        heights[i / columns] = heights[i / columns].max(size.height);
+    }
+    let offsets = |extents: &[u32], spacing: u32, start: i32| {
+        let mut next = start;
+        let mut offsets = Vec::with_capacity(extents.len());
+        for extent in extents {
+            offsets.push(next);
+            next += (extent + spacing) as i32;
+        }
+        offsets
+    };
+    let xs = offsets(&widths, options.column_spacing, options.origin.left);
+    let ys = offsets(&heights, options.row_spacing, options.origin.top);
+    (0..sizes.len())
+        .map(|i| point(xs[i % columns], ys[i / columns]))
+        .collect()
+}
+
+/// Keep the edges of a depth-first search that don't close a cycle
+fn acyclic_children(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<usize>> {
+    let mut children = vec![Vec::new(); n];
+    for &(from, to) in edges {
+        if from != to && from < n && to < n && !children[from].contains(&to) {
+            children[from].push(to);
+        }
+    }
+    const NEW: u8 = 0;
+    const ACTIVE: u8 = 1;
+    const DONE: u8 = 2;
+    let mut state = vec![NEW; n];
+    let mut acyclic = vec![Vec::new(); n];
+    for root in 0..n {
+        if state[root] != NEW {
+            continue;
+        }
+        state[root] = ACTIVE;
+        let mut stack = vec![(root, 0)];
+        while let Some(&(v, i)) = stack.last() {
+            match children[v].get(i) {
+                Some(&c) => {
+                    let top = stack.len() - 1;
+                    stack[top].1 += 1;
+                    match state[c] {
+                        NEW => {
+                            acyclic[v].push(c);
+                            state[c] = ACTIVE;
+                            stack.push((c, 0));
+                        }
+                        ACTIVE => {} // back edge
+                        _ => acyclic[v].push(c),
+                    }
+                }
+                None => {
+                    state[v] = DONE;
+                    stack.pop();
+                }
+            }
+        }
+    }
+    acyclic
+}
+
+/// Longest path layering, every table is one layer below the tables it references
+fn longest_path_layers(children: &[Vec<usize>]) -> Vec<usize> {
+    let n = children.len();
+    let mut in_degree = vec![0; n];
+    for c in children.iter().flatten() {
+        in_degree[*c] += 1;
+    }
+    let mut queue: Vec<usize> = (0..n).filter(|&v| in_degree[v] == 0).rev().collect();
+    let mut layer = vec![0; n];
+    while let Some(v) = queue.pop() {
+        for &c in &children[v] {
+            layer[c] = layer[c].max(layer[v] + 1);
+            in_degree[c] -= 1;
+            if in_degree[c] == 0 {
+                queue.push(c);
+            }
+        }
+    }
+    layer
+}
+
+fn barycenter(neighbors: &[usize], index: &[usize], current: usize) -> f64 {
+    if neighbors.is_empty() {
+        current as f64
+    } else {
+        neighbors.iter().map(|&v| index[v] as f64).sum::<f64>() / neighbors.len() as f64
+    }
+}
+
+/// Sort a layer by the mean index of the neighbors of each node
+fn order_layer(layer: &mut [usize], neighbors: &[Vec<usize>], index: &mut [usize]) {
+    let mut keyed: Vec<(f64, usize)> = layer
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| (barycenter(&neighbors[v], index, i), v))
+        .collect();
+    keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    for (i, (_, v)) in keyed.into_iter().enumerate() {
+        layer[i] = v;
+        index[v] = i;
+    }
+}
+
+fn layered(sizes: &[Size], edges: &[(usize, usize)], options: &LayoutOptions) -> Vec<Position> {
+    let n = sizes.len();
+    let children = acyclic_children(n, edges);
+    let mut layer_of = longest_path_layers(&children);
+
+    // Split edges that span several layers with dummy nodes, which have no size
+    // but keep a gap for the line in each layer they pass.
+    let mut upper: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut lower: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for (from, tos) in children.iter().enumerate() {
+        for &to in tos {
+            let mut prev = from;
+            for layer in layer_of[from] + 1..layer_of[to] {
+                let dummy = layer_of.len();
+                layer_of.push(layer);
+                upper.push(vec![prev]);
+                lower.push(Vec::new());
+                lower[prev].push(dummy);
+                prev = dummy;
+            }
+            upper[to].push(prev);
+            lower[prev].push(to);
+        }
+    }
+
+    let layer_count = layer_of.iter().max().map_or(0, |l| l + 1);
+    let mut layers = vec![Vec::new(); layer_count];
+    let mut index = vec![0; layer_of.len()];
+    for (v, &layer) in layer_of.iter().enumerate() {
+        index[v] = layers[layer].len();
+        layers[layer].push(v);
+    }
+    for _ in 0..ORDER_SWEEPS {
+        for layer in layers.iter_mut().skip(1) {
+            order_layer(layer, &upper, &mut index);
+        }
+        for layer in layers.iter_mut().rev().skip(1) {
+            order_layer(layer, &lower, &mut index);
+        }
+    }
+
+    let size = |v: usize| sizes.get(v).map_or((0, 0), |s| (s.width, s.height));
+    let spacing = options.column_spacing;
+    let layer_widths: Vec<u32> = layers
+        .iter()
+        .map(|layer| {
+            let widths: u32 = layer.iter().map(|&v| size(v).0).sum();
+            widths + spacing * (layer.len() as u32).saturating_sub(1)
+        })
+        .collect();
+    let max_width = layer_widths.iter().copied().max().unwrap_or(0);
+
+    let mut positions = vec![options.origin; n];
+    let mut top = options.origin.top;
+    for (layer, width) in layers.iter().zip(layer_widths) {
+        let mut left = options.origin.left + ((max_width - width) / 2) as i32;
+        let mut height = 0;
+        for &v in layer {
+            let (w, h) = size(v);
+            if v < n {
+                positions[v] = point(left, top);
+            }
+            left += (w + spacing) as i32;
+            height = height.max(h);
+        }
+        top += (height + options.row_spacing) as i32;
+    }
+    positions
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Side {
+    Left,
+    Top,
+    Right,
+    Bottom,
+}
+
+// Directions of travel, in the order of `STEPS`
+const RIGHT: usize = 0;
+const LEFT: usize = 1;
+const DOWN: usize = 2;
+const UP: usize = 3;
+const STEPS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+fn opposite(dir: usize) -> usize {
+    dir ^ 1
+}
+
+impl Side {
+    fn outward(self) -> usize {
+        match self {
+            Side::Left => LEFT,
+            Side::Top => UP,
+            Side::Right => RIGHT,
+            Side::Bottom => DOWN,
+        }
+    }
+
+    /// The side of a table that a line leaves from `a` toward `b`
+    fn toward(a: &Position, b: &Position) -> Option<Self> {
+        match (b.left.cmp(&a.left), b.top.cmp(&a.top)) {
+            (Ordering::Greater, Ordering::Equal) => Some(Side::Right),
+            (Ordering::Less, Ordering::Equal) => Some(Side::Left),
+            (Ordering::Equal, Ordering::Greater) => Some(Side::Bottom),
+            (Ordering::Equal, Ordering::Less) => Some(Side::Top),
+            _ => None,
+        }
+    }
+
+    fn is_vertical(self) -> bool {
+        matches!(self, Side::Left | Side::Right)
+    }
+
+    /// The point on this side of `rect` closest to `hint`, or the middle of the side
+    fn port(self, rect: &Rect, hint: &Position) -> Position {
+        let (cx, cy) = rect.center();
+        let y = if rect.top < hint.top && hint.top < rect.bottom {
+            hint.top
+        } else {
+            cy
+        };
+        let x = if rect.left < hint.left && hint.left < rect.right {
+            hint.left
+        } else {
+            cx
+        };
+        match self {
+            Side::Left => point(rect.left, y),
+            Side::Right => point(rect.right, y),
+            Side::Top => point(x, rect.top),
+            Side::Bottom => point(x, rect.bottom),
+        }
+    }
+
+    /// Move `bend` so that the segment from `port` on this side stays perpendicular
+    fn align(self, bend: &mut Position, port: &Position) {
+        if self.is_vertical() {
+            bend.top = port.top;
+        } else {
+            bend.left = port.left;
+        }
+    }
+}
+
+/// A point on the edge of a table where a line can start or end
+#[derive(Debug, Copy, Clone)]
+struct Port {
+    pos: Position,
+    side: Side,
+}
+
+impl Port {
+    /// The first bend point, [`ROUTE_MARGIN`] away from the table
+    fn stub(&self) -> Position {
+        let (dx, dy) = STEPS[self.side.outward()];
+        point(
+            self.pos.left + dx as i32 * ROUTE_MARGIN,
+            self.pos.top + dy as i32 * ROUTE_MARGIN,
+        )
+    }
+}
+
+/// The middle of each side, and the middle of the range shared with `other`
+fn ports(rect: &Rect, other: &Rect) -> Vec<Port> {
+    let (cx, cy) = rect.center();
+    let mut ys = vec![cy];
+    let (top, bottom) = (rect.top.max(other.top), rect.bottom.min(other.bottom));
+    if top < bottom && (top + bottom) / 2 != cy {
+        ys.push((top + bottom) / 2);
+    }
+    let mut xs = vec![cx];
+    let (left, right) = (rect.left.max(other.left), rect.right.min(other.right));
+    if left < right && (left + right) / 2 != cx {
+        xs.push((left + right) / 2);
+    }
+    let mut ports = Vec::new();
+    for &y in &ys {
+        ports.push(Port {
+            pos: point(rect.left, y),
+            side: Side::Left,
+        });
+        ports.push(Port {
+            pos: point(rect.right, y),
+            side: Side::Right,
+        });
+    }
+    for &x in &xs {
+        ports.push(Port {
+            pos: point(x, rect.top),
+            side: Side::Top,
+        });
+        ports.push(Port {
+            pos: point(x, rect.bottom),
+            side: Side::Bottom,
+        });
+    }
+    ports
+}
+
+/// The coordinates of the lines that a route may use and the blocked segments
+struct RouteGrid {
+    xs: Vec<i32>,
+    ys: Vec<i32>,
+    /// `(x, y)` to `(x + 1, y)` crosses a table
+    h_blocked: Vec<bool>,
+    /// `(x, y)` to `(x, y + 1)` crosses a table
+    v_blocked: Vec<bool>,
+}
+
+impl RouteGrid {
+    fn new(rects: &[Rect], ports: &[Port]) -> Self {
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+        for rect in rects {
+            xs.extend([rect.left, rect.right]);
+            ys.extend([rect.top, rect.bottom]);
+        }
+        for port in ports {
+            let stub = port.stub();
+            xs.push(stub.left);
+            ys.push(stub.top);
+        }
+        xs.sort_unstable();
+        xs.dedup();
+        ys.sort_unstable();
+        ys.dedup();
+
+        let (nx, ny) = (xs.len(), ys.len());
+        let mut h_blocked = vec![false; nx * ny];
+        let mut v_blocked = vec![false; nx * ny];
+        let index = |values: &[i32], v: i32| values.binary_search(&v).unwrap_or(0);
+        for rect in rects {
+            let (x0, x1) = (index(&xs, rect.left), index(&xs, rect.right));
+            let (y0, y1) = (index(&ys, rect.top), index(&ys, rect.bottom));
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    if x < x1 && y0 < y && y < y1 {
+                        h_blocked[x * ny + y] = true;
+                    }
+                    if y < y1 && x0 < x && x < x1 {
+                        v_blocked[x * ny + y] = true;
+                    }
+                }
+            }
+        }
+        Self {
+            xs,
+            ys,
+            h_blocked,
+            v_blocked,
+        }
+    }
+
+    fn node(&self, pos: &Position) -> Option<usize> {
+        let x = self.xs.binary_search(&pos.left).ok()?;
+        let y = self.ys.binary_search(&pos.top).ok()?;
+        Some(x * self.ys.len() + y)
+    }
+
+    fn pos(&self, node: usize) -> Position {
+        let ny = self.ys.len();
+        point(self.xs[node / ny], self.ys[node % ny])
+    }
+
+    /// The next node in `dir` and the length of the step, if not blocked
+    fn step(&self, node: usize, dir: usize) -> Option<(usize, i64)> {
+        let count = self.ys.len();
+        let (dx, dy) = STEPS[dir];
+        let x = (node / count).checked_add_signed(dx)?;
+        let y = (node % count).checked_add_signed(dy)?;
+        if x >= self.xs.len() || y >= count {
+            return None;
+        }
+        let next = x * count + y;
+        let blocked = match dir {
+            RIGHT => self.h_blocked[node],
+            LEFT => self.h_blocked[next],
+            DOWN => self.v_blocked[node],
+            _ => self.v_blocked[next],
+        };
+        if blocked {
+            return None;
+        }
+        let (a, b) = (self.pos(node), self.pos(next));
+        let len = (a.left - b.left).abs() + (a.top - b.top).abs();
+        Some((next, i64::from(len)))
+    }
+}
+
+/// Remove points in the middle of a straight segment
+fn simplify(points: Vec<Position>) -> Vec<Position> {
+    let mut result: Vec<Position> = Vec::with_capacity(points.len());
+    for p in points {
+        if let Some(last) = result.last() {
+            if (last.left, last.top) == (p.left, p.top) {
+                continue;
+            }
+        }
+        if result.len() >= 2 {
+            let (a, b) = (result[result.len() - 2], result[result.len() - 1]);
+            if (a.left == b.left && b.left == p.left) || (a.top == b.top && b.top == p.top) {
+                result.pop();
+            }
+        }
+        result.push(p);
+    }
+    result
+}
+
+/// A straight line from the right edge of `src` to the left edge of `dest`, or
+/// between the centers if there is no free path
+fn fallback(src: &Rect, dest: &Rect) -> Vec<Position> {
+    let (sx, sy) = src.center();
+    let (dx, dy) = dest.center();
+    if src.right <= dest.left {
+        simplify(vec![
+            point(src.right, sy),
+            point((src.right + dest.left) / 2, sy),
+            point((src.right + dest.left) / 2, dy),
+            point(dest.left, dy),
+        ])
+    } else {
+        vec![point(sx, sy), point(dx, dy)]
+    }
+}
+
+/// A straight line between facing edges that are too close for a margin
+fn short_gap(src: &Rect, dest: &Rect) -> Option<Vec<Position>> {
+    let near = |gap: i32| (0..2 * ROUTE_MARGIN).contains(&gap);
+    let (top, bottom) = (src.top.max(dest.top), src.bottom.min(dest.bottom));
+    let (left, right) = (src.left.max(dest.left), src.right.min(dest.right));
+    let (y, x) = ((top + bottom) / 2, (left + right) / 2);
+    if top < bottom && near(dest.left - src.right) {
+        Some(vec![point(src.right, y), point(dest.left, y)])
+    } else if top < bottom && near(src.left - dest.right) {
+        Some(vec![point(src.left, y), point(dest.right, y)])
+    } else if left < right && near(dest.top - src.bottom) {
+        Some(vec![point(x, src.bottom), point(x, dest.top)])
+    } else if left < right && near(src.top - dest.bottom) {
+        Some(vec![point(x, src.top), point(x, dest.bottom)])
+    } else {
+        None
+    }
+}
+
+/// A rectilinear line from an edge of `src` to an edge of `dest`
+///
+/// The line starts and ends perpendicular to the edges and keeps a small margin
+/// around `obstacles` (which may include `src` and `dest`). It is the shortest such
+/// line, where each bend counts as an extra 2 cm. If there is no free path, e.g.
+/// because the tables overlap, the line goes straight through.
+pub fn route(src: &Rect, dest: &Rect, obstacles: &[Rect]) -> Vec<Position> {
+    if let Some(line) = short_gap(src, dest) {
+        return line;
+    }
+    let self_loop = src == dest;
+    let starts: Vec<Port> = ports(src, dest)
+        .into_iter()
+        .filter(|p| !self_loop || p.side == Side::Right)
+        .collect();
+    let ends: Vec<Port> = ports(dest, src)
+        .into_iter()
+        .filter(|p| !self_loop || p.side == Side::Top)
+        .collect();
+
+    let mut rects: Vec<Rect> = obstacles.iter().map(|r| r.inflate(ROUTE_MARGIN)).collect();
+    rects.push(src.inflate(ROUTE_MARGIN));
+    rects.push(dest.inflate(ROUTE_MARGIN));
+    let all_ports: Vec<Port> = starts.iter().chain(&ends).copied().collect();
+    let grid = RouteGrid::new(&rects, &all_ports);
+    let inside = |p: &Position| {
+        rects
+            .iter()
+            .any(|r| r.left < p.left && p.left < r.right && r.top < p.top && p.top < r.bottom)
+    };
+
+    // Dijkstra on (node, direction of travel)
+    let states = grid.xs.len() * grid.ys.len() * 4;
+    let mut dist = vec![i64::MAX; states];
+    let mut prev = vec![usize::MAX; states];
+    let mut heap = BinaryHeap::new();
+    let mut origin = vec![None; states];
+    for port in &starts {
+        let stub = port.stub();
+        if inside(&stub) {
+            continue;
+        }
+        if let Some(node) = grid.node(&stub) {
+            let state = node * 4 + port.side.outward();
+            let cost = i64::from(ROUTE_MARGIN);
+            if cost < dist[state] {
+                dist[state] = cost;
+                origin[state] = Some(*port);
+                heap.push(Reverse((cost, state)));
+            }
+        }
+    }
+    let goals: Vec<(usize, Port)> = ends
+        .iter()
+        .filter(|p| !inside(&p.stub()))
+        .filter_map(|p| Some((grid.node(&p.stub())?, *p)))
+        .collect();
+
+    let mut best: Option<(i64, usize, Port)> = None;
+    while let Some(Reverse((cost, state))) = heap.pop() {
+        if best.is_some_and(|(b, _, _)| cost >= b) {
+            break;
+        }
+        if cost > dist[state] {
+            continue;
+        }
+        let (node, dir) = (state / 4, state % 4);
+        for (_, port) in goals.iter().filter(|(n, _)| *n == node) {
+            let inward = opposite(port.side.outward());
+            let bend = if dir == inward { 0 } else { BEND_COST };
+            let total = cost + i64::from(ROUTE_MARGIN) + bend;
+            if dir != port.side.outward() && best.is_none_or(|(b, _, _)| total < b) {
+                best = Some((total, state, *port));
+            }
+        }
+        for next_dir in 0..4 {
+            if next_dir == opposite(dir) {
+                continue;
+            }
+            if let Some((next, len)) = grid.step(node, next_dir) {
+                let bend = if next_dir == dir { 0 } else { BEND_COST };
+                let next_state = next * 4 + next_dir;
+                let next_cost = cost + len + bend;
+                if next_cost < dist[next_state] {
+                    dist[next_state] = next_cost;
+                    prev[next_state] = state;
+                    origin[next_state] = origin[state];
+                    heap.push(Reverse((next_cost, next_state)));
+                }
+            }
+        }
+    }
+
+    match best {
+        Some((_, state, end)) => {
+            let mut nodes = vec![end.pos];
+            let mut s = state;
+            while s != usize::MAX {
+                nodes.push(grid.pos(s / 4));
+                s = prev[s];
+            }
+            if let Some(start) = origin[state] {
+                nodes.push(start.pos);
+            }
+            nodes.reverse();
+            simplify(nodes)
+        }
+        None => fallback(src, dest),
+    }
+}
+
+/// Whether `points` leave `src` at `start`, enter `dest` at `end` and only have
+/// horizontal and vertical segments outside of all tables
+fn is_valid_route(
+    points: &[Position],
+    (src, start): (&Rect, Side),
+    (dest, end): (&Rect, Side),
+    obstacles: &[Rect],
+) -> bool {
+    let n = points.len();
+    if n < 2
+        || Side::toward(&points[0], &points[1]) != Some(start)
+        || Side::toward(&points[n - 1], &points[n - 2]) != Some(end)
+    {
+        return false;
+    }
+    points.windows(2).all(|segment| {
+        let (a, b) = (segment[0], segment[1]);
+        let bounds = Rect {
+            left: a.left.min(b.left),
+            top: a.top.min(b.top),
+            right: a.left.max(b.left),
+            bottom: a.top.max(b.top),
+        };
+        Side::toward(&a, &b).is_some()
+            && !bounds.overlaps(src)
+            && !bounds.overlaps(dest)
+            && obstacles.iter().all(|r| !bounds.overlaps(r))
+    })
+}
+
+/// Update a line after `src` or `dest` (or one of the `obstacles`) moved
+///
+/// The line stays attached to the same sides of the tables, at the same offset
+/// where possible, and keeps its bend points. Only the first and last bend point
+/// move, to keep the first and last segment perpendicular to the tables. If the
+/// result would cross a table, the line is replaced with a new [`route`].
+pub fn reroute(points: &[Position], src: &Rect, dest: &Rect, obstacles: &[Rect]) -> Vec<Position> {
+    let old = simplify(points.to_vec());
+    let n = old.len();
+    if n >= 2 {
+        let start = Side::toward(&old[0], &old[1]);
+        let end = Side::toward(&old[n - 1], &old[n - 2]);
+        if let (Some(start), Some(end)) = (start, end) {
+            let mut new = old.clone();
+            new[0] = start.port(src, &old[0]);
+            new[n - 1] = end.port(dest, &old[n - 1]);
+            if n > 2 {
+                let (first, last) = (new[0], new[n - 1]);
+                start.align(&mut new[1], &first);
+                end.align(&mut new[n - 2], &last);
+            }
+            let new = simplify(new);
+            if is_valid_route(&new, (src, start), (dest, end), obstacles) {
+                return new;
+            }
+        }
+    }
+    route(src, dest, obstacles)
+}
+
+/// The middle of the longest segment of a line, and whether that is horizontal
+fn label_anchor(points: &[Position]) -> Option<(Position, bool)> {
+    let segment = points
+        .windows(2)
+        .max_by_key(|s| (s[1].left - s[0].left).abs() + (s[1].top - s[0].top).abs())?;
+    let (a, b) = (segment[0], segment[1]);
+    let middle = point((a.left + b.left) / 2, (a.top + b.top) / 2);
+    Some((middle, a.top == b.top))
+}
+
+/// Place a label of `size` next to the middle of the longest segment of a line
+pub(crate) fn label_position(points: &[Position], size: &Size) -> Position {
+    match label_anchor(points) {
+        Some((middle, true)) => point(
+            middle.left - size.width as i32 / 2,
+            middle.top + LABEL_OFFSET,
+        ),
+        Some((middle, false)) => point(
+            middle.left + LABEL_OFFSET,
+            middle.top - size.height as i32 / 2,
+        ),
+        None => point(0, 0),
+    }
+}
+
+/// The position of the site of a polyline with the given points
+pub(crate) fn polyline_site_pos(points: &[Position]) -> Position {
+    let left = points.iter().map(|p| p.left).min().unwrap_or_default();
+    let top = points.iter().map(|p| p.top).min().unwrap_or_default();
+    point(left - POLYLINE_SITE_OFFSET.0, top - POLYLINE_SITE_OFFSET.1)
+}
+
+/// The sites of the tables and the relationships between them
+///
+/// Relationships whose tooltip does not name two tables of the diagram are left out.
+pub(crate) struct SiteGraph {
+    /// Index of the site of each table
+    pub(crate) tables: Vec<usize>,
+    /// Index of the site of each relationship, and the `from` and `to` tables
+    pub(crate) relationships: Vec<(usize, usize, usize)>,
+}
+
+impl SiteGraph {
+    pub(crate) fn new(streams: &SysDiagramStreams) -> Self {
+        let sites = &streams.form.sites;
+        let controls = sites.iter().zip(&streams.controls).enumerate();
+        let tables: Vec<usize> = controls
+            .clone()
+            .filter(|(_, (_, c))| matches!(c, Control::SchGrid(_)))
+            .map(|(i, _)| i)
+            .collect();
+        let table = |caption: &str| tables.iter().position(|&i| sites[i].tooltip == caption);
+        let relationships = controls
+            .filter(|(_, (_, control))| matches!(control, Control::Polyline(_)))
+            .filter_map(|(i, (site, _))| {
+                let (_, (_, from, to)) = parse_relationship(&site.tooltip).ok()?;
+                Some((i, table(&from)?, table(&to)?))
+            })
+            .collect();
+        Self {
+            tables,
+            relationships,
+        }
+    }
+
+    pub(crate) fn rects(&self, streams: &SysDiagramStreams) -> Vec<Rect> {
+        self.tables
+            .iter()
+            .filter_map(|&i| match &streams.controls[i] {
+                Control::SchGrid(sch_grid) => {
+                    Some(Rect::new(&streams.form.sites[i].pos, &sch_grid.extent))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn site_mut(sites: &mut [OleSite], id: u32) -> Option<&mut OleSite> {
+    sites.iter_mut().find(|s| s.id as u32 == id)
+}
+
+/// Replace the points of the polyline at site `index`, moving the site and its labels
+///
+/// With `keep_labels`, labels move along with the longest segment of the line, so
+/// that their offset from the line stays the same. Otherwise, or if the longest
+/// segment changes direction, they are placed with [`label_position`].
+pub(crate) fn set_polyline_points(
+    streams: &mut SysDiagramStreams,
+    index: usize,
+    points: Vec<Position>,
+    keep_labels: bool,
+) {
+    let polyline = match &mut streams.controls[index] {
+        Control::Polyline(polyline) => polyline,
+        _ => return,
+    };
+    let old_anchor = label_anchor(&polyline.positions).filter(|_| keep_labels);
+    streams.form.sites[index].pos = polyline_site_pos(&points);
+    polyline.positions = points;
+    let new_anchor = label_anchor(&polyline.positions);
+    for label_ref in &mut polyline.labels {
+        label_ref.pos = match (old_anchor, new_anchor) {
+            (Some((old, horizontal)), Some((new, h))) if horizontal == h => point(
+                label_ref.pos.left + new.left - old.left,
+                label_ref.pos.top + new.top - old.top,
+            ),
+            _ => label_position(&polyline.positions, &label_ref.size),
+        };
+        if let Some(site) = site_mut(&mut streams.form.sites, label_ref.id) {
+            site.pos = label_ref.pos;
+        }
+    }
+}
+
+/// Route every relationship of a diagram again, see [`route`]
+///
+/// Labels are moved next to the longest segment of their line.
+pub fn route_relationships(streams: &mut SysDiagramStreams) {
+    let graph = SiteGraph::new(streams);
+    let rects = graph.rects(streams);
+    for &(index, from, to) in &graph.relationships {
+        let points = route(&rects[from], &rects[to], &rects);
+        set_polyline_points(streams, index, points, false);
+    }
+}
+
+/// Update every relationship of a diagram after tables moved, see [`reroute`]
+///
+/// Lines that are still valid are not changed at all. The labels of the other
+/// lines keep their offset from the line where possible.
+pub fn update_relationships(streams: &mut SysDiagramStreams) {
+    let graph = SiteGraph::new(streams);
+    let rects = graph.rects(streams);
+    for &(index, from, to) in &graph.relationships {
+        let points = match &streams.controls[index] {
+            Control::Polyline(polyline) => &polyline.positions,
+            _ => continue,
+        };
+        let new = reroute(points, &rects[from], &rects[to], &rects);
+        let unchanged = new.len() == points.len()
+            && new
+                .iter()
+                .zip(points)
+                .all(|(a, b)| (a.left, a.top) == (b.left, b.top));
+        if !unchanged {
+            set_polyline_points(streams, index, new, true);
+        }
+    }
+}
+
+/// Move all tables of a diagram with [`layout_tables`] and route the relationships
+///
+/// Tables keep their size ([`crate::SchGrid::extent`]).
+pub fn apply_layout(streams: &mut SysDiagramStreams, options: &LayoutOptions) {
+    let graph = SiteGraph::new(streams);
+    let sizes: Vec<Size> = graph
+        .rects(streams)
+        .iter()
+        .map(|r| Size {
+            width: (r.right - r.left) as u32,
+            height: (r.bottom - r.top) as u32,
+        })
+        .collect();
+    let edges: Vec<(usize, usize)> = graph
+        .relationships
+        .iter()
+        .map(|&(_, from, to)| (from, to))
+        .collect();
+    let positions = layout_tables(&sizes, &edges, options);
+    for (&index, pos) in graph.tables.iter().zip(positions) {
+        streams.form.sites[index].pos = pos;
+    }
+    route_relationships(streams);
+}
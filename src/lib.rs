@@ -69,6 +69,20 @@
 //! - There are two streams `Schema UDV Default` and `Schema UDV Default Post V6` where `UDV` might
 //!   stand for user-defined value/variable/view in the SQL Server context.
 //!
+//! ## Writing
+//!
+//! [`SysDiagramFile::streams`] loads every stream in a form that [`SysDiagramStreams::write`]
+//! can persist again. Unknown fields and controls are carried along, so a file that is
+//! read and written without changes has the same streams with the exact same contents.
+//! [`SysDiagramStreams::from_bytes`] also keeps the compound file around them, so that
+//! an unchanged file is written back byte for byte.
+//!
+//! A [`SysDiagramBuilder`] creates the streams of a new diagram from a list of tables
+//! and foreign keys. Tables of an existing diagram can be added, removed, renamed,
+//! moved and resized with methods like [`SysDiagramStreams::rename_table`], which
+//! update every stream that refers to the table. The [`layout`] module places the
+//! tables and routes the relationship lines of new or reorganized diagrams.
+//!
 //! ## Preview
 //!
 //! ![Database Diagram](https://raw.githubusercontent.com/Xiphoseer/sysdiagram/ad596ad4e17bf25e6e004a212c1d12d03c97f28e/res/dv3w7c1.gif)
@@ -80,12 +94,21 @@
 //! [VBA UserForm]: https://learn.microsoft.com/en-us/office/vba/excel/concepts/controls-dialogboxes-forms/create-a-user-form
 //! [`dts-designer-1.0`]: https://learn.microsoft.com/en-us/openspecs/sql_data_portability/ms-dtsx/a7d84cd1-4aca-433a-b450-58b331fca519
 
+mod blob;
+mod builder;
+mod catalog;
 mod core;
+mod edit;
+pub use blob::*;
+pub use builder::*;
+pub use catalog::*;
 pub use core::*;
 use std::{
     convert::TryFrom,
-    io::{Read, Seek},
+    fs,
+    io::{Cursor, Read, Seek, SeekFrom, Write},
     ops::DerefMut,
+    path::{Component, Path, PathBuf},
 };
 mod dtyp;
 pub use dtyp::*;
@@ -94,24 +117,51 @@ use dds::DdsStream;
 pub use error::*;
 pub mod mdtdb;
 mod parser;
+mod record;
+#[cfg(feature = "serde")]
+mod remote;
+pub mod render;
+mod script;
 pub use mdtdb::SchGrid;
 use ms_oforms::{
     controls::user_form::FormControl, properties::FormEmbeddedActiveXControl, OFormsFile,
 };
-use nom::{error::VerboseError, Finish};
+use nom::Finish;
 pub use parser::*;
+pub use record::*;
+pub use script::*;
+use uuid::Uuid;
 mod connection_string;
 pub mod dds;
 pub mod dsref;
+pub mod form;
+mod hexdump;
+pub mod layout;
+mod writer;
+use bstr::BString;
 pub use connection_string::*;
-use dsref::{parse_dsref_schema_contents, DSRefSchemaContents};
+use dsref::{parse_dsref_schema_contents, write_dsref_schema_contents, DSRefSchemaContents};
+use form::{annotate_form_stream, parse_form_stream, write_form_stream, FormStream};
+use hexdump::Layout;
+pub use hexdump::{write_hexdump, Field};
 
 use crate::{
-    dds::{parse_dds_stream, parse_label, parse_polyline, CLSID_DDSLABEL, CLSID_POLYLINE},
-    mdtdb::{parse_sch_grid, CLSID_SCHGRID},
+    dds::{
+        annotate_dds_stream, annotate_label, annotate_polyline, parse_dds_stream, parse_label,
+        parse_polyline, write_dds_stream, write_label, write_polyline, CLSID_DDSLABEL,
+        CLSID_POLYLINE,
+    },
+    dsref::annotate_dsref_schema_contents,
+    mdtdb::{annotate_sch_grid, parse_sch_grid, write_sch_grid, CLSID_SCHGRID},
 };
 
+const COMP_OBJ: &str = "/\x01CompObj";
+const FORM: &str = "/f";
+const OBJECTS: &str = "/o";
+const DDS_STREAM: &str = "/\x03DdsStream";
 const DSREF_SCHEMA_CONTENTS: &str = "/DSREF-SCHEMA-CONTENTS";
+const SCHEMA_UDV_DEFAULT: &str = "/Schema UDV Default";
+const SCHEMA_UDV_DEFAULT_POST_V6: &str = "/Schema UDV Default Post V6";
 
 // See: http://www.dejadejadeja.com/detech/ocxdb/
 // See: http://pitcheploy.free.fr/Microsoft%20Visual%20Basic%206.0%20%C3%89dition%20Professionnelle%20(Fran%C3%A7ais)/HKEY_LOCAL_MACHINE.txt
@@ -124,6 +174,65 @@ pub struct SysDiagramFile<T> {
 
 type SchemaForm = (FormControl, Vec<(SiteInfo, Control)>, DdsStream);
 
+/// The bytes of one site in the `o` stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiteObject {
+    /// The ID of the site in the `f` stream
+    pub id: i32,
+    /// The class of the control
+    pub clsid: Uuid,
+    pub data: Vec<u8>,
+}
+
+impl SiteObject {
+    /// The file name used by [`SysDiagramFile::extract_site_objects`], e.g.
+    /// `o.3.e9b0e6d9-811c-11d0-ad51-00a0c90f5739.bin`
+    pub fn file_name(&self) -> String {
+        format!("o.{}.{}.bin", self.id, self.clsid)
+    }
+}
+
+/// A file name for a stream, with control characters as `_XX`
+///
+/// For example, `\x03DdsStream` becomes `_03DdsStream`.
+pub fn stream_file_name(name: &str) -> String {
+    let mut file_name = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_control() {
+            file_name.push_str(&format!("_{:02X}", c as u32));
+        } else {
+            file_name.push(c);
+        }
+    }
+    file_name
+}
+
+/// The path below `dir` for the stream at `path`, see [`stream_file_name`]
+///
+/// Streams in a storage are placed in a directory of the same name, so streams
+/// with the same name in different storages don't overwrite each other.
+pub fn stream_file_path(dir: &Path, path: &Path) -> PathBuf {
+    let mut target = dir.to_path_buf();
+    for component in path.components() {
+        if let Component::Normal(name) = component {
+            target.push(stream_file_name(&name.to_string_lossy()));
+        }
+    }
+    target
+}
+
+impl SysDiagramFile<Cursor<Vec<u8>>> {
+    /// Open a base64 encoded sysdiagram, see [`decode_base64`]
+    pub fn from_base64(input: &[u8]) -> Result<Self, Error> {
+        Ok(Self::open(Cursor::new(decode_base64(input)?))?)
+    }
+
+    /// Open a hex encoded sysdiagram, see [`decode_hex`]
+    pub fn from_hex(input: &[u8]) -> Result<Self, Error> {
+        Ok(Self::open(Cursor::new(decode_hex(input)?))?)
+    }
+}
+
 impl<T: Read + Seek> SysDiagramFile<T> {
     pub fn open(inner: T) -> std::io::Result<Self> {
         let inner = OFormsFile::open(inner)?;
@@ -135,30 +244,174 @@ impl<T: Read + Seek> SysDiagramFile<T> {
     }
 
     pub fn dsref_schema_contents(&mut self) -> Result<DSRefSchemaContents, Error> {
-        if self.is_stream(DSREF_SCHEMA_CONTENTS) {
-            let mut r_stream = self.dsref_schema_contents_stream().map_err(Error::Cfb)?;
+        let bytes = self.read_stream(DSREF_SCHEMA_CONTENTS)?;
+        let (_, dsref_schema_contents) = parse_dsref_schema_contents::<Error>(&bytes[..])?;
+        Ok(dsref_schema_contents)
+    }
+
+    /// Read the raw bytes of a stream
+    pub fn read_stream(&mut self, path: &'static str) -> Result<Vec<u8>, Error> {
+        if self.is_stream(path) {
+            let mut r_stream = self.open_stream(path).map_err(Error::Cfb)?;
             let r_stream_len = usize::try_from(r_stream.len()).map_err(Error::StreamTooLong)?;
             let mut bytes: Vec<u8> = Vec::with_capacity(r_stream_len);
             r_stream.read_to_end(&mut bytes).map_err(Error::Cfb)?;
-            let (_, dsref_schema_contents) =
-                parse_dsref_schema_contents::<VerboseError<_>>(&bytes[..])?;
-            Ok(dsref_schema_contents)
+            Ok(bytes)
         } else {
-            Err(Error::MissingStream(DSREF_SCHEMA_CONTENTS))
+            Err(Error::MissingStream(path))
+        }
+    }
+
+    /// The paths of all streams in the compound file
+    pub fn stream_paths(&self) -> Vec<PathBuf> {
+        self.walk()
+            .filter(|entry| entry.is_stream())
+            .map(|entry| entry.path().to_path_buf())
+            .collect()
+    }
+
+    /// Write the raw bytes of streams to files in `dir`, at [`stream_file_path`]
+    ///
+    /// A stream is selected if one of `names` is its name, its file name or its name
+    /// without leading control characters (e.g. `DdsStream`). If `names` is empty,
+    /// all streams are extracted. Returns the paths of the files written.
+    pub fn extract_streams(&mut self, dir: &Path, names: &[&str]) -> Result<Vec<PathBuf>, Error> {
+        let streams: Vec<(PathBuf, String)> = self
+            .stream_paths()
+            .into_iter()
+            .filter_map(|path| {
+                let name = path.file_name()?.to_string_lossy().into_owned();
+                Some((path, name))
+            })
+            .collect();
+        for name in names {
+            let known = streams.iter().any(|(_, n)| stream_matches(n, name));
+            if !known {
+                return Err(Error::UnknownStream(name.to_string()));
+            }
+        }
+
+        let mut written = Vec::new();
+        for (path, name) in streams {
+            if !names.is_empty() && !names.iter().any(|n| stream_matches(&name, n)) {
+                continue;
+            }
+            let mut bytes = Vec::new();
+            self.open_stream(&path)?.read_to_end(&mut bytes)?;
+            let target = stream_file_path(dir, &path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, &bytes)?;
+            written.push(target);
+        }
+        Ok(written)
+    }
+
+    /// Split the `o` stream into the bytes of each site, using the sizes from the `f` stream
+    pub fn site_objects(&mut self) -> Result<Vec<SiteObject>, Error> {
+        let mut form = self.root_form().map_err(Error::Cfb)?;
+        let mut iter = form.site_iter();
+        let mut objects = Vec::new();
+        while let Some((ctrl_class, _depth, ole_site)) = iter.next() {
+            let clsid = match ctrl_class {
+                FormEmbeddedActiveXControl::ControlNonCached(class_info) => class_info.cls_id,
+                FormEmbeddedActiveXControl::ControlCached(_) => {
+                    return Err(Error::CachedControl(ole_site.id))
+                }
+            };
+            let id = ole_site.id;
+            let mut data = Vec::with_capacity(ole_site.object_stream_size as usize);
+            let mut s = iter.site_stream().map_err(Error::Cfb)?;
+            s.read_to_end(&mut data)?;
+            objects.push(SiteObject { id, clsid, data });
+        }
+        Ok(objects)
+    }
+
+    /// Write every [`SiteObject`] to a file in `dir`, named by [`SiteObject::file_name`]
+    pub fn extract_site_objects(&mut self, dir: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut written = Vec::new();
+        for object in self.site_objects()? {
+            let target = dir.join(object.file_name());
+            fs::write(&target, &object.data)?;
+            written.push(target);
         }
+        Ok(written)
+    }
+
+    /// Read a stream and the [`Field`]s its bytes were parsed into, e.g. for [`write_hexdump`]
+    ///
+    /// The stream is selected like in [`SysDiagramFile::extract_streams`]. The `f`, `o`,
+    /// `\3DdsStream` and `DSREF-SCHEMA-CONTENTS` streams are annotated, all other
+    /// streams have no fields. Returns [`Error::AnnotationMismatch`] if the bytes
+    /// recorded for the fields are not the bytes of the stream.
+    pub fn annotated_stream(&mut self, name: &str) -> Result<(Vec<u8>, Vec<Field>), Error> {
+        let path = self
+            .stream_paths()
+            .into_iter()
+            .find(|path| {
+                path.file_name()
+                    .map_or(false, |n| stream_matches(&n.to_string_lossy(), name))
+            })
+            .ok_or_else(|| Error::UnknownStream(name.to_string()))?;
+        let mut bytes = Vec::new();
+        self.open_stream(&path)?.read_to_end(&mut bytes)?;
+
+        let mut layout = Layout::new();
+        match path.to_string_lossy().as_ref() {
+            FORM => {
+                let (_, form) = parse_form_stream(&bytes)?;
+                annotate_form_stream(&mut layout, &form)?;
+            }
+            OBJECTS => {
+                let (_, controls, _) = self.schema_form()?;
+                for (site, control) in &controls {
+                    let name = format!("site {}", site.id);
+                    layout.group(&name, |l| annotate_control(l, control))?;
+                }
+            }
+            DDS_STREAM => {
+                let (_, _, dds_stream) = self.schema_form()?;
+                annotate_dds_stream(&mut layout, &dds_stream)?;
+            }
+            DSREF_SCHEMA_CONTENTS => {
+                let contents = self.dsref_schema_contents()?;
+                annotate_dsref_schema_contents(&mut layout, &contents)?;
+            }
+            _ => return Ok((bytes, Vec::new())),
+        }
+
+        // The annotate functions mirror the writers, so check they didn't drift apart
+        let written = layout.bytes();
+        if written != bytes.as_slice() {
+            let offset = written
+                .iter()
+                .zip(&bytes)
+                .take_while(|(a, b)| a == b)
+                .count();
+            let name = path.to_string_lossy().into_owned();
+            return Err(Error::AnnotationMismatch(name, offset));
+        }
+        Ok((bytes, layout.into_fields()))
+    }
+
+    /// Load the `f` stream losslessly, see [`form`]
+    pub fn form_stream(&mut self) -> Result<FormStream, Error> {
+        let bytes = self.read_stream(FORM)?;
+        let (_, form) = parse_form_stream(&bytes)?;
+        Ok(form)
     }
 
     pub fn schema_form(&mut self) -> Result<SchemaForm, Error> {
         if !self.is_stream("/f") {
             return Err(Error::MissingStream("f"));
         }
-        eprintln!("Parsing FormControl");
         let mut form = self.root_form().map_err(Error::Cfb)?;
 
         if !self.is_stream("/o") {
             return Err(Error::MissingStream("o"));
         }
-        eprintln!("Parsing Objects");
 
         let mut iter = form.site_iter();
         let mut controls = Vec::new();
@@ -177,7 +430,9 @@ impl<T: Read + Seek> SysDiagramFile<T> {
             //println!("{:?}", ole_site.site_position);
             let clsid = match ctrl_class {
                 FormEmbeddedActiveXControl::ControlNonCached(class_info) => class_info.cls_id,
-                FormEmbeddedActiveXControl::ControlCached(_) => unimplemented!(""),
+                FormEmbeddedActiveXControl::ControlCached(_) => {
+                    return Err(Error::CachedControl(ole_site.id))
+                }
             };
             let control = match clsid {
                 CLSID_SCHGRID => {
@@ -187,18 +442,18 @@ impl<T: Read + Seek> SysDiagramFile<T> {
                 }
                 CLSID_POLYLINE => {
                     // Foreign Key
-                    let (_, control) = parse_polyline(data)?;
+                    let (_, control) = parse_polyline::<Error>(data)?;
                     //let (_, (name, from, to)) = parser::parse_relationship(&caption[..])?;
                     Control::Polyline(control)
                 }
                 CLSID_DDSLABEL => {
-                    let (_, label) = parse_label::<nom::error::Error<_>>(data)?;
+                    let (_, label) = parse_label::<Error>(data)?;
                     Control::Label(label)
                 }
-                _ => {
-                    eprintln!("Unknown clsid: {}", clsid);
-                    Control::Unknown(clsid)
-                }
+                _ => Control::Unknown {
+                    clsid,
+                    data: BString::from(data),
+                },
             };
             controls.push((
                 SiteInfo {
@@ -212,7 +467,7 @@ impl<T: Read + Seek> SysDiagramFile<T> {
         }
         let form_control = form.into_form_control();
 
-        let mut dds_stream = self.open_stream("\x03DdsStream")?;
+        let mut dds_stream = self.open_stream(DDS_STREAM)?;
         let mut buf = Vec::with_capacity(dds_stream.len() as usize);
         dds_stream.read_to_end(&mut buf)?;
 
@@ -220,6 +475,176 @@ impl<T: Read + Seek> SysDiagramFile<T> {
 
         Ok((form_control, controls, diagram))
     }
+
+    /// Load the high-level [`SysDiagram`], i.e. all tables and relationships with their labels
+    pub fn diagram(&mut self) -> Result<SysDiagram, Error> {
+        let dsref_schema_contents = self.dsref_schema_contents()?;
+        let (form_control, controls, dds_stream) = self.schema_form()?;
+        Ok(SysDiagram::from_controls(
+            controls,
+            &dds_stream,
+            dsref_schema_contents,
+            form_control.back_color,
+        ))
+    }
+
+    /// Load all streams, such that they can be written back with [`SysDiagramStreams::write`]
+    ///
+    /// The compound file itself is not kept, see [`SysDiagramStreams::from_bytes`].
+    pub fn streams(&mut self) -> Result<SysDiagramStreams, Error> {
+        let (_form_control, controls, dds_stream) = self.schema_form()?;
+        let form = self.form_stream()?;
+        if form.sites.len() != controls.len() {
+            return Err(Error::SiteCount(form.sites.len(), controls.len()));
+        }
+        Ok(SysDiagramStreams {
+            comp_obj: BString::from(self.read_stream(COMP_OBJ)?),
+            form,
+            controls: controls.into_iter().map(|(_, control)| control).collect(),
+            dds_stream,
+            dsref_schema_contents: self.dsref_schema_contents()?,
+            schema_udv_default: BString::from(self.read_stream(SCHEMA_UDV_DEFAULT)?),
+            schema_udv_default_post_v6: BString::from(
+                self.read_stream(SCHEMA_UDV_DEFAULT_POST_V6)?,
+            ),
+            container: None,
+        })
+    }
+}
+
+/// The content of all streams of a sysdiagram
+///
+/// The `i`-th entry of `controls` is persisted in the `o` stream for the `i`-th
+/// site of `form`. All other streams are written as they were read.
+#[derive(Debug)]
+pub struct SysDiagramStreams {
+    /// The `\1CompObj` stream
+    pub comp_obj: BString,
+    /// The `f` stream
+    pub form: FormStream,
+    /// The controls in the `o` stream
+    pub controls: Vec<Control>,
+    /// The `\3DdsStream` stream
+    pub dds_stream: DdsStream,
+    /// The `DSREF-SCHEMA-CONTENTS` stream
+    pub dsref_schema_contents: DSRefSchemaContents,
+    /// The `Schema UDV Default` stream
+    pub schema_udv_default: BString,
+    /// The `Schema UDV Default Post V6` stream
+    pub schema_udv_default_post_v6: BString,
+    /// The compound file the streams were read from, see [`SysDiagramStreams::from_bytes`]
+    pub container: Option<BString>,
+}
+
+/// Persist a control to the `o` stream
+pub fn write_control<W: Write>(w: &mut W, control: &Control) -> Result<(), Error> {
+    match control {
+        Control::SchGrid(sch_grid) => write_sch_grid(w, sch_grid)?,
+        Control::Polyline(polyline) => write_polyline(w, polyline)?,
+        Control::Label(label) => write_label(w, label)?,
+        Control::Unknown { data, .. } => w.write_all(data)?,
+    }
+    Ok(())
+}
+
+/// Annotated version of [`write_control`]
+fn annotate_control(l: &mut Layout, control: &Control) -> Result<(), Error> {
+    match control {
+        Control::SchGrid(sch_grid) => annotate_sch_grid(l, sch_grid),
+        Control::Polyline(polyline) => annotate_polyline(l, polyline),
+        Control::Label(label) => annotate_label(l, label),
+        Control::Unknown { data, .. } => l.unknown("data", |w| w.write_all(data)),
+    }
+}
+
+/// Write a stream, unless it already has the same contents
+fn write_stream<F: Read + Write + Seek>(
+    cfb: &mut cfb::CompoundFile<F>,
+    path: &str,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    if cfb.is_stream(path) {
+        let mut old = Vec::new();
+        cfb.open_stream(path)?.read_to_end(&mut old)?;
+        if old == bytes {
+            return Ok(());
+        }
+    }
+    let mut stream = cfb.create_stream(path)?;
+    stream.write_all(bytes)?;
+    Ok(())
+}
+
+impl SysDiagramStreams {
+    /// Load the streams of a sysdiagram like [`SysDiagramFile::streams`], keeping the
+    /// compound file as [`SysDiagramStreams::container`]
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        let mut streams = SysDiagramFile::open(Cursor::new(&bytes[..]))?.streams()?;
+        streams.container = Some(BString::from(bytes));
+        Ok(streams)
+    }
+
+    /// Write a sysdiagram compound file to the empty `inner`
+    ///
+    /// The `ObjectStreamSize` of each site in [`SysDiagramStreams::form`] is updated
+    /// to match the persisted control. Every stream is reproduced byte for byte.
+    ///
+    /// If there is a [`SysDiagramStreams::container`], it is copied to `inner` and only
+    /// the streams that changed are replaced, so the sector layout and timestamps are
+    /// kept. Otherwise, the layout of the new compound file is left to [`cfb`].
+    pub fn write<F: Read + Write + Seek>(&self, mut inner: F) -> Result<F, Error> {
+        if self.form.sites.len() != self.controls.len() {
+            return Err(Error::SiteCount(self.form.sites.len(), self.controls.len()));
+        }
+        let mut form = self.form.clone();
+        let mut objects = Vec::new();
+        for (site, control) in form.sites.iter_mut().zip(&self.controls) {
+            let start = objects.len();
+            write_control(&mut objects, control)?;
+            let size = objects.len() - start;
+            site.object_stream_size = u32::try_from(size).map_err(Error::BufTooLong)?;
+        }
+
+        let mut f = Vec::new();
+        write_form_stream(&mut f, &form)?;
+        let mut dds_stream = Vec::new();
+        write_dds_stream(&mut dds_stream, &self.dds_stream)?;
+        let mut dsref_schema_contents = Vec::new();
+        write_dsref_schema_contents(&mut dsref_schema_contents, &self.dsref_schema_contents)?;
+
+        let mut cfb = match &self.container {
+            Some(container) => {
+                inner.write_all(container)?;
+                inner.seek(SeekFrom::Start(0))?;
+                cfb::CompoundFile::open(inner)?
+            }
+            None => cfb::CompoundFile::create_with_version(cfb::Version::V3, inner)?,
+        };
+        write_stream(&mut cfb, FORM, &f)?;
+        write_stream(&mut cfb, OBJECTS, &objects)?;
+        write_stream(&mut cfb, COMP_OBJ, &self.comp_obj)?;
+        write_stream(&mut cfb, DDS_STREAM, &dds_stream)?;
+        write_stream(&mut cfb, SCHEMA_UDV_DEFAULT, &self.schema_udv_default)?;
+        write_stream(&mut cfb, DSREF_SCHEMA_CONTENTS, &dsref_schema_contents)?;
+        write_stream(
+            &mut cfb,
+            SCHEMA_UDV_DEFAULT_POST_V6,
+            &self.schema_udv_default_post_v6,
+        )?;
+        cfb.flush()?;
+        Ok(cfb.into_inner())
+    }
+
+    /// Write the sysdiagram to a new buffer, e.g. for the `definition` column
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.write(Cursor::new(Vec::new()))?.into_inner())
+    }
+}
+
+fn stream_matches(name: &str, query: &str) -> bool {
+    name == query
+        || stream_file_name(name) == query
+        || name.trim_start_matches(char::is_control) == query
 }
 
 impl<T> std::ops::Deref for SysDiagramFile<T> {
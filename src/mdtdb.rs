@@ -15,13 +15,25 @@
 //!
 //! See also: <http://www.dejadejadeja.com/detech/ocxdb/mdt2db.dll.txt.lisp>
 
-use crate::{le_u32_2, parse_u32_wstring_nt, parse_wstring_nt};
+use crate::hexdump::Layout;
+use crate::writer::{
+    length_prefix, write_length_value, write_size, write_u16, write_u32, write_u32_wstring_nt,
+    write_wstring_nt,
+};
+use crate::{le_u32_2, parse_u32_wstring_nt, parse_wstring_nt, Error};
+use bstr::BString;
 use ms_oforms::properties::Size;
 use nom::bytes::complete::tag;
+use nom::combinator::{map, rest};
 use nom::multi::{count, length_count, length_value};
 use nom::number::complete::{le_u16, le_u32};
 use nom::sequence::pair;
 use nom::IResult;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive as _;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Write};
 use uuid::{uuid, Uuid};
 
 /// `SchGrid OLE Custom Control module` (`mdt2db.dll`)
@@ -53,8 +65,10 @@ pub const CLSID_DSCHGRID_EVENTS: Uuid = uuid!("847f3bf4-617f-43c7-8535-2986e1d55
 /// ## SchGrid Control
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[allow(dead_code)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SchGrid {
     /// Actual size of the control on the diagram surface
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::SizeDef"))]
     pub extent: Size,
     /// Configuration of the column grid frame (layout)
     pub frame: GridFrameWnd,
@@ -63,14 +77,26 @@ pub struct SchGrid {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GridFrameWnd {
     /// The title of the frame window
     pub caption: String,
+    /// Remainder of the caption buffer after the terminating NUL (stale data)
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::hex"))]
+    pub(crate) _caption_pad: BString,
     /// A set of grid layout structs, one for each [`TableView`].
     pub layouts: Box<[GridSpec; 5]>,
 }
 
+impl GridFrameWnd {
+    /// The grid layout for a [`TableView`]
+    pub fn layout(&self, view: TableView) -> &GridSpec {
+        &self.layouts[view as usize]
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataSource {
     // one of these is the [`TableView`]
     pub(crate) _cd3: u32, // 1
@@ -86,55 +112,158 @@ pub struct DataSource {
 
 /// "Table View" selection for a table
 ///
+/// The value is the index into [`GridFrameWnd::layouts`] and the `N` in the
+/// `TableViewMode:N` property of the `\3DdsStream`. `ActiveTableViewMode` selects
+/// the current view.
+///
 /// See:
 /// - <https://nakulvachhrajani.com/2021/03/15/0423-sql-server-exporting-database-diagrams-for-offline-viewing/>
 /// - <https://learn.microsoft.com/en-us/sql/ssms/visual-db-tools/column-selection-dialog-box-visual-database-tools>
 /// - <https://learn.microsoft.com/en-us/sql/ssms/visual-db-tools/customize-the-amount-of-information-displayed-in-diagrams-visual-database-tools>
 ///
+#[derive(Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TableView {
-    Custom = 0,
+    /// Column Name, Data Type, and Allow Nulls.
+    ///
+    /// See: <https://learn.microsoft.com/en-us/sql/ssms/visual-db-tools/walkthrough-adding-and-changing-a-database-diagram?view=sql-server-ver16>
+    Standard = 0,
     ColumnNames = 1,
     Keys = 2,
     NameOnly = 3,
-    /// Column Name, Data Type, and Allow Nulls.
+    /// Column Name, Condensed Type and Nullable by default
+    Custom = 4,
+}
+
+impl TableView {
+    /// The property columns shown in this view for a new table
     ///
-    /// See: <https://learn.microsoft.com/en-us/sql/ssms/visual-db-tools/walkthrough-adding-and-changing-a-database-diagram?view=sql-server-ver16>
-    Standard = 4,
+    /// The columns of [`TableView::Custom`] can be changed for each table, the actual
+    /// columns of a table are stored in its [`TableViewMode`].
+    pub fn columns(self) -> &'static [PropViewColumn] {
+        use PropViewColumn::*;
+        match self {
+            TableView::Standard => &[ColumnName, DataType, AllowNulls],
+            TableView::ColumnNames | TableView::Keys => &[ColumnName],
+            TableView::NameOnly => &[],
+            TableView::Custom => &[ColumnName, CondensedType, Nullable],
+        }
+    }
+}
+
+/// The columns of a [`TableView`] of one table
+///
+/// This is the `TableViewMode:N` property of the `\3DdsStream`, a count followed by
+/// pairs of a [`PropViewColumn`] and its width in twips. The first pair is the icon
+/// column, e.g. `4,0,284,0,1980,12,2340,11,1440` for [`TableView::Custom`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TableViewMode {
+    /// Width of the icon column (in twips)
+    pub icon_width: u32,
+    /// The visible columns and their width (in twips), in display order
+    pub columns: Vec<(PropViewColumn, u32)>,
+}
+
+impl TableViewMode {
+    /// Parse the value of a `TableViewMode:N` property
+    pub fn parse(value: &str) -> Option<Self> {
+        let numbers: Vec<u32> = value
+            .split(',')
+            .map(|n| n.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let (&count, pairs) = numbers.split_first()?;
+        if pairs.len() % 2 != 0 || pairs.len() / 2 != count as usize {
+            return None;
+        }
+        let mut pairs = pairs.chunks_exact(2);
+        let icon_width = pairs.next()?[1];
+        let columns = pairs
+            .map(|pair| Some((PropViewColumn::from_u32(pair[0])?, pair[1])))
+            .collect::<Option<_>>()?;
+        Some(Self {
+            icon_width,
+            columns,
+        })
+    }
+}
+
+impl fmt::Display for TableViewMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},0,{}", self.columns.len() + 1, self.icon_width)?;
+        for &(column, width) in &self.columns {
+            write!(f, ",{},{}", column as u32, width)?;
+        }
+        Ok(())
+    }
 }
 
 /// Columns that can be shown for a table
 ///
+/// The [`GridSpec`] for [`TableView::Standard`] has one width for each of the
+/// first eleven columns (after the icon column), [`DataSource::column_selection`]
+/// lists their indices.
+///
 /// See:
 /// - <https://stackoverflow.com/a/10538313>
 /// - <https://dataedo.com/kb/tools/ssms/how-to-view-and-edit-table-and-column-comments>
 /// - <https://www.west-wind.com/WebLog/images/200701/WindowsLiveWriter/AneasierViewforRecordDesigninSqlServerSt_103BE/ColumnSelection_2.png>
+#[derive(Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PropViewColumn {
-    ColumnName,
-    DataType,
-    Length,
-    Precision,
-    Scale,
-    AllowNulls,
-    DefaultValue,
-    Identity,
-    IdentitySeed,
-    IdentityIncrement,
-    RowGUID,
-    Nullable,
-    CondensedType,
-    NotForReplication,
-    Formula,
-    Collation,
-    Description,
+    ColumnName = 0,
+    DataType = 1,
+    Length = 2,
+    Precision = 3,
+    Scale = 4,
+    AllowNulls = 5,
+    DefaultValue = 6,
+    Identity = 7,
+    IdentitySeed = 8,
+    IdentityIncrement = 9,
+    RowGUID = 10,
+    Nullable = 11,
+    CondensedType = 12,
+    NotForReplication = 13,
+    Formula = 14,
+    Collation = 15,
+    Description = 16,
+}
+
+impl PropViewColumn {
+    /// The column header in the grid
+    pub fn title(self) -> &'static str {
+        match self {
+            PropViewColumn::ColumnName => "Column Name",
+            PropViewColumn::DataType => "Data Type",
+            PropViewColumn::Length => "Length",
+            PropViewColumn::Precision => "Precision",
+            PropViewColumn::Scale => "Scale",
+            PropViewColumn::AllowNulls => "Allow Nulls",
+            PropViewColumn::DefaultValue => "Default Value",
+            PropViewColumn::Identity => "Identity",
+            PropViewColumn::IdentitySeed => "Identity Seed",
+            PropViewColumn::IdentityIncrement => "Identity Increment",
+            PropViewColumn::RowGUID => "RowGuid",
+            PropViewColumn::Nullable => "Nullable",
+            PropViewColumn::CondensedType => "Condensed Type",
+            PropViewColumn::NotForReplication => "Not for Replication",
+            PropViewColumn::Formula => "Formula",
+            PropViewColumn::Collation => "Collation",
+            PropViewColumn::Description => "Description",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GridSpec {
     /// 1 for [`TableView::NameOnly`], 0 otherwise
     pub hidden: u32,
     /// Unknown
     pub(crate) v1: u32,
     /// The physical size of the grid frame
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::SizeDef"))]
     pub size: Size,
     /// Unknown
     pub(crate) v2: u32,
@@ -155,7 +284,7 @@ pub struct GridSpec {
     pub widths: Vec<u32>,
 }
 
-fn parse_grid_spec(input: &[u8]) -> IResult<&[u8], GridSpec> {
+fn parse_grid_spec(input: &[u8]) -> IResult<&[u8], GridSpec, Error> {
     let (input, (hidden, v1)) = le_u32_2(input)?;
     let (input, size) = Size::parse(input)?;
     let (input, v2) = le_u32(input)?;
@@ -179,21 +308,39 @@ fn parse_grid_spec(input: &[u8]) -> IResult<&[u8], GridSpec> {
     ))
 }
 
+/// Expect a `(minor, major)` version of `structure`
+fn parse_version(
+    structure: &'static str,
+    minor: u16,
+    major: u16,
+) -> impl Fn(&[u8]) -> IResult<&[u8], (u16, u16), Error> {
+    move |input: &[u8]| {
+        let (input, found) = pair(le_u16, le_u16)(input)?;
+        if found != (minor, major) {
+            return Err(nom::Err::Failure(Error::UnexpectedVersion {
+                structure,
+                expected: (minor, major),
+                found,
+            }));
+        }
+        Ok((input, found))
+    }
+}
+
 // See:
 // - <https://github.com/jandubois/win32-ole/blob/27570c90dcb3cf56ef815f668cc346dc0ac099a3/OLE.xs#L151>
 // - <https://github.com/LibreOffice/core/blob/b4e7ebebd583a2a3856231aead66d72d3bc1cb46/oox/source/ole/axcontrol.cxx#L722>
 const OLE_CONTROL_MAGIC: u32 = 0x1234_4321;
 
 // See: <https://github.com/LibreOffice/core/blob/b4e7ebebd583a2a3856231aead66d72d3bc1cb46/oox/source/ole/axcontrol.cxx#L720-L729>
-fn parse_ole_control_extent(input: &[u8]) -> IResult<&[u8], Size> {
+fn parse_ole_control_extent(input: &[u8]) -> IResult<&[u8], Size, Error> {
     let (input, _) = tag(OLE_CONTROL_MAGIC.to_le_bytes())(input)?;
-    let (input, (v_minor, v_major)) = pair(le_u16, le_u16)(input)?;
-    assert_eq!((v_minor, v_major), (8, 0));
+    let (input, _) = parse_version("OleControl", 8, 0)(input)?;
     let (input, size) = Size::parse(input)?;
     Ok((input, size))
 }
 
-fn _parse_data_source(input: &[u8]) -> IResult<&[u8], DataSource> {
+fn _parse_data_source(input: &[u8]) -> IResult<&[u8], DataSource, Error> {
     let (input, _cd3) = le_u32(input)?;
     let (input, _cd4) = le_u32(input)?;
     let (input, _d14) = length_count(le_u32, le_u32)(input)?;
@@ -211,18 +358,17 @@ fn _parse_data_source(input: &[u8]) -> IResult<&[u8], DataSource> {
     ))
 }
 
-fn parse_data_source(input: &[u8]) -> IResult<&[u8], DataSource> {
+fn parse_data_source(input: &[u8]) -> IResult<&[u8], DataSource, Error> {
     let (input, _) = tag(u32::to_le_bytes(0x1234_5678))(input)?;
-    let (input, (v_minor, v_major)) = pair(le_u16, le_u16)(input)?;
-    assert_eq!((v_minor, v_major), (4, 0));
+    let (input, _) = parse_version("DataSource", 4, 0)(input)?;
     length_value(le_u32, _parse_data_source)(input)
 }
 
-fn parse_grid_frame_wnd(input: &[u8]) -> IResult<&[u8], GridFrameWnd> {
+fn parse_grid_frame_wnd(input: &[u8]) -> IResult<&[u8], GridFrameWnd, Error> {
     let (input, _) = tag(u32::to_le_bytes(0x1234_5678))(input)?;
-    let (input, (v_minor, v_major)) = pair(le_u16, le_u16)(input)?;
-    assert_eq!((v_minor, v_major), (7, 0));
-    let (input, caption) = length_value(le_u32, parse_wstring_nt)(input)?;
+    let (input, _) = parse_version("GridFrameWnd", 7, 0)(input)?;
+    let (input, (caption, _caption_pad)) =
+        length_value(le_u32, pair(parse_wstring_nt, map(rest, BString::from)))(input)?;
 
     let (input, x1) = parse_grid_spec(input)?;
     let (input, cols) = parse_grid_spec(input)?;
@@ -233,12 +379,13 @@ fn parse_grid_frame_wnd(input: &[u8]) -> IResult<&[u8], GridFrameWnd> {
         input,
         GridFrameWnd {
             caption,
+            _caption_pad,
             layouts: Box::new([x1, cols, keys, x2, x3]),
         },
     ))
 }
 
-pub fn parse_sch_grid(input: &[u8]) -> IResult<&[u8], SchGrid> {
+pub fn parse_sch_grid(input: &[u8]) -> IResult<&[u8], SchGrid, Error> {
     let (input, extent) = parse_ole_control_extent(input)?;
     let (input, frame) = parse_grid_frame_wnd(input)?;
     let (input, data_source) = parse_data_source(input)?;
@@ -252,3 +399,130 @@ pub fn parse_sch_grid(input: &[u8]) -> IResult<&[u8], SchGrid> {
         },
     ))
 }
+
+fn write_grid_spec<W: Write>(w: &mut W, spec: &GridSpec) -> io::Result<()> {
+    write_u32(w, spec.hidden)?;
+    write_u32(w, spec.v1)?;
+    write_size(w, &spec.size)?;
+    write_u32(w, spec.v2)?;
+    write_u32(w, spec.row_max)?;
+    write_u32(w, spec.row_min)?;
+    write_u32(w, spec.col_max)?;
+    write_u32(w, spec.col_min)?;
+    for width in &spec.widths {
+        write_u32(w, *width)?;
+    }
+    Ok(())
+}
+
+fn write_ole_control_extent<W: Write>(w: &mut W, size: &Size) -> io::Result<()> {
+    write_u32(w, OLE_CONTROL_MAGIC)?;
+    write_u16(w, 8)?;
+    write_u16(w, 0)?;
+    write_size(w, size)
+}
+
+fn write_data_source<W: Write>(w: &mut W, data_source: &DataSource) -> io::Result<()> {
+    write_u32(w, 0x1234_5678)?;
+    write_u16(w, 4)?;
+    write_u16(w, 0)?;
+    write_length_value(w, |w| {
+        write_u32(w, data_source._cd3)?;
+        write_u32(w, data_source._cd4)?;
+        write_u32(w, length_prefix(data_source.column_selection.len())?)?;
+        for column in &data_source.column_selection {
+            write_u32(w, *column)?;
+        }
+        write_u32_wstring_nt(w, &data_source.schema)?;
+        write_u32_wstring_nt(w, &data_source.table)
+    })
+}
+
+fn write_grid_frame_wnd<W: Write>(w: &mut W, frame: &GridFrameWnd) -> io::Result<()> {
+    write_u32(w, 0x1234_5678)?;
+    write_u16(w, 7)?;
+    write_u16(w, 0)?;
+    write_length_value(w, |w| {
+        write_wstring_nt(w, &frame.caption)?;
+        w.write_all(&frame._caption_pad)
+    })?;
+    for layout in &frame.layouts[..] {
+        write_grid_spec(w, layout)?;
+    }
+    Ok(())
+}
+
+/// Inverse of [`parse_sch_grid`]
+pub fn write_sch_grid<W: Write>(w: &mut W, sch_grid: &SchGrid) -> io::Result<()> {
+    write_ole_control_extent(w, &sch_grid.extent)?;
+    write_grid_frame_wnd(w, &sch_grid.frame)?;
+    write_data_source(w, &sch_grid.data_source)
+}
+
+fn annotate_grid_spec(l: &mut Layout, spec: &GridSpec) -> Result<(), Error> {
+    l.known("hidden", |w| write_u32(w, spec.hidden))?;
+    l.unknown("v1", |w| write_u32(w, spec.v1))?;
+    l.known("size", |w| write_size(w, &spec.size))?;
+    l.unknown("v2", |w| write_u32(w, spec.v2))?;
+    l.known("row_max", |w| write_u32(w, spec.row_max))?;
+    l.known("row_min", |w| write_u32(w, spec.row_min))?;
+    l.known("col_max", |w| write_u32(w, spec.col_max))?;
+    l.known("col_min", |w| write_u32(w, spec.col_min))?;
+    for (i, width) in spec.widths.iter().enumerate() {
+        l.known(&format!("widths[{}]", i), |w| write_u32(w, *width))?;
+    }
+    Ok(())
+}
+
+/// Annotated version of [`write_sch_grid`]
+pub(crate) fn annotate_sch_grid(l: &mut Layout, sch_grid: &SchGrid) -> Result<(), Error> {
+    l.group("extent", |l| {
+        l.known("magic", |w| write_u32(w, OLE_CONTROL_MAGIC))?;
+        l.known("version", |w| {
+            write_u16(w, 8)?;
+            write_u16(w, 0)
+        })?;
+        l.known("size", |w| write_size(w, &sch_grid.extent))
+    })?;
+    let frame = &sch_grid.frame;
+    l.group("frame", |l| {
+        l.known("magic", |w| write_u32(w, 0x1234_5678))?;
+        l.known("version", |w| {
+            write_u16(w, 7)?;
+            write_u16(w, 0)
+        })?;
+        l.length_value("len", |l| {
+            l.known("caption", |w| write_wstring_nt(w, &frame.caption))?;
+            l.unknown("_caption_pad", |w| w.write_all(&frame._caption_pad))
+        })?;
+        for (i, layout) in frame.layouts.iter().enumerate() {
+            l.group(&format!("layouts[{}]", i), |l| {
+                annotate_grid_spec(l, layout)
+            })?;
+        }
+        Ok(())
+    })?;
+    let data_source = &sch_grid.data_source;
+    l.group("data_source", |l| {
+        l.known("magic", |w| write_u32(w, 0x1234_5678))?;
+        l.known("version", |w| {
+            write_u16(w, 4)?;
+            write_u16(w, 0)
+        })?;
+        l.length_value("len", |l| {
+            l.unknown("_cd3", |w| write_u32(w, data_source._cd3))?;
+            l.unknown("_cd4", |w| write_u32(w, data_source._cd4))?;
+            let count =
+                u32::try_from(data_source.column_selection.len()).map_err(Error::BufTooLong)?;
+            l.known("column_selection", |w| {
+                write_u32(w, count)?;
+                for column in &data_source.column_selection {
+                    write_u32(w, *column)?;
+                }
+                Ok(())
+            })?;
+            l.known("schema", |w| write_u32_wstring_nt(w, &data_source.schema))?;
+            l.known("table", |w| write_u32_wstring_nt(w, &data_source.table))
+        })
+    })
+}
@@ -1,6 +1,6 @@
 use encoding_rs::UTF_16LE;
 use nom::bytes::complete::{tag, take, take_until};
-use nom::combinator::{map, map_opt, recognize};
+use nom::combinator::{map, map_opt, recognize, verify};
 use nom::error::{FromExternalError, ParseError};
 use nom::multi::many_till;
 use nom::number::complete::{le_u16, le_u32};
@@ -8,13 +8,15 @@ use nom::sequence::pair;
 use nom::IResult;
 use std::borrow::Cow;
 
-fn decode_utf16(input: &[u8]) -> Option<String> {
+pub(crate) fn decode_utf16(input: &[u8]) -> Option<String> {
     UTF_16LE
         .decode_without_bom_handling_and_without_replacement(input)
         .map(Cow::into_owned)
 }
 
-pub(crate) fn parse_wstring_nt(input: &[u8]) -> IResult<&[u8], String> {
+pub(crate) fn parse_wstring_nt<'a, E: ParseError<&'a [u8]>>(
+    input: &'a [u8],
+) -> IResult<&'a [u8], String, E> {
     map_opt(
         map(
             recognize(many_till(le_u16, tag([0x00, 0x00]))),
@@ -24,7 +26,9 @@ pub(crate) fn parse_wstring_nt(input: &[u8]) -> IResult<&[u8], String> {
     )(input)
 }
 
-pub(crate) fn le_u32_2(input: &[u8]) -> IResult<&[u8], (u32, u32)> {
+pub(crate) fn le_u32_2<'a, E: ParseError<&'a [u8]>>(
+    input: &'a [u8],
+) -> IResult<&'a [u8], (u32, u32), E> {
     pair(le_u32, le_u32)(input)
 }
 
@@ -33,14 +37,16 @@ where
     E: ParseError<&'a [u8]>,
     E: FromExternalError<&'a [u8], Cow<'static, str>>,
 {
-    let (input, len) = le_u32(input)?;
+    let (input, len) = verify(le_u32, |len: &u32| *len >= 2)(input)?;
     let (input, string) = map_opt(take(len - 2), decode_utf16)(input)?;
     let (input, _) = tag([0x00, 0x00])(input)?;
     Ok((input, string))
 }
 
-pub(crate) fn parse_u32_wstring_nt(input: &[u8]) -> IResult<&[u8], String> {
-    let (input, len) = le_u32(input)?;
+pub(crate) fn parse_u32_wstring_nt<'a, E: ParseError<&'a [u8]>>(
+    input: &'a [u8],
+) -> IResult<&'a [u8], String, E> {
+    let (input, len) = verify(le_u32, |len: &u32| (1..=u32::MAX / 2).contains(len))(input)?;
     let (input, string) = map_opt(take(len * 2 - 2), decode_utf16)(input)?;
     let (input, _) = tag([0x00, 0x00])(input)?;
     Ok((input, string))
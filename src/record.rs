@@ -0,0 +1,94 @@
+//! Rows of the `[dbo].[sysdiagrams]` table
+//!
+//! The AdventureWorks sample scripts ship the table as a pipe-separated `sysdiagrams.csv`
+//! (as produced by `bcp`), with one row per line:
+//!
+//! ```text
+//! name|principal_id|diagram_id|version|definition
+//! ```
+//!
+//! where `definition` is the hex encoded blob.
+
+use std::io::Cursor;
+
+use crate::{blob::decode_text, decode_hex, Error, SysDiagramFile};
+
+/// One row of the `sysdiagrams` table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagramRecord {
+    /// Name of the diagram
+    pub name: String,
+    /// Owner of the diagram
+    pub principal_id: i32,
+    /// Primary key of the row
+    pub diagram_id: i32,
+    /// Version of the diagram format (`NULL` for some old rows)
+    pub version: Option<i32>,
+    /// The binary `definition` blob
+    pub definition: Vec<u8>,
+}
+
+impl DiagramRecord {
+    /// Open the `definition` of the record
+    pub fn open(&self) -> std::io::Result<SysDiagramFile<Cursor<&[u8]>>> {
+        SysDiagramFile::open(Cursor::new(&self.definition[..]))
+    }
+
+    /// A unique file name for the diagram, e.g. `7_ProductInventory.sysdiagram`
+    ///
+    /// The name is prefixed with the `diagram_id`, so that rows with the same name
+    /// (for different owners) don't overwrite each other. Path separators, control
+    /// characters and the characters that Windows does not allow in file names
+    /// (`<>:"|?*`) are replaced by `_`.
+    pub fn file_name(&self) -> String {
+        let name: String = self
+            .name
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+        format!("{}_{}.sysdiagram", self.diagram_id, name)
+    }
+}
+
+fn parse_int(field: &str) -> Result<i32, Error> {
+    field.trim().parse().map_err(Error::InvalidInteger)
+}
+
+fn parse_record(line: &str) -> Result<DiagramRecord, Error> {
+    let fields: Vec<&str> = line.split('|').collect();
+    let [name, principal_id, diagram_id, version, definition] = fields[..] else {
+        return Err(Error::FieldCount(5, fields.len()));
+    };
+    let version = match version.trim() {
+        "" | "NULL" => None,
+        v => Some(parse_int(v)?),
+    };
+    Ok(DiagramRecord {
+        name: name.to_string(),
+        principal_id: parse_int(principal_id)?,
+        diagram_id: parse_int(diagram_id)?,
+        version,
+        definition: decode_hex(definition.as_bytes())?,
+    })
+}
+
+/// Parse a pipe-separated export of the `sysdiagrams` table
+///
+/// The input may be UTF-8 or UTF-16 with a byte order mark. Empty lines and a
+/// header row (`name|principal_id|...`) are skipped.
+pub fn parse_sysdiagrams_csv(input: &[u8]) -> Result<Vec<DiagramRecord>, Error> {
+    let text = decode_text(input)?;
+    let mut records = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with("name|principal_id|") {
+            continue;
+        }
+        let record = parse_record(line).map_err(|e| Error::Record(index + 1, Box::new(e)))?;
+        records.push(record);
+    }
+    Ok(records)
+}
@@ -0,0 +1,146 @@
+//! # Serde representations for foreign types
+//!
+//! Used with `#[serde(with = "...")]` on the fields that have types from `ms-oforms`
+//! or `bstr`:
+//!
+//! - [`Position`] and [`Size`] are structs of HIMETRIC values
+//! - [`OleColor`] is the `OLE_COLOR` value as an integer
+//! - [`StdFont`] is a struct with the fields of the persisted font
+//! - [`BString`] (unknown bytes) is a hex string
+
+use std::convert::TryFrom;
+
+use bstr::BString;
+use encoding_rs::mem::encode_latin1_lossy;
+use ms_oforms::properties::{
+    color::{parse_ole_color, OleColor},
+    font::{parse_std_font, StdFont},
+    Position, Size,
+};
+use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{decode_hex, writer::write_ole_color};
+
+// Only a template for the derives, never constructed
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Position")]
+pub(crate) struct PositionDef {
+    left: i32,
+    top: i32,
+}
+
+// Only a template for the derives, never constructed
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Size")]
+pub(crate) struct SizeDef {
+    width: u32,
+    height: u32,
+}
+
+pub(crate) mod positions {
+    use super::*;
+
+    struct Ref<'a>(&'a Position);
+
+    impl Serialize for Ref<'_> {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            PositionDef::serialize(self.0, s)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Owned(#[serde(with = "PositionDef")] Position);
+
+    pub(crate) fn serialize<S: Serializer>(v: &[Position], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(v.iter().map(Ref))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Position>, D::Error> {
+        let v = Vec::<Owned>::deserialize(d)?;
+        Ok(v.into_iter().map(|p| p.0).collect())
+    }
+}
+
+pub(crate) mod ole_color {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(color: &OleColor, s: S) -> Result<S::Ok, S::Error> {
+        let mut bytes = [0; 4];
+        write_ole_color(&mut &mut bytes[..], color).map_err(S::Error::custom)?;
+        s.serialize_u32(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<OleColor, D::Error> {
+        let bytes = u32::deserialize(d)?.to_le_bytes();
+        let result: nom::IResult<&[u8], OleColor> = parse_ole_color(&bytes);
+        result
+            .map(|(_, color)| color)
+            .map_err(|_| D::Error::custom("invalid OLE_COLOR"))
+    }
+}
+
+pub(crate) mod std_font {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Font {
+        face: String,
+        charset: u16,
+        flags: u8,
+        weight: u16,
+        /// In 1/10000 pt
+        height: u32,
+    }
+
+    pub(crate) fn serialize<S: Serializer>(font: &StdFont, s: S) -> Result<S::Ok, S::Error> {
+        Font {
+            face: font.font_face.clone(),
+            charset: font.charset,
+            flags: font.flags.bits(),
+            weight: font.weight,
+            height: font.height,
+        }
+        .serialize(s)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<StdFont, D::Error> {
+        let font = Font::deserialize(d)?;
+        let face = encode_latin1_lossy(&font.face);
+        let len = u8::try_from(face.len()).map_err(D::Error::custom)?;
+        // Build the persisted form, see `write_std_font`
+        let mut bytes = vec![0x01];
+        bytes.extend_from_slice(&font.charset.to_le_bytes());
+        bytes.push(font.flags);
+        bytes.extend_from_slice(&font.weight.to_le_bytes());
+        bytes.extend_from_slice(&font.height.to_le_bytes());
+        bytes.push(len);
+        bytes.extend_from_slice(&face);
+        let result: nom::IResult<&[u8], StdFont> = parse_std_font(&bytes);
+        result
+            .map(|(_, font)| font)
+            .map_err(|_| D::Error::custom("invalid StdFont"))
+    }
+}
+
+pub(crate) mod hex {
+    use std::fmt::Write;
+
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(bytes: &BString, s: S) -> Result<S::Ok, S::Error> {
+        let mut text = String::with_capacity(bytes.len() * 2);
+        for b in bytes.iter() {
+            write!(text, "{:02x}", b).map_err(S::Error::custom)?;
+        }
+        s.serialize_str(&text)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<BString, D::Error> {
+        let text = String::deserialize(d)?;
+        decode_hex(text.as_bytes())
+            .map(BString::from)
+            .map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,261 @@
+//! # DBML
+//!
+//! Exports a diagram as [DBML](https://dbml.dbdiagram.io/docs/) for dbdiagram.io.
+//!
+//! The position and size of every table (in HIMETRIC) is kept in its `Note`, as
+//! `sysdiagram pos=<left>,<top> size=<width>,<height>`.
+//!
+//! A `Ref` needs the columns on both sides, which a sysdiagram doesn't store. With a
+//! [`ColumnCatalog`], the primary key of the key side is matched by name on the other
+//! side. Where that fails, the tables get placeholder columns of type `unknown`: a
+//! `"(key)"` primary key on the key side, and a column named after the relationship
+//! on the other side.
+
+use std::io::{self, Write};
+
+use super::{line_tables, skipped_relationship};
+use crate::{Column, ColumnCatalog, Relationship, SysDiagram, Table};
+
+/// The name of the placeholder primary key column
+const KEY_PLACEHOLDER: &str = "(key)";
+
+/// The data type of placeholder columns
+const PLACEHOLDER_TYPE: &str = "unknown";
+
+/// Options for [`render_dbml`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DbmlOptions {
+    /// Column definitions for the tables and references
+    pub columns: ColumnCatalog,
+}
+
+/// Quote an identifier
+fn ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\\\""))
+}
+
+/// Quote a string
+fn string(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn table_name(table: &Table) -> String {
+    let data_source = &table.sch_grid.data_source;
+    format!(
+        "{}.{}",
+        ident(&data_source.schema),
+        ident(&data_source.table)
+    )
+}
+
+/// Write a column, marked `[pk]` if it is the only primary key column
+fn write_column<W: Write>(w: &mut W, column: &Column, single_key: bool) -> io::Result<()> {
+    write!(w, "  {} ", ident(&column.name))?;
+    if column.data_type.contains(char::is_whitespace) {
+        write!(w, "{}", ident(&column.data_type))?;
+    } else {
+        write!(w, "{}", column.data_type)?;
+    }
+    match (column.primary_key && single_key, column.nullable) {
+        (true, _) => writeln!(w, " [pk]"),
+        (false, false) => writeln!(w, " [not null]"),
+        (false, true) => writeln!(w),
+    }
+}
+
+fn write_table<W: Write>(
+    w: &mut W,
+    table: &Table,
+    placeholders: &[(i32, Column)],
+    options: &DbmlOptions,
+) -> io::Result<()> {
+    writeln!(w, "Table {} {{", table_name(table))?;
+    let placeholders = placeholders
+        .iter()
+        .filter(|(id, _)| *id == table.id)
+        .map(|(_, column)| column);
+    let columns: Vec<&Column> = options
+        .columns
+        .for_table(table)
+        .unwrap_or_default()
+        .iter()
+        .chain(placeholders)
+        .collect();
+    let key: Vec<String> = columns
+        .iter()
+        .filter(|c| c.primary_key)
+        .map(|c| ident(&c.name))
+        .collect();
+    for column in &columns {
+        write_column(w, column, key.len() == 1)?;
+    }
+    // A composite primary key is an index, not a column setting
+    if key.len() > 1 {
+        writeln!(w)?;
+        writeln!(w, "  indexes {{")?;
+        writeln!(w, "    ({}) [pk]", key.join(", "))?;
+        writeln!(w, "  }}")?;
+    }
+    let extent = table.sch_grid.extent;
+    let note = format!(
+        "sysdiagram pos={},{} size={},{}",
+        table.pos.left, table.pos.top, extent.width, extent.height
+    );
+    writeln!(w)?;
+    writeln!(w, "  Note: {}", string(&note))?;
+    writeln!(w, "}}")
+}
+
+/// The columns of a reference, as `"a"` or `("a", "b")`
+fn ref_columns(columns: &[String]) -> String {
+    let quoted: Vec<_> = columns.iter().map(|c| ident(c)).collect();
+    match &quoted[..] {
+        [column] => column.clone(),
+        _ => format!("({})", quoted.join(", ")),
+    }
+}
+
+/// A `Ref` from the columns of one table to the key columns of another
+struct Ref<'a> {
+    relationship: &'a Relationship,
+    key: (&'a Table, Vec<String>),
+    other: (&'a Table, Vec<String>),
+    op: &'static str,
+}
+
+impl<'a> Ref<'a> {
+    fn new(
+        relationship: &'a Relationship,
+        (src, dest): (&'a Table, &'a Table),
+        options: &DbmlOptions,
+    ) -> Self {
+        let line = &relationship.control;
+        let (src_end, dest_end) = (line.end_type_src, line.end_type_dest);
+
+        // The key side is marked on the line, or else the side that isn't "many"
+        let src_is_key = src_end.is_key() || (!dest_end.is_key() && dest_end.is_many());
+        let (key_table, key_end, other_table, other_end) = if src_is_key {
+            (src, src_end, dest, dest_end)
+        } else {
+            (dest, dest_end, src, src_end)
+        };
+        let op = match (other_end.is_many(), key_end.is_many()) {
+            (true, true) => "<>",
+            (true, false) => ">",
+            (false, true) => "<",
+            (false, false) => "-",
+        };
+
+        let mut key: Vec<String> = options
+            .columns
+            .for_table(key_table)
+            .unwrap_or_default()
+            .iter()
+            .filter(|c| c.primary_key)
+            .map(|c| c.name.clone())
+            .collect();
+        if key.is_empty() {
+            key.push(String::from(KEY_PLACEHOLDER));
+        }
+        let other = options.columns.for_table(other_table).unwrap_or_default();
+        let other = if key.iter().all(|k| other.iter().any(|c| c.name == *k)) {
+            key.clone()
+        } else {
+            let name = match relationship.name.as_str() {
+                "" => format!("FK_{}", relationship.id),
+                name => name.to_string(),
+            };
+            match &key[..] {
+                [_] => vec![name],
+                _ => key.iter().map(|k| format!("{}_{}", name, k)).collect(),
+            }
+        };
+        Self {
+            relationship,
+            key: (key_table, key),
+            other: (other_table, other),
+            op,
+        }
+    }
+
+    /// Add the columns of this reference that are missing from the catalog
+    fn add_placeholders(&self, placeholders: &mut Vec<(i32, Column)>, options: &DbmlOptions) {
+        let sides = [(&self.key, true), (&self.other, false)];
+        for ((table, columns), primary_key) in sides {
+            let known = options.columns.for_table(table).unwrap_or_default();
+            for name in columns {
+                let exists = known.iter().any(|c| c.name == *name)
+                    || placeholders
+                        .iter()
+                        .any(|(id, c)| *id == table.id && c.name == *name);
+                if !exists {
+                    let column = Column {
+                        name: name.clone(),
+                        data_type: String::from(PLACEHOLDER_TYPE),
+                        nullable: true,
+                        primary_key,
+                    };
+                    placeholders.push((table.id, column));
+                }
+            }
+        }
+    }
+
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "Ref")?;
+        if !self.relationship.name.is_empty() {
+            write!(w, " {}", ident(&self.relationship.name))?;
+        }
+        writeln!(
+            w,
+            ": {}.{} {} {}.{}",
+            table_name(self.other.0),
+            ref_columns(&self.other.1),
+            self.op,
+            table_name(self.key.0),
+            ref_columns(&self.key.1),
+        )
+    }
+}
+
+/// Render a diagram to a DBML string
+pub fn render_dbml(diagram: &SysDiagram, options: &DbmlOptions) -> String {
+    let mut buf = Vec::new();
+    write_dbml(&mut buf, diagram, options).expect("writing to a Vec can't fail");
+    String::from_utf8(buf).expect("DBML is valid UTF-8")
+}
+
+/// Export a diagram as DBML
+pub fn write_dbml<W: Write>(
+    w: &mut W,
+    diagram: &SysDiagram,
+    options: &DbmlOptions,
+) -> io::Result<()> {
+    if let Some(name) = diagram.name() {
+        writeln!(w, "Project {} {{", ident(name))?;
+        writeln!(w, "  database_type: 'SQL Server'")?;
+        writeln!(w, "}}")?;
+        writeln!(w)?;
+    }
+    let refs: Vec<_> = diagram
+        .relationships
+        .iter()
+        .map(|r| line_tables(diagram, r).map(|tables| Ref::new(r, tables, options)))
+        .collect();
+    let mut placeholders = Vec::new();
+    for r in refs.iter().flatten() {
+        r.add_placeholders(&mut placeholders, options);
+    }
+
+    for table in &diagram.tables {
+        write_table(w, table, &placeholders, options)?;
+        writeln!(w)?;
+    }
+    for (relationship, r) in diagram.relationships.iter().zip(&refs) {
+        match r {
+            Some(r) => r.write(w)?,
+            None => writeln!(w, "// {}", skipped_relationship(relationship))?,
+        }
+    }
+    Ok(())
+}
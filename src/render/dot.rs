@@ -0,0 +1,153 @@
+//! # Graphviz DOT
+//!
+//! Exports a diagram as a [DOT](https://graphviz.org/doc/info/lang.html) graph, with
+//! one HTML-label node per table and one edge per relationship.
+//!
+//! In the [pinned][DotOptions::pinned] mode, every node has a `pos="x,y!"` in points,
+//! so that `neato -n` keeps the layout from the diagram. Otherwise, Graphviz is free
+//! to lay out the graph, e.g. with `dot`.
+
+use std::io::{self, Write};
+
+use super::{line_tables, skipped_relationship, svg::Escape};
+use crate::{dds::DdsPolylineEndType, ColumnCatalog, SysDiagram, Table};
+
+/// Options for [`render_dot`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DotOptions {
+    /// Pin the nodes at their position on the diagram
+    pub pinned: bool,
+    /// Column definitions to list in the nodes
+    pub columns: ColumnCatalog,
+}
+
+/// Convert HIMETRIC to points (1/72 inch)
+fn himetric_to_pt(len: i32) -> f32 {
+    len as f32 * 72.0 / 2540.0
+}
+
+/// Convert HIMETRIC to inches
+fn u_himetric_to_in(len: u32) -> f32 {
+    len as f32 / 2540.0
+}
+
+/// The `arrowhead`/`arrowtail` for a line end
+fn arrow(end_type: DdsPolylineEndType) -> &'static str {
+    match end_type {
+        t if t.is_many() => "crow",
+        t if t.is_key() => "tee",
+        DdsPolylineEndType::LittleNub => "odot",
+        DdsPolylineEndType::RoundNub => "dot",
+        DdsPolylineEndType::SingleArrowFill => "normal",
+        DdsPolylineEndType::SingleArrow => "vee",
+        DdsPolylineEndType::OpenArrow => "onormal",
+        DdsPolylineEndType::DoubleArrow => "normalnormal",
+        DdsPolylineEndType::Diamond => "odiamond",
+        DdsPolylineEndType::DiamondFill => "diamond",
+        DdsPolylineEndType::DiamondArrow => "normalodiamond",
+        DdsPolylineEndType::DiamondFillArrow => "normaldiamond",
+        _ => "none",
+    }
+}
+
+/// Quote a string as a DOT ID
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn write_node<W: Write>(w: &mut W, table: &Table, options: &DotOptions) -> io::Result<()> {
+    let name = table.qualified_name();
+    write!(w, "    t{} [label=<", table.id)?;
+    write!(
+        w,
+        r#"<TABLE BORDER="0" CELLBORDER="1" CELLSPACING="0" CELLPADDING="4">"#
+    )?;
+    write!(
+        w,
+        r##"<TR><TD BGCOLOR="#dae6f6"><B>{}</B></TD></TR>"##,
+        Escape(&name)
+    )?;
+    for column in options.columns.for_table(table).unwrap_or_default() {
+        let (open, close) = if column.primary_key {
+            ("<U>", "</U>")
+        } else {
+            ("", "")
+        };
+        write!(
+            w,
+            r#"<TR><TD ALIGN="LEFT">{}{}{} {}</TD></TR>"#,
+            open,
+            Escape(&column.name),
+            close,
+            Escape(&column.data_type),
+        )?;
+    }
+    write!(w, "</TABLE>>, tooltip={}", quote(&table.caption))?;
+    if options.pinned {
+        // `pos` is the center, and the y axis of DOT points up
+        let extent = table.sch_grid.extent;
+        let x = himetric_to_pt(table.pos.left + extent.width as i32 / 2);
+        let y = -himetric_to_pt(table.pos.top + extent.height as i32 / 2);
+        write!(
+            w,
+            r#", pos="{},{}!", width={}, height={}, fixedsize=true"#,
+            x,
+            y,
+            u_himetric_to_in(extent.width),
+            u_himetric_to_in(extent.height),
+        )?;
+    }
+    writeln!(w, "];")
+}
+
+/// Render a diagram to a DOT string
+pub fn render_dot(diagram: &SysDiagram, options: &DotOptions) -> String {
+    let mut buf = Vec::new();
+    write_dot(&mut buf, diagram, options).expect("writing to a Vec can't fail");
+    String::from_utf8(buf).expect("DOT is valid UTF-8")
+}
+
+/// Export a diagram as a DOT graph
+pub fn write_dot<W: Write>(
+    w: &mut W,
+    diagram: &SysDiagram,
+    options: &DotOptions,
+) -> io::Result<()> {
+    writeln!(
+        w,
+        "digraph {} {{",
+        quote(diagram.name().unwrap_or("sysdiagram"))
+    )?;
+    if options.pinned {
+        writeln!(w, "    graph [layout=neato, splines=true];")?;
+    } else {
+        writeln!(w, "    graph [rankdir=LR];")?;
+    }
+    writeln!(w, "    node [shape=plain, fontname=Tahoma, fontsize=9];")?;
+    writeln!(w, "    edge [dir=both, fontname=Tahoma, fontsize=8];")?;
+
+    for table in &diagram.tables {
+        write_node(w, table, options)?;
+    }
+
+    for relationship in &diagram.relationships {
+        let (src, dest) = match line_tables(diagram, relationship) {
+            Some(tables) => tables,
+            None => {
+                writeln!(w, "    // {}", skipped_relationship(relationship))?;
+                continue;
+            }
+        };
+        let line = &relationship.control;
+        writeln!(
+            w,
+            "    t{} -> t{} [arrowtail={}, arrowhead={}, label={}];",
+            src.id,
+            dest.id,
+            arrow(line.end_type_src),
+            arrow(line.end_type_dest),
+            quote(&relationship.name),
+        )?;
+    }
+    writeln!(w, "}}")
+}
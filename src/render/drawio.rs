@@ -0,0 +1,272 @@
+//! # draw.io
+//!
+//! Exports a diagram as an uncompressed [draw.io](https://www.drawio.com/doc/faq/save-file-formats)
+//! (mxGraph) file. Every table, line and label keeps its geometry, converted from
+//! HIMETRIC to pixels at 96 DPI.
+
+use std::io::{self, Write};
+
+use ms_oforms::properties::{color::OleColor, Position};
+
+use super::{line_tables, rgb, skipped_relationship, svg::Escape, ROW_HEIGHT};
+use crate::{
+    dds::{DdsPolylineEndType, LabelFlags, LabelJustification},
+    mdtdb::TableView,
+    Annotation, ColumnCatalog, Relationship, SysDiagram, Table,
+};
+
+/// `FONTPERSIST_*` bits of the [`StdFont`](ms_oforms::properties::font::StdFont) flags
+const FONT_ITALIC: u8 = 0x02;
+const FONT_UNDERLINE: u8 = 0x04;
+const FONT_STRIKETHROUGH: u8 = 0x08;
+
+/// Options for [`render_drawio`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DrawioOptions {
+    /// Column definitions to add as rows of the tables
+    pub columns: ColumnCatalog,
+}
+
+fn himetric_to_px(len: i32) -> f32 {
+    len as f32 * 96.0 / 2540.0
+}
+
+fn u_himetric_to_px(len: u32) -> f32 {
+    len as f32 * 96.0 / 2540.0
+}
+
+/// Escape an attribute value, keeping line breaks
+fn attr(text: &str) -> String {
+    Escape(text)
+        .to_string()
+        .replace('\r', "")
+        .replace('\n', "&#xa;")
+}
+
+/// Text for an XML comment, which can't contain `--`
+fn comment(text: &str) -> String {
+    let mut text = text.to_string();
+    while text.contains("--") {
+        text = text.replace("--", "- -");
+    }
+    text
+}
+
+fn color(color: OleColor) -> String {
+    rgb(color).map_or_else(|| String::from("default"), |c| c.to_string())
+}
+
+/// The ER arrow style and fill for a line end
+fn arrow(end_type: DdsPolylineEndType) -> (&'static str, bool) {
+    match end_type {
+        t if t.is_many() => ("ERmany", false),
+        t if t.is_key() => ("ERmandOne", false),
+        DdsPolylineEndType::LittleNub => ("oval", false),
+        DdsPolylineEndType::RoundNub => ("oval", true),
+        DdsPolylineEndType::SingleArrowFill => ("block", true),
+        DdsPolylineEndType::SingleArrow => ("open", false),
+        DdsPolylineEndType::OpenArrow => ("block", false),
+        DdsPolylineEndType::DoubleArrow => ("doubleBlock", true),
+        DdsPolylineEndType::Diamond => ("diamond", false),
+        DdsPolylineEndType::DiamondFill => ("diamond", true),
+        DdsPolylineEndType::DiamondArrow => ("diamondThin", false),
+        DdsPolylineEndType::DiamondFillArrow => ("diamondThin", true),
+        _ => ("none", false),
+    }
+}
+
+fn write_point<W: Write>(w: &mut W, p: &Position, role: &str) -> io::Result<()> {
+    writeln!(
+        w,
+        r#"            <mxPoint x="{}" y="{}"{} />"#,
+        himetric_to_px(p.left),
+        himetric_to_px(p.top),
+        role
+    )
+}
+
+fn write_table<W: Write>(w: &mut W, table: &Table, options: &DrawioOptions) -> io::Result<()> {
+    let frame = &table.sch_grid.frame;
+    let extent = table.sch_grid.extent;
+    let width = u_himetric_to_px(extent.width);
+    let title_height = frame
+        .layout(TableView::NameOnly)
+        .size
+        .height
+        .min(extent.height);
+    writeln!(
+        w,
+        r#"        <mxCell id="t{}" value="{}" tooltip="{}" style="swimlane;fontStyle=1;fontFamily=Tahoma;childLayout=stackLayout;horizontal=1;startSize={};horizontalStack=0;resizeParent=1;collapsible=0;fillColor=#dae6f6;strokeColor=#7f9db9;" vertex="1" parent="1">"#,
+        table.id,
+        attr(&frame.caption),
+        attr(&table.caption),
+        u_himetric_to_px(title_height),
+    )?;
+    writeln!(
+        w,
+        r#"          <mxGeometry x="{}" y="{}" width="{}" height="{}" as="geometry" />"#,
+        himetric_to_px(table.pos.left),
+        himetric_to_px(table.pos.top),
+        width,
+        u_himetric_to_px(extent.height),
+    )?;
+    writeln!(w, "        </mxCell>")?;
+
+    let columns = options.columns.for_table(table).unwrap_or_default();
+    for (index, column) in columns.iter().enumerate() {
+        let key = if column.primary_key {
+            "fontStyle=4;"
+        } else {
+            ""
+        };
+        writeln!(
+            w,
+            r#"        <mxCell id="t{}c{}" value="{} {}" style="text;fontFamily=Tahoma;align=left;verticalAlign=middle;spacingLeft=4;{}" vertex="1" parent="t{}">"#,
+            table.id,
+            index,
+            attr(&column.name),
+            attr(&column.data_type),
+            key,
+            table.id,
+        )?;
+        writeln!(
+            w,
+            r#"          <mxGeometry y="{}" width="{}" height="{}" as="geometry" />"#,
+            u_himetric_to_px(title_height + index as u32 * ROW_HEIGHT),
+            width,
+            u_himetric_to_px(ROW_HEIGHT),
+        )?;
+        writeln!(w, "        </mxCell>")?;
+    }
+    Ok(())
+}
+
+fn write_edge<W: Write>(
+    w: &mut W,
+    relationship: &Relationship,
+    (src, dest): (&Table, &Table),
+) -> io::Result<()> {
+    let line = &relationship.control;
+    let (start_arrow, start_fill) = arrow(line.end_type_src);
+    let (end_arrow, end_fill) = arrow(line.end_type_dest);
+    writeln!(
+        w,
+        r#"        <mxCell id="r{}" value="" tooltip="{}" style="rounded=0;html=1;startArrow={};startFill={};endArrow={};endFill={};strokeColor={};" edge="1" parent="1" source="t{}" target="t{}">"#,
+        relationship.id,
+        attr(&relationship.caption),
+        start_arrow,
+        u8::from(start_fill),
+        end_arrow,
+        u8::from(end_fill),
+        color(line.color),
+        src.id,
+        dest.id,
+    )?;
+    writeln!(w, r#"          <mxGeometry relative="1" as="geometry">"#)?;
+    if let [first, waypoints @ .., last] = &line.positions[..] {
+        write_point(w, first, r#" as="sourcePoint""#)?;
+        write_point(w, last, r#" as="targetPoint""#)?;
+        writeln!(w, r#"            <Array as="points">"#)?;
+        for p in waypoints {
+            write_point(w, p, "")?;
+        }
+        writeln!(w, r#"            </Array>"#)?;
+    }
+    writeln!(w, r#"          </mxGeometry>"#)?;
+    writeln!(w, "        </mxCell>")
+}
+
+fn write_label<W: Write>(w: &mut W, annotation: &Annotation) -> io::Result<()> {
+    let label = &annotation.label;
+    let font = &label.font;
+    let align = match label.justification {
+        LabelJustification::Left => "left",
+        LabelJustification::Center => "center",
+        LabelJustification::Right => "right",
+    };
+    let mut font_style = 0;
+    if font.weight >= 700 {
+        font_style |= 1;
+    }
+    if font.flags.bits() & FONT_ITALIC != 0 {
+        font_style |= 2;
+    }
+    if font.flags.bits() & FONT_UNDERLINE != 0 {
+        font_style |= 4;
+    }
+    if font.flags.bits() & FONT_STRIKETHROUGH != 0 {
+        font_style |= 8;
+    }
+    let fill = if label.flags.contains(LabelFlags::TRANSPARENT) {
+        String::from("none")
+    } else {
+        color(label.back_color)
+    };
+    writeln!(
+        w,
+        r#"        <mxCell id="l{}" value="{}" style="text;html=0;whiteSpace=wrap;align={};verticalAlign=top;fontFamily={};fontSize={};fontStyle={};fontColor={};fillColor={};" vertex="1" parent="1">"#,
+        annotation.id,
+        attr(&label.text),
+        align,
+        attr(&font.font_face),
+        // `height` is in 1/10000 pt
+        font.height as f32 / 10000.0 * 96.0 / 72.0,
+        font_style,
+        color(label.fore_color),
+        fill,
+    )?;
+    writeln!(
+        w,
+        r#"          <mxGeometry x="{}" y="{}" width="{}" height="{}" as="geometry" />"#,
+        himetric_to_px(annotation.pos.left),
+        himetric_to_px(annotation.pos.top),
+        u_himetric_to_px(label.size.width),
+        u_himetric_to_px(label.size.height),
+    )?;
+    writeln!(w, "        </mxCell>")
+}
+
+/// Render a diagram to a draw.io string
+pub fn render_drawio(diagram: &SysDiagram, options: &DrawioOptions) -> String {
+    let mut buf = Vec::new();
+    write_drawio(&mut buf, diagram, options).expect("writing to a Vec can't fail");
+    String::from_utf8(buf).expect("draw.io XML is valid UTF-8")
+}
+
+/// Export a diagram as a draw.io file
+pub fn write_drawio<W: Write>(
+    w: &mut W,
+    diagram: &SysDiagram,
+    options: &DrawioOptions,
+) -> io::Result<()> {
+    let name = diagram.name().unwrap_or("sysdiagram");
+    writeln!(w, r#"<mxfile host="sysdiagram">"#)?;
+    writeln!(w, r#"  <diagram id="sysdiagram" name="{}">"#, attr(name))?;
+    writeln!(w, r#"    <mxGraphModel grid="1" gridSize="10" page="0">"#)?;
+    writeln!(w, "      <root>")?;
+    writeln!(w, r#"        <mxCell id="0" />"#)?;
+    writeln!(w, r#"        <mxCell id="1" parent="0" />"#)?;
+
+    for table in &diagram.tables {
+        write_table(w, table, options)?;
+    }
+    for relationship in &diagram.relationships {
+        match line_tables(diagram, relationship) {
+            Some(tables) => write_edge(w, relationship, tables)?,
+            None => writeln!(
+                w,
+                "        <!-- {} -->",
+                comment(&skipped_relationship(relationship))
+            )?,
+        }
+    }
+    let labels = diagram.relationships.iter().flat_map(|r| &r.labels);
+    for annotation in labels.chain(&diagram.labels) {
+        write_label(w, annotation)?;
+    }
+
+    writeln!(w, "      </root>")?;
+    writeln!(w, "    </mxGraphModel>")?;
+    writeln!(w, "  </diagram>")?;
+    writeln!(w, "</mxfile>")
+}
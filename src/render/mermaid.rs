@@ -0,0 +1,74 @@
+//! # Mermaid
+//!
+//! Exports a diagram as a Mermaid [`erDiagram`](https://mermaid.js.org/syntax/entityRelationshipDiagram.html),
+//! with one entity per table and one relationship per line. Positions are not kept,
+//! Mermaid computes its own layout.
+
+use std::io::{self, Write};
+
+use super::{ie_left, ie_right, line_tables, skipped_relationship};
+use crate::{SysDiagram, Table};
+
+/// Mermaid entity IDs may only contain alphanumerics, `-` and `_`
+///
+/// The table ID keeps names apart that only differ in other characters,
+/// e.g. `dbo.A B` and `dbo.A_B`.
+fn entity_id(table: &Table) -> String {
+    let name: String = table
+        .qualified_name()
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}_{}", name, table.id)
+}
+
+/// Quoted strings can't contain `"`, which needs an entity code
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "#quot;"))
+}
+
+/// Render a diagram to a Mermaid string
+pub fn render_mermaid(diagram: &SysDiagram) -> String {
+    let mut buf = Vec::new();
+    write_mermaid(&mut buf, diagram).expect("writing to a Vec can't fail");
+    String::from_utf8(buf).expect("Mermaid is valid UTF-8")
+}
+
+/// Export a diagram as a Mermaid `erDiagram`
+pub fn write_mermaid<W: Write>(w: &mut W, diagram: &SysDiagram) -> io::Result<()> {
+    if let Some(name) = diagram.name() {
+        writeln!(w, "---")?;
+        writeln!(w, "title: {}", quote(name))?;
+        writeln!(w, "---")?;
+    }
+    writeln!(w, "erDiagram")?;
+
+    for table in &diagram.tables {
+        let name = table.qualified_name();
+        writeln!(w, "    {}[{}]", entity_id(table), quote(&name))?;
+    }
+
+    for relationship in &diagram.relationships {
+        let (src, dest) = match line_tables(diagram, relationship) {
+            Some(tables) => tables,
+            None => {
+                writeln!(w, "    %% {}", skipped_relationship(relationship))?;
+                continue;
+            }
+        };
+        let line = &relationship.control;
+        writeln!(
+            w,
+            "    {} {}--{} {} : {}",
+            entity_id(src),
+            ie_left(line.end_type_src),
+            ie_right(line.end_type_dest),
+            entity_id(dest),
+            quote(&relationship.name),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+//! # Rendering diagrams
+//!
+//! All coordinates in a sysdiagram are in HIMETRIC units, i.e. 1/100 mm.
+//! The renderers in this module work in millimeters.
+
+use ms_oforms::properties::{
+    color::{OleColor, RgbColor},
+    Position, Size,
+};
+
+use crate::{dds::DdsPolylineEndType, Relationship, SysDiagram, Table};
+
+pub mod dbml;
+pub mod dot;
+pub mod drawio;
+pub mod mermaid;
+pub mod plantuml;
+pub mod svg;
+
+/// Height of a grid row (in HIMETRIC), as observed on tables in the
+/// [`TableView::Keys`](crate::mdtdb::TableView::Keys) view
+pub(crate) const ROW_HEIGHT: u32 = 529;
+
+pub(crate) fn himetric_to_mm(len: i32) -> f32 {
+    len as f32 / 100.0
+}
+
+pub(crate) fn u_himetric_to_mm(len: u32) -> f32 {
+    len as f32 / 100.0
+}
+
+pub(crate) fn pos_himetric_to_mm(p: &Position) -> (f32, f32) {
+    (himetric_to_mm(p.left), himetric_to_mm(p.top))
+}
+
+pub(crate) fn size_himetric_to_mm(size: Size) -> (f32, f32) {
+    (u_himetric_to_mm(size.width), u_himetric_to_mm(size.height))
+}
+
+/// Resolve an [`OleColor`] to RGB, if possible without a palette
+pub fn rgb(color: OleColor) -> Option<RgbColor> {
+    match color {
+        OleColor::Default(d) | OleColor::RgbColor(d) => Some(d),
+        OleColor::SystemPalette(p) => p.as_system_color().map(RgbColor::from),
+        OleColor::PaletteEntry(_) => None,
+    }
+}
+
+fn rect(pos: &Position, size: &Size) -> (i32, i32, i32, i32) {
+    (
+        pos.left,
+        pos.top,
+        pos.left + size.width as i32,
+        pos.top + size.height as i32,
+    )
+}
+
+/// The smallest rectangle `(left, top, right, bottom)` in HIMETRIC that contains
+/// all tables, labels and relationship lines of a diagram
+pub fn bounds(diagram: &SysDiagram) -> Option<(i32, i32, i32, i32)> {
+    let tables = diagram
+        .tables
+        .iter()
+        .map(|t| rect(&t.pos, &t.sch_grid.extent));
+    let labels = diagram
+        .relationships
+        .iter()
+        .flat_map(|r| &r.labels)
+        .chain(&diagram.labels)
+        .map(|a| rect(&a.pos, &a.label.size));
+    let points = diagram
+        .relationships
+        .iter()
+        .flat_map(|r| &r.control.positions)
+        .map(|p| (p.left, p.top, p.left, p.top));
+
+    tables
+        .chain(labels)
+        .chain(points)
+        .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+}
+
+/// The Information Engineering (crow's foot) marker on the left side of `--`,
+/// as used by Mermaid and PlantUML
+pub(crate) fn ie_left(end_type: DdsPolylineEndType) -> &'static str {
+    if end_type.is_many() {
+        "}o"
+    } else if end_type.is_key() {
+        "||"
+    } else {
+        "|o"
+    }
+}
+
+/// The mirror image of [`ie_left`] for the right side of `--`
+pub(crate) fn ie_right(end_type: DdsPolylineEndType) -> &'static str {
+    if end_type.is_many() {
+        "o{"
+    } else if end_type.is_key() {
+        "||"
+    } else {
+        "o|"
+    }
+}
+
+/// A note for a relationship that [`line_tables`] can't place, which the exporters
+/// write as a comment instead of the line
+pub(crate) fn skipped_relationship(relationship: &Relationship) -> String {
+    format!(
+        "relationship {:?} skipped, no table {:?} or {:?} in the diagram",
+        relationship.name, relationship.from, relationship.to
+    )
+}
+
+/// Squared distance from a point to a rectangle
+fn distance(p: &Position, (left, top, right, bottom): (i32, i32, i32, i32)) -> i64 {
+    let dx = i64::from((left - p.left).max(p.left - right).max(0));
+    let dy = i64::from((top - p.top).max(p.top - bottom).max(0));
+    dx * dx + dy * dy
+}
+
+/// The tables at the first (source) and last (destination) point of a relationship line
+///
+/// The tooltip of a relationship names the two tables, but not which end of the
+/// line belongs to which, so this picks the table closest to the first point.
+pub fn line_tables<'a>(
+    diagram: &'a SysDiagram,
+    relationship: &Relationship,
+) -> Option<(&'a Table, &'a Table)> {
+    let from = diagram.table_by_name(&relationship.from)?;
+    let to = diagram.table_by_name(&relationship.to)?;
+    let first = match relationship.control.positions.first() {
+        Some(p) => p,
+        None => return Some((from, to)),
+    };
+    let from_distance = distance(first, rect(&from.pos, &from.sch_grid.extent));
+    let to_distance = distance(first, rect(&to.pos, &to.sch_grid.extent));
+    if to_distance < from_distance {
+        Some((to, from))
+    } else {
+        Some((from, to))
+    }
+}
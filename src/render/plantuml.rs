@@ -0,0 +1,138 @@
+//! # PlantUML
+//!
+//! Exports a diagram as a PlantUML [entity relationship diagram](https://plantuml.com/ie-diagram),
+//! with the tables grouped in one `package` per schema. Labels that are not attached
+//! to a relationship become notes.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use super::{ie_left, ie_right, line_tables, skipped_relationship};
+use crate::{Column, ColumnCatalog, SysDiagram, Table};
+
+/// Options for [`render_plantuml`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlantUmlOptions {
+    /// Column definitions to list in the entities
+    pub columns: ColumnCatalog,
+}
+
+/// Keep text on one line, a line break would end the statement
+fn single_line(text: &str) -> String {
+    text.replace(|c: char| c.is_control(), " ")
+}
+
+/// Quote a name, PlantUML has no escape for `"`
+fn quote(text: &str) -> String {
+    format!("\"{}\"", single_line(text).replace('"', "'"))
+}
+
+/// A line of a note, which must not end the note or the diagram early
+///
+/// Lines that read `end note` or start with `@` (like `@enduml`) are escaped
+/// with PlantUML's `~`.
+fn note_line(text: &str) -> String {
+    let line = single_line(text);
+    let words: String = line.split_whitespace().collect();
+    if words.eq_ignore_ascii_case("endnote") || line.trim_start().starts_with('@') {
+        format!("~{}", line.trim_start())
+    } else {
+        line
+    }
+}
+
+fn write_column<W: Write>(w: &mut W, column: &Column) -> io::Result<()> {
+    let mandatory = if column.nullable { "" } else { "* " };
+    let (name, data_type) = (single_line(&column.name), single_line(&column.data_type));
+    writeln!(w, "    {}{} : {}", mandatory, name, data_type)
+}
+
+fn write_entity<W: Write>(w: &mut W, table: &Table, options: &PlantUmlOptions) -> io::Result<()> {
+    writeln!(
+        w,
+        "  entity {} as t{} {{",
+        quote(&table.qualified_name()),
+        table.id
+    )?;
+    if let Some(columns) = options.columns.for_table(table) {
+        let (keys, others): (Vec<_>, Vec<_>) = columns.iter().partition(|c| c.primary_key);
+        for column in &keys {
+            write_column(w, column)?;
+        }
+        if !keys.is_empty() && !others.is_empty() {
+            writeln!(w, "    --")?;
+        }
+        for column in others {
+            write_column(w, column)?;
+        }
+    }
+    writeln!(w, "  }}")
+}
+
+/// Render a diagram to a PlantUML string
+pub fn render_plantuml(diagram: &SysDiagram, options: &PlantUmlOptions) -> String {
+    let mut buf = Vec::new();
+    write_plantuml(&mut buf, diagram, options).expect("writing to a Vec can't fail");
+    String::from_utf8(buf).expect("PlantUML is valid UTF-8")
+}
+
+/// Export a diagram as a PlantUML entity diagram
+pub fn write_plantuml<W: Write>(
+    w: &mut W,
+    diagram: &SysDiagram,
+    options: &PlantUmlOptions,
+) -> io::Result<()> {
+    writeln!(w, "@startuml")?;
+    if let Some(name) = diagram.name() {
+        writeln!(w, "title {}", single_line(name))?;
+    }
+    writeln!(w, "hide circle")?;
+    writeln!(w, "skinparam linetype ortho")?;
+
+    let mut schemas: BTreeMap<&str, Vec<&Table>> = BTreeMap::new();
+    for table in &diagram.tables {
+        let schema = table.sch_grid.data_source.schema.as_str();
+        schemas.entry(schema).or_default().push(table);
+    }
+    for (schema, tables) in schemas {
+        writeln!(w)?;
+        writeln!(w, "package {} {{", quote(schema))?;
+        for table in tables {
+            write_entity(w, table, options)?;
+        }
+        writeln!(w, "}}")?;
+    }
+
+    writeln!(w)?;
+    for relationship in &diagram.relationships {
+        let (src, dest) = match line_tables(diagram, relationship) {
+            Some(tables) => tables,
+            None => {
+                writeln!(w, "' {}", skipped_relationship(relationship))?;
+                continue;
+            }
+        };
+        let line = &relationship.control;
+        writeln!(
+            w,
+            "t{} {}--{} t{} : {}",
+            src.id,
+            ie_left(line.end_type_src),
+            ie_right(line.end_type_dest),
+            dest.id,
+            quote(&relationship.name),
+        )?;
+    }
+
+    for annotation in &diagram.labels {
+        writeln!(w)?;
+        writeln!(w, "note as n{}", annotation.id)?;
+        for line in annotation.label.text.lines() {
+            writeln!(w, "  {}", note_line(line))?;
+        }
+        writeln!(w, "end note")?;
+    }
+    writeln!(w, "@enduml")
+}
@@ -0,0 +1,482 @@
+//! # SVG rendering
+//!
+//! The SVG uses millimeters as user units, so the `viewBox` matches the HIMETRIC
+//! coordinates of the diagram divided by 100.
+
+use std::{
+    convert::TryFrom,
+    fmt,
+    io::{self, Write},
+};
+
+use ms_oforms::properties::color::OleColor;
+
+use super::{bounds, pos_himetric_to_mm, rgb, size_himetric_to_mm, u_himetric_to_mm, ROW_HEIGHT};
+use crate::{
+    dds::DdsPolylineEndType,
+    mdtdb::{PropViewColumn, TableView},
+    Annotation, Column, ColumnCatalog, SysDiagram, Table,
+};
+
+/// Margin around the diagram (in mm)
+const MARGIN: f32 = 10.0;
+
+/// Font size of the grid text (in mm)
+const FONT_SIZE: f32 = 3.0;
+
+/// Colors used for the SVG output
+///
+/// Colors are CSS color values. Where a color is `None`, the color stored in
+/// the diagram is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub background: Option<String>,
+    pub table_stroke: String,
+    pub table_fill: String,
+    /// Fill of the title bar of a table
+    pub title_fill: String,
+    /// Lines between the rows and columns of a table
+    pub grid_stroke: String,
+    /// Primary key marker
+    pub key: String,
+    pub text: String,
+    pub line: Option<String>,
+    pub label_fill: Option<String>,
+    pub label_text: Option<String>,
+    /// Fill of the `Many` end of a relationship, with or without cascades
+    pub many_end: String,
+    /// Fill of the `Key` end of a relationship, with or without cascades
+    pub key_end: String,
+    /// Fill of all other ends of a relationship
+    pub other_end: String,
+}
+
+impl Theme {
+    /// Use the colors of the diagram
+    pub fn light() -> Self {
+        Self {
+            background: None,
+            table_stroke: String::from("#7f9db9"),
+            table_fill: String::from("#ffffff"),
+            title_fill: String::from("#dae6f6"),
+            grid_stroke: String::from("#d4d0c8"),
+            key: String::from("#e8b100"),
+            text: String::from("#000000"),
+            line: None,
+            label_fill: None,
+            label_text: None,
+            many_end: String::from("yellow"),
+            key_end: String::from("orange"),
+            other_end: String::from("black"),
+        }
+    }
+
+    /// Light on dark, ignoring the colors of the diagram
+    pub fn dark() -> Self {
+        Self {
+            background: Some(String::from("#1e1e1e")),
+            table_stroke: String::from("#d4d4d4"),
+            table_fill: String::from("#252526"),
+            title_fill: String::from("#37373d"),
+            grid_stroke: String::from("#3c3c3c"),
+            key: String::from("#dcdcaa"),
+            text: String::from("#d4d4d4"),
+            line: Some(String::from("#d4d4d4")),
+            label_fill: Some(String::from("#1e1e1e")),
+            label_text: Some(String::from("#d4d4d4")),
+            many_end: String::from("#dcdcaa"),
+            key_end: String::from("#ce9178"),
+            other_end: String::from("#d4d4d4"),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::light()
+    }
+}
+
+/// Options for [`render_svg`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgOptions {
+    /// Factor applied to the `width` and `height` of the document
+    pub scale: f32,
+    pub theme: Theme,
+    /// Add visual debugging nodes (anchor points, grid layout)
+    pub debug: bool,
+    /// Appended to every `font-family`, e.g. for viewers without `Tahoma`
+    pub font_fallback: String,
+    /// Column definitions to fill the table grids with
+    pub columns: ColumnCatalog,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            theme: Theme::default(),
+            debug: false,
+            font_fallback: String::from("sans-serif"),
+            columns: ColumnCatalog::default(),
+        }
+    }
+}
+
+impl SvgOptions {
+    fn font_family(&self, face: &str) -> String {
+        if self.font_fallback.is_empty() {
+            face.to_string()
+        } else {
+            format!("{}, {}", face, self.font_fallback)
+        }
+    }
+}
+
+/// Escapes text for use in XML content and attribute values
+pub(crate) struct Escape<'a>(pub &'a str);
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&apos;")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn css_color(theme: &Option<String>, color: OleColor, fallback: &str) -> String {
+    match (theme, rgb(color)) {
+        (Some(c), _) => c.clone(),
+        (None, Some(c)) => c.to_string(),
+        (None, None) => fallback.to_string(),
+    }
+}
+
+/// Render a diagram to an SVG string
+pub fn render_svg(diagram: &SysDiagram, options: &SvgOptions) -> String {
+    let mut buf = Vec::new();
+    write_svg(&mut buf, diagram, options).expect("writing to a Vec can't fail");
+    String::from_utf8(buf).expect("SVG is valid UTF-8")
+}
+
+/// Render a diagram as an SVG document
+pub fn write_svg<W: Write>(
+    w: &mut W,
+    diagram: &SysDiagram,
+    options: &SvgOptions,
+) -> io::Result<()> {
+    let theme = &options.theme;
+    let (left, top, right, bottom) = bounds(diagram).unwrap_or_default();
+    let (min_x, min_y) = (left as f32 / 100.0 - MARGIN, top as f32 / 100.0 - MARGIN);
+    let width = (right - left) as f32 / 100.0 + 2.0 * MARGIN;
+    let height = (bottom - top) as f32 / 100.0 + 2.0 * MARGIN;
+
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(w, r#"<svg xmlns="http://www.w3.org/2000/svg""#)?;
+    writeln!(w, r#"    xmlns:xlink="http://www.w3.org/1999/xlink""#)?;
+    writeln!(w, r#"    version="1.1" baseProfile="full""#)?;
+    writeln!(
+        w,
+        r#"    width="{}mm" height="{}mm""#,
+        width * options.scale,
+        height * options.scale
+    )?;
+    writeln!(
+        w,
+        r#"    viewBox="{} {} {} {}""#,
+        min_x, min_y, width, height
+    )?;
+    let background = css_color(&theme.background, diagram.back_color, "#ffffff");
+    writeln!(w, r#"    style="background-color: {}""#, background)?;
+    writeln!(w, ">")?;
+    if let Some(name) = diagram.name() {
+        writeln!(w, r#"    <title>{}</title>"#, Escape(name))?;
+    }
+    if options.debug {
+        writeln!(w, r#"<circle cx="0" cy="0" r="4" fill="red" />"#)?;
+    }
+
+    for table in &diagram.tables {
+        write_table(w, table, options)?;
+    }
+
+    for relationship in &diagram.relationships {
+        let line = &relationship.control;
+        if options.debug {
+            for label in &line.labels {
+                let (lx, ly) = pos_himetric_to_mm(&label.pos);
+                writeln!(w, r#"<circle cx="{}" cy="{}" r="4" fill="cyan" />"#, lx, ly)?;
+            }
+        }
+        write!(
+            w,
+            r#"<polyline stroke-width="1" id="c{}" fill="none" stroke="{}" points=""#,
+            relationship.id,
+            css_color(&theme.line, line.color, &theme.text),
+        )?;
+        for p in &line.positions {
+            let (x, y) = pos_himetric_to_mm(p);
+            write!(w, "{},{} ", x, y)?;
+        }
+        writeln!(w, "\">")?;
+        writeln!(w, "<title>{}</title>", Escape(&relationship.caption))?;
+        writeln!(w, "</polyline>")?;
+
+        let cap_color = |cap: DdsPolylineEndType| match cap {
+            t if t.is_many() => &theme.many_end,
+            t if t.is_key() => &theme.key_end,
+            _ => &theme.other_end,
+        };
+        let ends = [
+            (line.positions.first(), line.end_type_src),
+            (line.positions.last(), line.end_type_dest),
+        ];
+        for (pos, end_type) in ends {
+            if let Some(pos) = pos {
+                let (x, y) = pos_himetric_to_mm(pos);
+                writeln!(
+                    w,
+                    r#"<circle cx="{}" cy="{}" r="2" fill="{}" />"#,
+                    x,
+                    y,
+                    cap_color(end_type)
+                )?;
+            }
+        }
+
+        for annotation in &relationship.labels {
+            write_annotation(w, annotation, options, &background)?;
+        }
+    }
+
+    for annotation in &diagram.labels {
+        write_annotation(w, annotation, options, &background)?;
+    }
+
+    writeln!(w, "</svg>")
+}
+
+/// Convert a grid column width (in twips) to HIMETRIC
+fn twips_to_himetric(len: u32) -> u32 {
+    u32::try_from(u64::from(len) * 2540 / 1440).unwrap_or(u32::MAX)
+}
+
+fn cell_text(column: &Column, prop: PropViewColumn) -> &str {
+    match prop {
+        PropViewColumn::ColumnName => &column.name,
+        PropViewColumn::DataType | PropViewColumn::CondensedType => &column.data_type,
+        PropViewColumn::AllowNulls if column.nullable => "\u{2713}",
+        PropViewColumn::Nullable if column.nullable => "NULL",
+        PropViewColumn::Nullable => "NOT NULL",
+        _ => "",
+    }
+}
+
+/// Draw a [`SchGrid`](crate::SchGrid) like the database designer does for its [`TableView`]
+///
+/// The height of the title bar is the height of the [`TableView::NameOnly`] layout,
+/// every further row is [`ROW_HEIGHT`] high. The column widths come from the
+/// [`GridSpec`](crate::mdtdb::GridSpec) of the view.
+fn write_table<W: Write>(w: &mut W, table: &Table, options: &SvgOptions) -> io::Result<()> {
+    let theme = &options.theme;
+    let sch_grid = &table.sch_grid;
+    let view = table.view.unwrap_or(TableView::Standard);
+    let layout = sch_grid.frame.layout(view);
+    let font_family = options.font_family("Tahoma");
+
+    let (x, y) = pos_himetric_to_mm(&table.pos);
+    let (width, height) = size_himetric_to_mm(sch_grid.extent);
+    let title_height =
+        u_himetric_to_mm(sch_grid.frame.layout(TableView::NameOnly).size.height).min(height);
+    let row_height = u_himetric_to_mm(ROW_HEIGHT);
+
+    writeln!(w, r#"<g id="c{}">"#, table.id)?;
+    writeln!(w, "<title>{}</title>", Escape(&table.caption))?;
+    writeln!(
+        w,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" stroke="{}" stroke-width="0.5" fill="{}" />"#,
+        x, y, width, height, theme.table_stroke, theme.table_fill,
+    )?;
+    writeln!(
+        w,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" stroke="{}" stroke-width="0.5" fill="{}" />"#,
+        x, y, width, title_height, theme.table_stroke, theme.title_fill,
+    )?;
+    writeln!(
+        w,
+        r#"<text x="{}" y="{}" font-size="{}" font-family="{}" fill="{}">{}</text>"#,
+        x + 1.0,
+        y + (title_height + FONT_SIZE) / 2.0,
+        FONT_SIZE,
+        Escape(&font_family),
+        theme.text,
+        Escape(&sch_grid.frame.caption),
+    )?;
+
+    // The columns and their widths are stored in the `TableViewMode` of the table.
+    // Without it, the grid widths of the Standard view are indexed by property, all
+    // others are in display order. The first width is the icon column.
+    let (icon_twips, columns) = match &table.view_mode {
+        Some(mode) => (mode.icon_width, mode.columns.clone()),
+        None => {
+            let width = |index: usize| layout.widths.get(index).copied().unwrap_or(0);
+            let columns = view
+                .columns()
+                .iter()
+                .enumerate()
+                .map(|(i, &prop)| match view {
+                    TableView::Standard => (prop, width(1 + prop as usize)),
+                    _ => (prop, width(1 + i)),
+                });
+            (width(0), columns.collect())
+        }
+    };
+    let grid_width = |twips: u32| u_himetric_to_mm(twips_to_himetric(twips));
+    let props: Vec<PropViewColumn> = columns.iter().map(|&(prop, _)| prop).collect();
+    let icon_width = grid_width(icon_twips);
+    let mut offsets = Vec::with_capacity(columns.len());
+    let mut offset = icon_width;
+    for &(_, twips) in &columns {
+        offsets.push(offset);
+        offset += grid_width(twips);
+    }
+
+    let mut row_y = y + title_height;
+    let bottom = y + height;
+    let mut row = |w: &mut W, texts: &[&str], key: bool| -> io::Result<bool> {
+        if row_y + row_height > bottom + 0.01 {
+            return Ok(false);
+        }
+        writeln!(
+            w,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="0.25" />"#,
+            x,
+            row_y + row_height,
+            x + width,
+            row_y + row_height,
+            theme.grid_stroke
+        )?;
+        if key {
+            writeln!(
+                w,
+                r#"<circle cx="{}" cy="{}" r="{}" fill="{}" />"#,
+                x + icon_width / 2.0,
+                row_y + row_height / 2.0,
+                icon_width.min(row_height) / 4.0,
+                theme.key
+            )?;
+        }
+        for (col_x, &text) in offsets.iter().zip(texts) {
+            if !text.is_empty() {
+                writeln!(
+                    w,
+                    r#"<text x="{}" y="{}" font-size="{}" font-family="{}" fill="{}">{}</text>"#,
+                    x + col_x + 0.5,
+                    row_y + row_height * 0.75,
+                    FONT_SIZE,
+                    Escape(&font_family),
+                    theme.text,
+                    Escape(text),
+                )?;
+            }
+        }
+        row_y += row_height;
+        Ok(true)
+    };
+
+    if props.len() > 1 {
+        let titles: Vec<&str> = props.iter().map(|p| p.title()).collect();
+        row(w, &titles, false)?;
+    }
+    match options.columns.for_table(table) {
+        Some(table_columns) => {
+            let visible = table_columns
+                .iter()
+                .filter(|c| view != TableView::Keys || c.primary_key);
+            for column in visible {
+                let texts: Vec<&str> = props.iter().map(|&p| cell_text(column, p)).collect();
+                if !row(w, &texts, column.primary_key)? {
+                    break;
+                }
+            }
+        }
+        None => {
+            // Without metadata, draw as many empty rows as the grid has
+            for _ in 0..layout.row_max {
+                if !row(w, &[], false)? {
+                    break;
+                }
+            }
+        }
+    }
+
+    for col_x in offsets.iter().skip(1) {
+        writeln!(
+            w,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="0.25" />"#,
+            x + col_x,
+            y + title_height,
+            x + col_x,
+            row_y,
+            theme.grid_stroke
+        )?;
+    }
+
+    if options.debug {
+        writeln!(w, r#"<circle cx="{}" cy="{}" r="2" fill="blue" />"#, x, y)?;
+        writeln!(
+            w,
+            r#"<text x="{}" y="{}" font-size="2" font-family="{}" fill="purple">{:?} ({}/{})</text>"#,
+            x + width + 1.0,
+            y + 2.0,
+            Escape(&font_family),
+            view,
+            layout.row_max,
+            layout.row_min,
+        )?;
+    }
+    writeln!(w, "</g>")
+}
+
+fn write_annotation<W: Write>(
+    w: &mut W,
+    annotation: &Annotation,
+    options: &SvgOptions,
+    background: &str,
+) -> io::Result<()> {
+    let theme = &options.theme;
+    let label = &annotation.label;
+    let (x, y) = pos_himetric_to_mm(&annotation.pos);
+    if options.debug {
+        writeln!(w, r#"<circle cx="{}" cy="{}" r="2" fill="red" />"#, x, y)?;
+    }
+    let (width, height) = size_himetric_to_mm(label.size);
+    writeln!(
+        w,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" />"#,
+        x,
+        y,
+        width,
+        height,
+        css_color(&theme.label_fill, label.back_color, background),
+    )?;
+    writeln!(
+        w,
+        r#"<text font-family="{}" fill="{}" font-size="{}" id="c{}" x="{}" y="{}">{}</text>"#,
+        Escape(&options.font_family(&label.font.font_face)),
+        css_color(&theme.label_text, label.fore_color, &theme.text),
+        8.25 * 0.35,
+        annotation.id,
+        x,
+        y + height * 0.8,
+        Escape(&label.text)
+    )
+}
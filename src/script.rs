@@ -0,0 +1,126 @@
+//! T-SQL scripts to store a diagram
+//!
+//! SSMS keeps diagrams in the `[dbo].[sysdiagrams]` table, which can be written
+//! directly or through the `sp_creatediagram` and `sp_alterdiagram` procedures that
+//! are installed along with it. The `definition` is emitted as a `0x...` literal.
+//! Large blobs are appended to a `varbinary(max)` variable in chunks, since very long
+//! lines tend to break editors and `sqlcmd`.
+
+use std::io::{self, Write};
+
+use crate::DiagramRecord;
+
+/// The statement that stores the diagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptStatement {
+    /// `INSERT INTO [dbo].[sysdiagrams]`
+    Insert,
+    /// `EXEC [dbo].[sp_creatediagram]`
+    CreateDiagram,
+    /// `EXEC [dbo].[sp_alterdiagram]`, to replace an existing diagram
+    AlterDiagram,
+}
+
+/// Options for [`write_sql_script`]
+#[derive(Debug, Clone)]
+pub struct ScriptOptions {
+    pub statement: ScriptStatement,
+    /// Maximum number of bytes per `0x...` literal
+    pub chunk_size: usize,
+}
+
+impl Default for ScriptOptions {
+    fn default() -> Self {
+        Self {
+            statement: ScriptStatement::Insert,
+            chunk_size: 4000,
+        }
+    }
+}
+
+/// A Unicode string literal, e.g. `N'It''s'`
+fn quote(text: &str) -> String {
+    format!("N'{}'", text.replace('\'', "''"))
+}
+
+fn write_hex<W: Write>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write!(w, "0x")?;
+    for b in bytes {
+        write!(w, "{:02X}", b)?;
+    }
+    Ok(())
+}
+
+/// Render the script for a record to a string, see [`write_sql_script`]
+pub fn render_sql_script(record: &DiagramRecord, options: &ScriptOptions) -> String {
+    let mut buf = Vec::new();
+    write_sql_script(&mut buf, record, options).expect("writing to a Vec can't fail");
+    String::from_utf8(buf).expect("T-SQL is valid UTF-8")
+}
+
+/// Write a T-SQL script that stores `record` in the `sysdiagrams` table
+///
+/// The name, `principal_id` (the owner), version and definition are used, the
+/// `diagram_id` is assigned by the server.
+pub fn write_sql_script<W: Write>(
+    w: &mut W,
+    record: &DiagramRecord,
+    options: &ScriptOptions,
+) -> io::Result<()> {
+    let chunk_size = options.chunk_size.max(1);
+    let chunked = record.definition.len() > chunk_size;
+    if chunked {
+        writeln!(w, "DECLARE @definition varbinary(max);")?;
+        for (i, chunk) in record.definition.chunks(chunk_size).enumerate() {
+            if i == 0 {
+                write!(w, "SET @definition = ")?;
+            } else {
+                write!(w, "SET @definition = @definition + ")?;
+            }
+            write_hex(w, chunk)?;
+            writeln!(w, ";")?;
+        }
+    }
+    let definition = |w: &mut W| {
+        if chunked {
+            write!(w, "@definition")
+        } else {
+            write_hex(w, &record.definition)
+        }
+    };
+    let version = match record.version {
+        Some(version) => version.to_string(),
+        None => String::from("NULL"),
+    };
+
+    match options.statement {
+        ScriptStatement::Insert => {
+            writeln!(
+                w,
+                "INSERT INTO [dbo].[sysdiagrams] ([name], [principal_id], [version], [definition])"
+            )?;
+            write!(
+                w,
+                "VALUES ({}, {}, {}, ",
+                quote(&record.name),
+                record.principal_id,
+                version
+            )?;
+            definition(w)?;
+            writeln!(w, ");")
+        }
+        ScriptStatement::CreateDiagram | ScriptStatement::AlterDiagram => {
+            let procedure = match options.statement {
+                ScriptStatement::AlterDiagram => "sp_alterdiagram",
+                _ => "sp_creatediagram",
+            };
+            writeln!(w, "EXEC [dbo].[{}]", procedure)?;
+            writeln!(w, "    @diagramname = {},", quote(&record.name))?;
+            writeln!(w, "    @owner_id = {},", record.principal_id)?;
+            writeln!(w, "    @version = {},", version)?;
+            write!(w, "    @definition = ")?;
+            definition(w)?;
+            writeln!(w, ";")
+        }
+    }
+}
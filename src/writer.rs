@@ -0,0 +1,136 @@
+use encoding_rs::mem::encode_latin1_lossy;
+use ms_oforms::properties::{
+    color::{OleColor, RgbColor},
+    font::StdFont,
+    Position, Size,
+};
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+    num::TryFromIntError,
+};
+use uuid::Uuid;
+
+/// Convert the length of a buffer for its length prefix, failing instead of truncating
+pub(crate) fn length_prefix<T>(len: usize) -> io::Result<T>
+where
+    T: TryFrom<usize, Error = TryFromIntError>,
+{
+    T::try_from(len).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+pub(crate) fn write_u8<W: Write>(w: &mut W, v: u8) -> io::Result<()> {
+    w.write_all(&[v])
+}
+
+pub(crate) fn write_u16<W: Write>(w: &mut W, v: u16) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_u32<W: Write>(w: &mut W, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_i32<W: Write>(w: &mut W, v: i32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_u64<W: Write>(w: &mut W, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write_guid<W: Write>(w: &mut W, v: &Uuid) -> io::Result<()> {
+    w.write_all(&v.to_bytes_le())
+}
+
+pub(crate) fn write_position<W: Write>(w: &mut W, pos: &Position) -> io::Result<()> {
+    write_i32(w, pos.left)?;
+    write_i32(w, pos.top)
+}
+
+pub(crate) fn write_size<W: Write>(w: &mut W, size: &Size) -> io::Result<()> {
+    write_u32(w, size.width)?;
+    write_u32(w, size.height)
+}
+
+fn encode_utf16(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Inverse of [`crate::parse_wstring_nt`]
+pub(crate) fn write_wstring_nt<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    w.write_all(&encode_utf16(s))?;
+    write_u16(w, 0)
+}
+
+/// Inverse of [`crate::parse_u32_bytes_wstring_nt`]
+pub(crate) fn write_u32_bytes_wstring_nt<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    let bytes = encode_utf16(s);
+    write_u32(w, length_prefix(bytes.len() + 2)?)?;
+    w.write_all(&bytes)?;
+    write_u16(w, 0)
+}
+
+/// Inverse of [`crate::parse_u32_wstring_nt`]
+pub(crate) fn write_u32_wstring_nt<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    let bytes = encode_utf16(s);
+    write_u32(w, length_prefix(bytes.len() / 2 + 1)?)?;
+    w.write_all(&bytes)?;
+    write_u16(w, 0)
+}
+
+/// Inverse of [`crate::parse_u16_wstring`]
+pub(crate) fn write_u16_wstring<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    let bytes = encode_utf16(s);
+    write_u16(w, length_prefix(bytes.len() / 2)?)?;
+    w.write_all(&bytes)
+}
+
+/// Write a `u32` length prefix followed by the bytes produced by `f`
+pub(crate) fn write_length_value<W, F>(w: &mut W, f: F) -> io::Result<()>
+where
+    W: Write,
+    F: FnOnce(&mut Vec<u8>) -> io::Result<()>,
+{
+    let mut buf = Vec::new();
+    f(&mut buf)?;
+    write_u32(w, length_prefix(buf.len())?)?;
+    w.write_all(&buf)
+}
+
+fn rgb_bytes(c: &RgbColor) -> [u8; 3] {
+    [c.red, c.green, c.blue]
+}
+
+/// Inverse of [`ms_oforms::properties::color::parse_ole_color`]
+///
+/// See: <https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-oforms/4b8f4be0-3fff-4e42-9fc1-b9fd00251e8e>
+pub(crate) fn write_ole_color<W: Write>(w: &mut W, color: &OleColor) -> io::Result<()> {
+    let (value, ty) = match color {
+        OleColor::Default(c) => (rgb_bytes(c), 0x00),
+        OleColor::PaletteEntry(e) => {
+            let [lo, hi] = u16::from(*e).to_le_bytes();
+            ([lo, hi, 0], 0x01)
+        }
+        OleColor::RgbColor(c) => (rgb_bytes(c), 0x02),
+        OleColor::SystemPalette(p) => {
+            let [lo, hi] = u16::from(*p).to_le_bytes();
+            ([lo, hi, 0], 0x80)
+        }
+    };
+    w.write_all(&[value[0], value[1], value[2], ty])
+}
+
+/// Inverse of [`ms_oforms::properties::font::parse_std_font`]
+///
+/// See: <https://learn.microsoft.com/en-us/openspecs/office_file_formats/ms-oforms/d2a1fbea-4b9a-4f55-9b4f-66a4c72d9b5b>
+pub(crate) fn write_std_font<W: Write>(w: &mut W, font: &StdFont) -> io::Result<()> {
+    let face = encode_latin1_lossy(&font.font_face);
+    write_u8(w, 0x01)?; // version
+    write_u16(w, font.charset)?;
+    write_u8(w, font.flags.bits())?;
+    write_u16(w, font.weight)?;
+    write_u32(w, font.height)?;
+    write_u8(w, length_prefix(face.len())?)?;
+    w.write_all(&face)
+}
@@ -0,0 +1,131 @@
+use std::{
+    io::Cursor,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use ms_oforms::properties::Position;
+use sysdiagram::{mdtdb::TableView, Error, SysDiagramBuilder, SysDiagramFile};
+
+mod common;
+
+fn pos(left: i32, top: i32) -> Position {
+    Position { left, top }
+}
+
+fn builder() -> SysDiagramBuilder {
+    let mut builder = SysDiagramBuilder::new(
+        "Data Source=.;Initial Catalog=Shop;Integrated Security=True",
+        "Orders",
+    );
+    builder
+        .table("dbo", "Customer", pos(1000, 1000), TableView::Standard)
+        .table("sales", "Order", pos(14000, 2000), TableView::ColumnNames)
+        .table("sales", "OrderLine", pos(14000, 12000), TableView::NameOnly)
+        .foreign_key("FK_Order_Customer", "Customer", "sales.Order")
+        .foreign_key("FK_OrderLine_Order", "Order (sales)", "OrderLine")
+        .timestamp(UNIX_EPOCH + Duration::from_secs(1_300_000_000));
+    builder
+}
+
+#[test]
+fn built_diagram_can_be_read() {
+    let bytes = builder().build().unwrap().to_bytes().unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(bytes)).unwrap();
+    let diagram = file.diagram().unwrap();
+
+    assert_eq!(diagram.name(), Some("Orders"));
+    assert_eq!(diagram.dsref_schema_contents.get_time(), 1_300_000_000);
+
+    let tables: Vec<_> = diagram
+        .tables
+        .iter()
+        .map(|t| (t.id, t.qualified_name(), t.caption.as_str(), t.view))
+        .collect();
+    assert_eq!(
+        tables,
+        [
+            (
+                1,
+                String::from("dbo.Customer"),
+                "Customer",
+                Some(TableView::Standard)
+            ),
+            (
+                2,
+                String::from("sales.Order"),
+                "Order (sales)",
+                Some(TableView::ColumnNames)
+            ),
+            (
+                3,
+                String::from("sales.OrderLine"),
+                "OrderLine (sales)",
+                Some(TableView::NameOnly)
+            ),
+        ]
+    );
+    let order = diagram.table(2).unwrap();
+    assert_eq!((order.pos.left, order.pos.top), (14000, 2000));
+
+    let relationships: Vec<_> = diagram
+        .relationships
+        .iter()
+        .map(|r| (r.name.as_str(), r.from.as_str(), r.to.as_str()))
+        .collect();
+    assert_eq!(
+        relationships,
+        [
+            ("FK_Order_Customer", "Customer", "Order (sales)"),
+            ("FK_OrderLine_Order", "Order (sales)", "OrderLine (sales)"),
+        ]
+    );
+    for relationship in &diagram.relationships {
+        assert_eq!(relationship.labels.len(), 1);
+        assert_eq!(relationship.labels[0].label.text, relationship.name);
+        assert!(relationship.control.positions.len() >= 2);
+    }
+    assert!(diagram.labels.is_empty());
+}
+
+#[test]
+fn built_diagram_is_written_again_unchanged() {
+    let bytes = builder().build().unwrap().to_bytes().unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(bytes.clone())).unwrap();
+    let written = file.streams().unwrap().to_bytes().unwrap();
+
+    let streams = common::read_streams(&bytes);
+    assert_eq!(streams.len(), 7);
+    assert_eq!(common::read_streams(&written), streams);
+}
+
+#[test]
+fn foreign_key_to_unknown_table() {
+    let mut builder = builder();
+    builder.foreign_key("FK_Order_Store", "Store", "Order");
+    match builder.build() {
+        Err(Error::UnknownTable(name)) => assert_eq!(name, "Store"),
+        other => panic!("expected an unknown table, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn foreign_key_to_ambiguous_table() {
+    let mut builder = builder();
+    builder
+        .table("dbo", "Order", pos(1000, 12000), TableView::NameOnly)
+        .foreign_key("FK_Order_Customer2", "Customer", "Order");
+    match builder.build() {
+        Err(Error::AmbiguousTable(name)) => assert_eq!(name, "Order"),
+        other => panic!("expected an ambiguous table, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn duplicate_table() {
+    let mut builder = builder();
+    builder.table("sales", "Order", pos(1000, 12000), TableView::NameOnly);
+    match builder.build() {
+        Err(Error::DuplicateTable(name)) => assert_eq!(name, "sales.Order"),
+        other => panic!("expected a duplicate table, got {:?}", other.map(|_| ())),
+    }
+}
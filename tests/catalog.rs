@@ -0,0 +1,44 @@
+use sysdiagram::{parse_column_catalog, Column, Error};
+
+fn column(name: &str, data_type: &str, nullable: bool, primary_key: bool) -> Column {
+    Column {
+        name: name.to_string(),
+        data_type: data_type.to_string(),
+        nullable,
+        primary_key,
+    }
+}
+
+#[test]
+fn parse_sqlcmd_export() {
+    let input = "\
+TABLE_SCHEMA|TABLE_NAME|COLUMN_NAME|DATA_TYPE|IS_NULLABLE|IS_PRIMARY_KEY
+------------|----------|-----------|---------|-----------|--------------
+dbo|DimGeography|GeographyKey|int|NO|1
+dbo|DimGeography|City|nvarchar(30)|YES|0
+
+sales|Order|Total|decimal(18, 2)|NO|0
+";
+    let catalog = parse_column_catalog(input.as_bytes()).unwrap();
+    assert_eq!(
+        catalog.get("dbo", "DimGeography").unwrap(),
+        [
+            column("GeographyKey", "int", false, true),
+            column("City", "nvarchar(30)", true, false),
+        ]
+    );
+    assert_eq!(
+        catalog.get("sales", "Order").unwrap(),
+        [column("Total", "decimal(18, 2)", false, false)]
+    );
+}
+
+#[test]
+fn invalid_flag_names_the_line() {
+    let input =
+        "dbo|DimGeography|GeographyKey|int|NO|1\ndbo|DimGeography|City|nvarchar(30)|maybe|0\n";
+    match parse_column_catalog(input.as_bytes()) {
+        Err(Error::Record(2, e)) => assert!(matches!(*e, Error::InvalidFlag(_))),
+        other => panic!("expected an invalid flag, got {:?}", other),
+    }
+}
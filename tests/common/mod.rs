@@ -0,0 +1,64 @@
+//! Helpers shared by the integration tests, each test uses only some of them
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use ms_oforms::properties::Position;
+use sysdiagram::{layout::Rect, SysDiagramFile};
+
+/// The path of a file in `res`
+pub fn res(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join(name)
+}
+
+/// The `Geography.sysdiagram` sample
+pub fn geography() -> SysDiagramFile<fs::File> {
+    SysDiagramFile::open(fs::File::open(res("Geography.sysdiagram")).unwrap()).unwrap()
+}
+
+/// The paths of all `.sysdiagram` samples in `res`
+pub fn samples() -> Vec<PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir(res(""))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |e| e == "sysdiagram"))
+        .collect();
+    assert!(!paths.is_empty());
+    paths.sort();
+    paths
+}
+
+/// All streams of a compound file with their path, sorted by path
+pub fn read_streams(bytes: &[u8]) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut cfb = cfb::CompoundFile::open(Cursor::new(bytes)).unwrap();
+    let paths: Vec<PathBuf> = cfb
+        .walk()
+        .filter(|entry| entry.is_stream())
+        .map(|entry| entry.path().to_path_buf())
+        .collect();
+    let mut streams: Vec<_> = paths
+        .into_iter()
+        .map(|path| {
+            let mut buf = Vec::new();
+            cfb.open_stream(&path)
+                .unwrap()
+                .read_to_end(&mut buf)
+                .unwrap();
+            (path, buf)
+        })
+        .collect();
+    streams.sort();
+    streams
+}
+
+/// Whether a point is on the border of a rectangle
+pub fn on_edge(p: &Position, r: &Rect) -> bool {
+    let x_in = r.left <= p.left && p.left <= r.right;
+    let y_in = r.top <= p.top && p.top <= r.bottom;
+    ((p.left == r.left || p.left == r.right) && y_in)
+        || ((p.top == r.top || p.top == r.bottom) && x_in)
+}
@@ -0,0 +1,86 @@
+use std::{fs, path::Path};
+
+use sysdiagram::dds::{
+    xml::{extract_layouts, parse_dds_xml, PROGID_POLYLINE},
+    DdsPolylineEndType,
+};
+
+#[test]
+fn parse_product_layout() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/Product.dds.xml");
+    let text = fs::read_to_string(path).unwrap();
+    let dds = parse_dds_xml(&text).unwrap();
+
+    assert_eq!(dds.diagram.version, 7);
+    assert_eq!(dds.diagram.zoom, 75);
+    assert_eq!(dds.diagram.font.as_ref().unwrap().font_face, "Tahoma");
+    assert_eq!(dds.controls.len(), 5);
+
+    let table = dds.control(1).unwrap();
+    assert_eq!(table.tooltip.as_deref(), Some("DimProductCategory"));
+    assert_eq!(table.logical_object(), Some("dbo_DimProductCategory"));
+    assert_eq!((table.pos.left, table.pos.top), (2870, -353));
+    assert_eq!((table.size.width, table.size.height), (6818, 3149));
+
+    let (line, connector) = dds.connections().next().unwrap();
+    assert_eq!(line.prog_id, PROGID_POLYLINE);
+    let polyline = line.polyline.as_ref().unwrap();
+    assert_eq!(polyline.end_type_src, DdsPolylineEndType::SingleArrowFill);
+    assert_eq!(polyline.end_type_dest, DdsPolylineEndType::None);
+    assert_eq!((connector.source_id, connector.dest_id), (1, 3));
+    assert_eq!(connector.points.len(), 4);
+}
+
+#[test]
+fn extract_ssas_layouts() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/Create.xml");
+    let text = fs::read_to_string(path).unwrap();
+    let layouts = extract_layouts(&text).unwrap();
+
+    let owners: Vec<_> = layouts
+        .iter()
+        .map(|l| (l.owner.kind.as_str(), l.owner.name.as_deref()))
+        .collect();
+    assert_eq!(
+        owners,
+        [
+            ("Dimension", Some("Date")),
+            ("Dimension", Some("Product")),
+            ("Cube", Some("AdventureWorksDW2008Cube")),
+            ("DataSourceView", Some("dsvAdventureWorksDW2008")),
+        ]
+    );
+    assert_eq!(layouts[0].owner.id.as_deref(), Some("Dim Date"));
+    assert_eq!(layouts[1].layout.controls.len(), 5);
+    assert_eq!(layouts[2].layout.controls.len(), 15);
+
+    let dsv = &layouts[3];
+    assert_eq!(dsv.owner.id.as_deref(), Some("dsvAdventureWorksDW2008"));
+    assert_eq!(dsv.designer.as_deref(), Some("_ALL_TABLES_"));
+    assert_eq!(dsv.layout.controls.len(), 15);
+}
+
+#[test]
+fn extract_ssis_layouts() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/LoadSales.dtsx");
+    let text = fs::read_to_string(path).unwrap();
+    let layouts = extract_layouts(&text).unwrap();
+    assert_eq!(layouts.len(), 1);
+
+    let layout = &layouts[0];
+    assert_eq!(layout.owner.kind, "SSIS.Package.2");
+    assert_eq!(
+        layout.owner.id.as_deref(),
+        Some("{8E2B6A1C-5D4F-4E3A-B7C9-1A2B3C4D5E6F}")
+    );
+    assert_eq!(layout.owner.name.as_deref(), Some("LoadSales"));
+    assert_eq!(layout.designer.as_deref(), Some("DtsControlFlowDiagram"));
+
+    let task = layout.layout.control(1).unwrap();
+    assert_eq!(task.tooltip.as_deref(), Some("Execute SQL Task"));
+    assert_eq!(
+        task.logical_object(),
+        Some("{4B9A2F4E-6C1D-4F5A-9E2B-7D3C8A1F0E21}")
+    );
+    assert_eq!((task.size.width, task.size.height), (3598, 1164));
+}
@@ -0,0 +1,66 @@
+use sysdiagram::{Control, SysDiagram};
+
+mod common;
+
+#[test]
+fn diagram_links_relationships_and_labels() {
+    let diagram = common::geography().diagram().unwrap();
+    let relationships: Vec<_> = diagram
+        .relationships
+        .iter()
+        .map(|r| (r.name.as_str(), r.from.as_str(), r.to.as_str()))
+        .collect();
+    assert_eq!(
+        relationships,
+        [
+            ("FK_DimCustomer_DimGeography", "DimGeography", "DimCustomer"),
+            (
+                "FK_DimGeography_DimSalesTerritory",
+                "DimSalesTerritory",
+                "DimGeography"
+            ),
+            ("FK_DimReseller_DimGeography", "DimGeography", "DimReseller"),
+        ]
+    );
+    for relationship in &diagram.relationships {
+        assert!(diagram.table_by_name(&relationship.from).is_some());
+        assert!(diagram.table_by_name(&relationship.to).is_some());
+        let ids: Vec<_> = relationship.labels.iter().map(|l| l.id as u32).collect();
+        let refs: Vec<_> = relationship.control.labels.iter().map(|l| l.id).collect();
+        assert_eq!(ids, refs);
+    }
+    for label in &diagram.labels {
+        let referenced = diagram
+            .relationships
+            .iter()
+            .any(|r| r.control.labels.iter().any(|l| l.id == label.id as u32));
+        assert!(!referenced);
+    }
+}
+
+#[test]
+fn diagram_keeps_relationships_with_other_tooltips() {
+    let mut file = common::geography();
+    let dsref_schema_contents = file.dsref_schema_contents().unwrap();
+    let (form_control, mut controls, dds_stream) = file.schema_form().unwrap();
+    let (site, _) = controls
+        .iter_mut()
+        .find(|(_, c)| matches!(c, Control::Polyline(_)))
+        .unwrap();
+    let tooltip =
+        "Beziehung 'FK_DimCustomer_DimGeography' zwischen 'DimGeography' und 'DimCustomer'";
+    site.tooltip = tooltip.to_string();
+    let id = site.id;
+
+    let diagram = SysDiagram::from_controls(
+        controls,
+        &dds_stream,
+        dsref_schema_contents,
+        form_control.back_color,
+    );
+    assert_eq!(diagram.relationships.len(), 3);
+    let relationship = diagram.relationships.iter().find(|r| r.id == id).unwrap();
+    assert_eq!(relationship.caption, tooltip);
+    assert!(relationship.name.is_empty());
+    assert!(relationship.from.is_empty() && relationship.to.is_empty());
+}
@@ -0,0 +1,283 @@
+use std::io::Cursor;
+
+use ms_oforms::properties::{Position, Size};
+use sysdiagram::{
+    dsref::DsRefNode, layout::Rect, mdtdb::TableView, Control, Error, SysDiagram, SysDiagramFile,
+    SysDiagramStreams,
+};
+
+mod common;
+
+fn reopen(streams: &SysDiagramStreams) -> SysDiagram {
+    let bytes = streams.to_bytes().unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(bytes)).unwrap();
+    file.diagram().unwrap()
+}
+
+fn table_names(diagram: &SysDiagram) -> Vec<String> {
+    diagram.tables.iter().map(|t| t.qualified_name()).collect()
+}
+
+/// `owner.name` of the `TABLE` nodes of the DSRef
+fn dsref_tables(diagram: &SysDiagram) -> Vec<String> {
+    let root = &diagram.dsref_schema_contents.root_node;
+    let tables: &[DsRefNode] = &root.children[0].children;
+    tables
+        .iter()
+        .map(|node| {
+            let owner = node.owner.as_deref().unwrap_or_default();
+            format!("{}.{}", owner, node.name.as_deref().unwrap_or_default())
+        })
+        .collect()
+}
+
+fn relationships(diagram: &SysDiagram) -> Vec<(&str, &str, &str)> {
+    diagram
+        .relationships
+        .iter()
+        .map(|r| (r.name.as_str(), r.from.as_str(), r.to.as_str()))
+        .collect()
+}
+
+#[test]
+fn add_table() {
+    let mut streams = common::geography().streams().unwrap();
+    let pos = Position {
+        left: 1000,
+        top: 30000,
+    };
+    let id = streams
+        .add_table("sales", "Store", pos, TableView::NameOnly)
+        .unwrap();
+    assert_eq!(id, 11);
+    assert_eq!(streams.form.next_available_id(), Some(11));
+    assert_eq!(streams.table_id("Store (sales)"), Some(11));
+
+    let diagram = reopen(&streams);
+    let expected = [
+        "dbo.DimGeography",
+        "dbo.DimCustomer",
+        "dbo.DimSalesTerritory",
+        "dbo.DimReseller",
+        "sales.Store",
+    ];
+    assert_eq!(table_names(&diagram), expected);
+    assert_eq!(dsref_tables(&diagram), expected);
+    let store = diagram.table(11).unwrap();
+    assert_eq!(store.caption, "Store (sales)");
+    assert_eq!(store.view, Some(TableView::NameOnly));
+
+    match streams.add_table("dbo", "DimReseller", pos, TableView::Standard) {
+        Err(Error::DuplicateTable(name)) => assert_eq!(name, "dbo.DimReseller"),
+        other => panic!("expected a duplicate table, got {:?}", other),
+    }
+}
+
+#[test]
+fn remove_table() {
+    let mut streams = common::geography().streams().unwrap();
+    streams.remove_table("DimReseller").unwrap();
+    let diagram = reopen(&streams);
+    let expected = [
+        "dbo.DimGeography",
+        "dbo.DimCustomer",
+        "dbo.DimSalesTerritory",
+    ];
+    assert_eq!(table_names(&diagram), expected);
+    assert_eq!(dsref_tables(&diagram), expected);
+    assert_eq!(
+        relationships(&diagram),
+        [
+            ("FK_DimCustomer_DimGeography", "DimGeography", "DimCustomer"),
+            (
+                "FK_DimGeography_DimSalesTerritory",
+                "DimSalesTerritory",
+                "DimGeography"
+            ),
+        ]
+    );
+    assert!(diagram.labels.is_empty());
+    assert_eq!(streams.dds_stream.numbers.len(), 10);
+
+    // All remaining relationships are attached to this table
+    streams.remove_table("dbo.DimGeography").unwrap();
+    let diagram = reopen(&streams);
+    assert_eq!(diagram.tables.len(), 2);
+    assert!(diagram.relationships.is_empty());
+    assert!(diagram.labels.is_empty());
+    assert!(streams.dds_stream.numbers.is_empty());
+    assert_eq!(streams.form.sites.len(), 2);
+
+    match streams.remove_table("DimGeography") {
+        Err(Error::UnknownTable(name)) => assert_eq!(name, "DimGeography"),
+        other => panic!("expected an unknown table, got {:?}", other),
+    }
+}
+
+#[test]
+fn remove_table_with_unpaired_numbers() {
+    let mut streams = common::geography().streams().unwrap();
+    streams.dds_stream.numbers.pop();
+    let sites = streams.form.sites.len();
+    match streams.remove_table("DimCustomer") {
+        Err(Error::DdsNumberCount(count)) => assert_eq!(count, 14),
+        other => panic!("expected a number count error, got {:?}", other),
+    }
+    assert_eq!(streams.form.sites.len(), sites);
+}
+
+#[test]
+fn rename_table() {
+    let mut streams = common::geography().streams().unwrap();
+    streams
+        .rename_table("DimGeography", "geo", "Location")
+        .unwrap();
+    assert_eq!(streams.table_id("geo.Location"), Some(1));
+    assert_eq!(streams.table_id("DimGeography"), None);
+
+    let diagram = reopen(&streams);
+    let expected = [
+        "geo.Location",
+        "dbo.DimCustomer",
+        "dbo.DimSalesTerritory",
+        "dbo.DimReseller",
+    ];
+    assert_eq!(table_names(&diagram), expected);
+    assert_eq!(dsref_tables(&diagram), expected);
+    let table = diagram.table(1).unwrap();
+    assert_eq!(table.caption, "Location (geo)");
+    assert_eq!(table.sch_grid.frame.caption, "Location (geo)");
+    assert_eq!(
+        relationships(&diagram),
+        [
+            (
+                "FK_DimCustomer_DimGeography",
+                "Location (geo)",
+                "DimCustomer"
+            ),
+            (
+                "FK_DimGeography_DimSalesTerritory",
+                "DimSalesTerritory",
+                "Location (geo)"
+            ),
+            (
+                "FK_DimReseller_DimGeography",
+                "Location (geo)",
+                "DimReseller"
+            ),
+        ]
+    );
+    for relationship in &diagram.relationships {
+        assert!(relationship.caption.ends_with("'\0"));
+    }
+
+    match streams.rename_table("DimCustomer", "dbo", "DimReseller") {
+        Err(Error::DuplicateTable(name)) => assert_eq!(name, "dbo.DimReseller"),
+        other => panic!("expected a duplicate table, got {:?}", other),
+    }
+}
+
+#[test]
+fn edits_keep_relationships_with_other_tooltips() {
+    let mut streams = common::geography().streams().unwrap();
+    let index = streams
+        .controls
+        .iter()
+        .position(|c| matches!(c, Control::Polyline(_)))
+        .unwrap();
+    let tooltip =
+        "Beziehung 'FK_DimCustomer_DimGeography' zwischen 'DimGeography' und 'DimCustomer'";
+    streams.form.sites[index].tooltip = tooltip.to_string();
+    let id = streams.form.sites[index].id;
+
+    let mut pos = streams.form.sites[0].pos;
+    pos.top += 3000;
+    streams.move_table("DimGeography", pos).unwrap();
+    streams
+        .rename_table("DimCustomer", "dbo", "Customer")
+        .unwrap();
+    streams.remove_table("DimReseller").unwrap();
+    let diagram = reopen(&streams);
+    assert_eq!(diagram.relationships.len(), 2);
+    let relationship = diagram.relationships.iter().find(|r| r.id == id).unwrap();
+    assert_eq!(relationship.caption, tooltip);
+}
+
+#[test]
+fn move_and_resize_table() {
+    let mut streams = common::geography().streams().unwrap();
+    let pos = Position {
+        left: 12600,
+        top: 20000,
+    };
+    streams.move_table("DimGeography", pos).unwrap();
+    let size = Size {
+        width: 6000,
+        height: 4000,
+    };
+    streams.resize_table("DimGeography", size).unwrap();
+
+    let diagram = reopen(&streams);
+    let table = diagram.table(1).unwrap();
+    assert_eq!((table.pos.left, table.pos.top), (12600, 20000));
+    let extent = table.sch_grid.extent;
+    assert_eq!((extent.width, extent.height), (6000, 4000));
+    for relationship in &diagram.relationships {
+        let positions = &relationship.control.positions;
+        let end = match relationship.from.as_str() {
+            "DimGeography" => &positions[0],
+            _ => &positions[positions.len() - 1],
+        };
+        assert!(
+            common::on_edge(end, &Rect::new(&pos, &size)),
+            "{:?}",
+            positions
+        );
+    }
+
+    // Lines of the tables that didn't move stay as they are
+    let lines = |streams: &SysDiagramStreams| -> Vec<(i32, Vec<Position>)> {
+        let sites = streams.form.sites.iter().zip(&streams.controls);
+        sites
+            .filter_map(|(site, control)| match control {
+                Control::Polyline(polyline) if !site.tooltip.contains("'DimReseller'") => {
+                    Some((site.id, polyline.positions.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    };
+    let before = lines(&streams);
+    assert_eq!(before.len(), 2);
+    let pos = Position {
+        left: 40000,
+        top: 40000,
+    };
+    streams.move_table("DimReseller", pos).unwrap();
+    assert_eq!(lines(&streams), before);
+}
+
+#[test]
+fn same_table_name_in_two_schemas() {
+    let mut streams = common::geography().streams().unwrap();
+    let pos = Position {
+        left: 1000,
+        top: 30000,
+    };
+    let id = streams
+        .add_table("sales", "DimCustomer", pos, TableView::Standard)
+        .unwrap();
+    assert_eq!(streams.table_id("sales.DimCustomer"), Some(id));
+    assert_eq!(streams.table_id("DimCustomer"), None);
+    match streams.move_table("DimCustomer", pos) {
+        Err(Error::AmbiguousTable(name)) => assert_eq!(name, "DimCustomer"),
+        other => panic!("expected an ambiguous table, got {:?}", other),
+    }
+    match streams.rename_table("DimCustomer", "dbo", "Customer") {
+        Err(Error::AmbiguousTable(name)) => assert_eq!(name, "DimCustomer"),
+        other => panic!("expected an ambiguous table, got {:?}", other),
+    }
+
+    streams.remove_table("dbo.DimCustomer").unwrap();
+    assert_eq!(streams.table_id("DimCustomer"), Some(id));
+}
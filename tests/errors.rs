@@ -0,0 +1,105 @@
+use std::{fs, path::Path};
+
+use sysdiagram::{
+    dds::{parse_dds_stream, parse_polyline, write_polyline},
+    form::{parse_form_stream, write_form_stream, SitePropMask},
+    mdtdb::{parse_sch_grid, write_sch_grid},
+    parse_variant, Error, SysDiagramFile, SysDiagramStreams,
+};
+
+fn open(name: &str) -> (SysDiagramFile<fs::File>, SysDiagramStreams) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join(name);
+    let mut file = SysDiagramFile::open(fs::File::open(path).unwrap()).unwrap();
+    let streams = file.streams().unwrap();
+    (file, streams)
+}
+
+#[test]
+fn truncated_dds_stream_is_an_error() {
+    let (mut file, streams) = open("Geography.sysdiagram");
+    let bytes = file.read_stream("/\x03DdsStream").unwrap();
+    let count = streams.controls.len();
+    assert!(parse_dds_stream(&bytes, count).is_ok());
+    for len in 0..bytes.len() {
+        assert!(parse_dds_stream(&bytes[..len], count).is_err(), "{}", len);
+    }
+}
+
+#[test]
+fn unsupported_variant_type_is_reported() {
+    match parse_variant::<Error>(&[0x0D, 0x00, 0x00, 0x00]) {
+        Err(nom::Err::Failure(Error::UnsupportedVarType(0x000D))) => {}
+        other => panic!("expected an unsupported type, got {:?}", other),
+    }
+
+    // The type of the first property in the header of the `\3DdsStream`
+    let (mut file, streams) = open("Geography.sysdiagram");
+    let mut bytes = file.read_stream("/\x03DdsStream").unwrap();
+    let key_len = u32::from_le_bytes([bytes[14], bytes[15], bytes[16], bytes[17]]) as usize;
+    let offset = 18 + key_len + 4;
+    bytes[offset..offset + 2].copy_from_slice(&0x4001u16.to_le_bytes());
+    match parse_dds_stream(&bytes, streams.controls.len()) {
+        Err(nom::Err::Failure(Error::UnsupportedVarType(0x4001))) => {}
+        other => panic!("expected an unsupported type, got {:?}", other),
+    }
+}
+
+#[test]
+fn truncated_polyline_is_an_error() {
+    let (mut file, _) = open("Geography.sysdiagram");
+    let diagram = file.diagram().unwrap();
+    let polyline = &diagram.relationships[0].control;
+    let mut bytes = Vec::new();
+    write_polyline(&mut bytes, polyline).unwrap();
+    let (_, parsed) = parse_polyline::<Error>(&bytes).unwrap();
+    assert_eq!(parsed.positions.len(), polyline.positions.len());
+    assert_eq!(parsed.labels.len(), polyline.labels.len());
+    // Everything up to the trailing bytes has a fixed size
+    let len = 4 + 8 * polyline.positions.len() + 28 + 4 + 24 * polyline.labels.len() + 1;
+    for len in 0..len {
+        assert!(parse_polyline::<Error>(&bytes[..len]).is_err(), "{}", len);
+    }
+}
+
+#[test]
+fn truncated_sch_grid_is_an_error() {
+    let (mut file, _) = open("Geography.sysdiagram");
+    let diagram = file.diagram().unwrap();
+    let mut bytes = Vec::new();
+    write_sch_grid(&mut bytes, &diagram.tables[0].sch_grid).unwrap();
+    assert!(parse_sch_grid(&bytes).is_ok());
+    for len in 0..bytes.len() {
+        assert!(parse_sch_grid(&bytes[..len]).is_err(), "{}", len);
+    }
+}
+
+#[test]
+fn unexpected_sch_grid_version_is_reported() {
+    let (mut file, _) = open("Geography.sysdiagram");
+    let diagram = file.diagram().unwrap();
+    let mut bytes = Vec::new();
+    write_sch_grid(&mut bytes, &diagram.tables[0].sch_grid).unwrap();
+    bytes[4] = 9;
+    match parse_sch_grid(&bytes) {
+        Err(nom::Err::Failure(Error::UnexpectedVersion {
+            structure: "OleControl",
+            expected: (8, 0),
+            found: (9, 0),
+        })) => {}
+        other => panic!("expected an unexpected version, got {:?}", other),
+    }
+}
+
+#[test]
+fn site_without_position_is_reported() {
+    let (_, mut streams) = open("Geography.sysdiagram");
+    let site = &mut streams.form.sites[0];
+    site.mask.remove(SitePropMask::POSITION);
+    let bits = site.mask.bits();
+    let mut bytes = Vec::new();
+    write_form_stream(&mut bytes, &streams.form).unwrap();
+    match parse_form_stream(&bytes) {
+        Err(nom::Err::Failure(Error::InvalidSitePropMask(found))) => assert_eq!(found, bits),
+        other => panic!("expected an invalid mask, got {:?}", other),
+    }
+}
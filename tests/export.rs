@@ -0,0 +1,277 @@
+use std::{fs, io::Cursor};
+
+use sysdiagram::{
+    parse_column_catalog,
+    render::{
+        dbml::{render_dbml, DbmlOptions},
+        dot::{render_dot, DotOptions},
+        drawio::{render_drawio, DrawioOptions},
+        mermaid::render_mermaid,
+        plantuml::{render_plantuml, PlantUmlOptions},
+    },
+    Column, ColumnCatalog, SysDiagram, SysDiagramFile,
+};
+
+mod common;
+
+fn diagrams() -> Vec<SysDiagram> {
+    common::samples()
+        .into_iter()
+        .map(|path| {
+            let bytes = fs::read(path).unwrap();
+            let mut file = SysDiagramFile::open(Cursor::new(bytes)).unwrap();
+            file.diagram().unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn mermaid_has_all_tables_and_relationships() {
+    for diagram in diagrams() {
+        let mermaid = render_mermaid(&diagram);
+        assert!(mermaid.lines().any(|l| l == "erDiagram"));
+        for table in &diagram.tables {
+            let ds = &table.sch_grid.data_source;
+            assert!(mermaid.contains(&format!("[\"{}.{}\"]", ds.schema, ds.table)));
+        }
+        for relationship in &diagram.relationships {
+            assert!(mermaid.contains(&format!(": \"{}\"", relationship.name)));
+        }
+    }
+}
+
+#[test]
+fn dot_pins_every_table() {
+    let options = DotOptions {
+        pinned: true,
+        ..DotOptions::default()
+    };
+    for diagram in diagrams() {
+        let dot = render_dot(&diagram, &options);
+        assert!(dot.starts_with("digraph "));
+        for table in &diagram.tables {
+            let node = dot
+                .lines()
+                .find(|l| l.starts_with(&format!("    t{} [", table.id)))
+                .unwrap();
+            assert!(node.contains("!\", width="));
+        }
+        let edges = dot.lines().filter(|l| l.contains(" -> ")).count();
+        assert!(edges <= diagram.relationships.len());
+    }
+}
+
+#[test]
+fn plantuml_groups_tables_by_schema() {
+    for diagram in diagrams() {
+        let uml = render_plantuml(&diagram, &PlantUmlOptions::default());
+        assert!(uml.starts_with("@startuml\n"));
+        assert!(uml.ends_with("@enduml\n"));
+        for table in &diagram.tables {
+            let schema = &table.sch_grid.data_source.schema;
+            assert!(uml.contains(&format!("package \"{}\" {{", schema)));
+            assert!(uml.contains(&format!(" as t{} {{", table.id)));
+        }
+        for annotation in &diagram.labels {
+            assert!(uml.contains(&format!("note as n{}\n", annotation.id)));
+        }
+    }
+}
+
+#[test]
+fn drawio_is_well_formed() {
+    for diagram in diagrams() {
+        let xml = render_drawio(&diagram, &DrawioOptions::default());
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let cells: Vec<_> = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("mxCell"))
+            .collect();
+        let vertices = cells
+            .iter()
+            .filter(|n| n.attribute("vertex") == Some("1"))
+            .count();
+        let labels = diagram
+            .relationships
+            .iter()
+            .map(|r| r.labels.len())
+            .sum::<usize>()
+            + diagram.labels.len();
+        assert_eq!(vertices, diagram.tables.len() + labels);
+        for edge in cells.iter().filter(|n| n.attribute("edge") == Some("1")) {
+            let source = edge.attribute("source").unwrap();
+            assert!(cells.iter().any(|n| n.attribute("id") == Some(source)));
+        }
+    }
+}
+
+#[test]
+fn dbml_notes_keep_positions() {
+    for diagram in diagrams() {
+        let dbml = render_dbml(&diagram, &DbmlOptions::default());
+        for table in &diagram.tables {
+            let extent = table.sch_grid.extent;
+            let note = format!(
+                "  Note: 'sysdiagram pos={},{} size={},{}'",
+                table.pos.left, table.pos.top, extent.width, extent.height
+            );
+            assert!(dbml.lines().any(|l| l == note));
+        }
+        let refs = dbml.lines().filter(|l| l.starts_with("Ref ")).count();
+        assert_eq!(refs, diagram.relationships.len());
+        assert!(!dbml.lines().any(|l| l.starts_with("//")), "{}", dbml);
+        if refs > 0 {
+            assert!(dbml.lines().any(|l| l == "  \"(key)\" unknown [pk]"));
+        }
+    }
+}
+
+#[test]
+fn dbml_refs_use_the_column_catalog() {
+    let diagram = diagrams()
+        .into_iter()
+        .find(|d| d.name() == Some("Geography"))
+        .unwrap();
+    let path = common::res("Geography.columns.txt");
+    let options = DbmlOptions {
+        columns: parse_column_catalog(&fs::read(path).unwrap()).unwrap(),
+    };
+    let dbml = render_dbml(&diagram, &options);
+    let mut refs: Vec<_> = dbml.lines().filter(|l| l.starts_with("Ref ")).collect();
+    refs.sort_unstable();
+    assert_eq!(
+        refs,
+        [
+            "Ref \"FK_DimCustomer_DimGeography\": \"dbo\".\"DimCustomer\".\"GeographyKey\" > \"dbo\".\"DimGeography\".\"GeographyKey\"",
+            "Ref \"FK_DimGeography_DimSalesTerritory\": \"dbo\".\"DimGeography\".\"SalesTerritoryKey\" > \"dbo\".\"DimSalesTerritory\".\"SalesTerritoryKey\"",
+            "Ref \"FK_DimReseller_DimGeography\": \"dbo\".\"DimReseller\".\"GeographyKey\" > \"dbo\".\"DimGeography\".\"GeographyKey\"",
+        ]
+    );
+    assert!(!dbml.contains("unknown"), "{}", dbml);
+}
+
+#[test]
+fn dbml_composite_keys_are_indexes() {
+    let diagram = diagrams()
+        .into_iter()
+        .find(|d| d.name() == Some("Geography"))
+        .unwrap();
+    let mut catalog = fs::read(common::res("Geography.columns.txt")).unwrap();
+    catalog.extend_from_slice(b"dbo|DimGeography|CountryRegionCode|nvarchar(3)|0|1\n");
+    let options = DbmlOptions {
+        columns: parse_column_catalog(&catalog).unwrap(),
+    };
+    let dbml = render_dbml(&diagram, &options);
+    let table: Vec<_> = dbml
+        .lines()
+        .skip_while(|l| *l != "Table \"dbo\".\"DimGeography\" {")
+        .take_while(|l| *l != "}")
+        .collect();
+    assert!(
+        table.contains(&"  \"GeographyKey\" int [not null]"),
+        "{}",
+        dbml
+    );
+    assert!(table.contains(&"  \"CountryRegionCode\" nvarchar(3) [not null]"));
+    assert!(table.windows(3).any(|w| w
+        == [
+            "  indexes {",
+            "    (\"GeographyKey\", \"CountryRegionCode\") [pk]",
+            "  }",
+        ]));
+    assert!(
+        dbml.lines().any(|l| l == "  \"CustomerKey\" int [pk]"),
+        "{}",
+        dbml
+    );
+}
+
+#[test]
+fn mermaid_ids_are_unique() {
+    let mut diagram = diagrams().remove(0);
+    assert!(diagram.tables.len() >= 2);
+    diagram.tables[0].sch_grid.data_source.table = String::from("A B");
+    diagram.tables[1].sch_grid.data_source.table = String::from("A_B");
+    let mermaid = render_mermaid(&diagram);
+    let mut ids: Vec<_> = mermaid
+        .lines()
+        .filter(|l| l.ends_with("\"]"))
+        .map(|l| l.trim().split('[').next().unwrap())
+        .collect();
+    assert_eq!(ids.len(), diagram.tables.len());
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), diagram.tables.len());
+}
+
+#[test]
+fn relationships_to_unknown_tables_are_noted() {
+    let mut diagram = diagrams()
+        .into_iter()
+        .find(|d| !d.relationships.is_empty())
+        .unwrap();
+    diagram.relationships[0].from = String::from("Missing");
+    let name = format!("{:?}", diagram.relationships[0].name);
+    let outputs = [
+        render_mermaid(&diagram),
+        render_dot(&diagram, &DotOptions::default()),
+        render_plantuml(&diagram, &PlantUmlOptions::default()),
+        render_drawio(&diagram, &DrawioOptions::default()),
+        render_dbml(&diagram, &DbmlOptions::default()),
+    ];
+    for output in &outputs {
+        let line = output.lines().find(|l| l.contains("skipped")).unwrap();
+        assert!(line.contains(&name), "{}", line);
+        assert!(line.contains("\"Missing\""), "{}", line);
+    }
+    roxmltree::Document::parse(&outputs[3]).unwrap();
+
+    diagram.relationships[0].from = String::from("Missing---");
+    let xml = render_drawio(&diagram, &DrawioOptions::default());
+    roxmltree::Document::parse(&xml).unwrap();
+}
+
+#[test]
+fn plantuml_quotes_names() {
+    let mut diagram = diagrams()
+        .into_iter()
+        .find(|d| !d.relationships.is_empty())
+        .unwrap();
+    diagram.relationships[0].name = String::from("FK \"x\"\n@enduml");
+    let uml = render_plantuml(&diagram, &PlantUmlOptions::default());
+    assert_eq!(uml.lines().filter(|l| *l == "@enduml").count(), 1);
+    assert!(uml.contains(" : \"FK 'x' @enduml\"\n"));
+
+    let table = &diagram.tables[0];
+    let ds = &table.sch_grid.data_source;
+    let mut columns = ColumnCatalog::new();
+    let column = Column {
+        name: String::from("Name"),
+        data_type: String::from("nvarchar(50)"),
+        nullable: true,
+        primary_key: false,
+    };
+    columns.push(&ds.schema, &ds.table, column);
+    let uml = render_plantuml(&diagram, &PlantUmlOptions { columns });
+    assert!(uml.contains("    Name : nvarchar(50)\n"));
+    assert!(!uml.contains("    --\n"));
+}
+
+#[test]
+fn plantuml_notes_keep_their_end() {
+    let mut diagram = diagrams()
+        .into_iter()
+        .find(|d| d.relationships.iter().any(|r| !r.labels.is_empty()))
+        .unwrap();
+    let relationship = diagram
+        .relationships
+        .iter_mut()
+        .find(|r| !r.labels.is_empty())
+        .unwrap();
+    let mut annotation = relationship.labels.remove(0);
+    annotation.label.text = String::from("first\r\n End Note\n@enduml\tlast");
+    diagram.labels.push(annotation);
+    let uml = render_plantuml(&diagram, &PlantUmlOptions::default());
+    assert_eq!(uml.lines().filter(|l| *l == "@enduml").count(), 1);
+    assert!(uml.contains("\n  first\n  ~End Note\n  ~@enduml last\nend note\n"));
+}
@@ -0,0 +1,313 @@
+use std::{fs, io::Cursor, path::Path};
+
+use ms_oforms::properties::{Position, Size};
+use sysdiagram::{
+    layout::{
+        apply_layout, layout_tables, reroute, route, update_relationships, LayoutMode,
+        LayoutOptions, Rect,
+    },
+    mdtdb::TableView,
+    Control, SysDiagram, SysDiagramBuilder, SysDiagramFile, SysDiagramStreams,
+};
+
+mod common;
+
+fn size(width: u32, height: u32) -> Size {
+    Size { width, height }
+}
+
+fn pos(left: i32, top: i32) -> Position {
+    Position { left, top }
+}
+
+fn xy(points: &[Position]) -> Vec<(i32, i32)> {
+    points.iter().map(|p| (p.left, p.top)).collect()
+}
+
+fn rect(left: i32, top: i32, right: i32, bottom: i32) -> Rect {
+    Rect {
+        left,
+        top,
+        right,
+        bottom,
+    }
+}
+
+fn rects(positions: &[Position], sizes: &[Size]) -> Vec<Rect> {
+    positions
+        .iter()
+        .zip(sizes)
+        .map(|(pos, size)| Rect::new(pos, size))
+        .collect()
+}
+
+fn assert_no_overlaps(rects: &[Rect]) {
+    for (i, a) in rects.iter().enumerate() {
+        for b in &rects[i + 1..] {
+            assert!(!a.overlaps(b), "{:?} overlaps {:?}", a, b);
+        }
+    }
+}
+
+/// Check that a line is rectilinear, connects the tables and stays clear of `obstacles`
+fn assert_route(points: &[Position], src: &Rect, dest: &Rect, obstacles: &[Rect]) {
+    assert!(points.len() >= 2);
+    assert!(
+        common::on_edge(&points[0], src),
+        "{:?} starts off {:?}",
+        points,
+        src
+    );
+    assert!(
+        common::on_edge(points.last().unwrap(), dest),
+        "{:?} ends off {:?}",
+        points,
+        dest
+    );
+    for segment in points.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        assert!(
+            a.left == b.left || a.top == b.top,
+            "{:?} is diagonal",
+            segment
+        );
+        let bounds = rect(
+            a.left.min(b.left),
+            a.top.min(b.top),
+            a.left.max(b.left),
+            a.top.max(b.top),
+        );
+        for obstacle in obstacles {
+            let crosses = bounds.left < obstacle.right
+                && obstacle.left < bounds.right
+                && bounds.top < obstacle.bottom
+                && obstacle.top < bounds.bottom;
+            assert!(!crosses, "{:?} crosses {:?}", segment, obstacle);
+        }
+    }
+}
+
+#[test]
+fn grid_layout_has_no_overlaps() {
+    let sizes = [
+        size(9155, 8202),
+        size(4339, 6084),
+        size(4339, 1852),
+        size(4339, 794),
+        size(11060, 8202),
+    ];
+    let options = LayoutOptions {
+        mode: LayoutMode::Grid { columns: None },
+        ..LayoutOptions::default()
+    };
+    let positions = layout_tables(&sizes, &[], &options);
+    assert_eq!(positions.len(), sizes.len());
+    // three columns for five tables
+    assert_eq!(positions[0].top, positions[2].top);
+    assert!(positions[3].top > positions[0].top);
+    assert_eq!(positions[3].left, positions[0].left);
+    assert_no_overlaps(&rects(&positions, &sizes));
+}
+
+#[test]
+fn layered_layout_puts_referenced_tables_above() {
+    let sizes = [size(4339, 6084); 5];
+    // 0 <- 1 <- 2, 0 <- 2, 3 <- 4 and a cycle 2 -> 0
+    let edges = [(0, 1), (1, 2), (0, 2), (3, 4), (2, 0)];
+    let positions = layout_tables(&sizes, &edges, &LayoutOptions::default());
+    let table_rects = rects(&positions, &sizes);
+    assert_no_overlaps(&table_rects);
+    assert!(table_rects[0].bottom <= table_rects[1].top);
+    assert!(table_rects[1].bottom <= table_rects[2].top);
+    assert!(table_rects[3].bottom <= table_rects[4].top);
+    assert_eq!(positions[0].top, positions[3].top);
+}
+
+#[test]
+fn route_goes_around_obstacles() {
+    let src = rect(0, 0, 2000, 2000);
+    let dest = rect(8000, 0, 10000, 2000);
+    let wall = rect(4000, -3000, 5000, 5000);
+    let obstacles = [src, dest, wall];
+    let points = route(&src, &dest, &obstacles);
+    assert_route(&points, &src, &dest, &[wall]);
+
+    // Without the wall, the tables are connected by a straight line
+    let points = route(&src, &dest, &[src, dest]);
+    assert_eq!(
+        points,
+        [
+            Position {
+                left: 2000,
+                top: 1000
+            },
+            Position {
+                left: 8000,
+                top: 1000
+            }
+        ]
+    );
+}
+
+#[test]
+fn route_connects_a_table_to_itself() {
+    let table = rect(1000, 1000, 5000, 7000);
+    let points = route(&table, &table, &[table]);
+    assert_route(&points, &table, &table, &[]);
+    let (first, last) = (points[0], points[points.len() - 1]);
+    assert_eq!(first.left, table.right);
+    assert_eq!(last.top, table.top);
+}
+
+fn assert_diagram_layout(diagram: &SysDiagram) {
+    let table_rects: Vec<Rect> = diagram
+        .tables
+        .iter()
+        .map(|t| Rect::new(&t.pos, &t.sch_grid.extent))
+        .collect();
+    assert_no_overlaps(&table_rects);
+    let table = |caption: &str| {
+        let index = diagram
+            .tables
+            .iter()
+            .position(|t| t.caption == caption)
+            .unwrap();
+        table_rects[index]
+    };
+    for relationship in &diagram.relationships {
+        let (src, dest) = (table(&relationship.from), table(&relationship.to));
+        let others: Vec<Rect> = table_rects
+            .iter()
+            .copied()
+            .filter(|r| *r != src && *r != dest)
+            .collect();
+        assert_route(&relationship.control.positions, &src, &dest, &others);
+    }
+}
+
+#[test]
+fn built_diagram_with_layout() {
+    let origin = Position { left: 0, top: 0 };
+    let mut builder = SysDiagramBuilder::new("Data Source=.;Initial Catalog=Shop", "Orders");
+    builder
+        .table("dbo", "Customer", origin, TableView::Standard)
+        .table("dbo", "Order", origin, TableView::ColumnNames)
+        .table("dbo", "OrderLine", origin, TableView::Keys)
+        .table("dbo", "Product", origin, TableView::NameOnly)
+        .foreign_key("FK_Order_Customer", "Customer", "Order")
+        .foreign_key("FK_OrderLine_Order", "Order", "OrderLine")
+        .foreign_key("FK_OrderLine_Product", "Product", "OrderLine")
+        .foreign_key("FK_Customer_Product", "Product", "Customer");
+    builder.layout(&LayoutOptions::default()).unwrap();
+
+    let bytes = builder.build().unwrap().to_bytes().unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(bytes)).unwrap();
+    let diagram = file.diagram().unwrap();
+    assert_diagram_layout(&diagram);
+    let top = |caption: &str| {
+        let table = diagram.tables.iter().find(|t| t.caption == caption);
+        table.unwrap().pos.top
+    };
+    assert!(top("Product") < top("Customer"));
+    assert!(top("Customer") < top("Order"));
+    assert!(top("Order") < top("OrderLine"));
+}
+
+#[test]
+fn apply_layout_to_sample() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/Internet Sales.sysdiagram");
+    let mut file = SysDiagramFile::open(fs::File::open(path).unwrap()).unwrap();
+    let mut streams = file.streams().unwrap();
+    let options = LayoutOptions {
+        mode: LayoutMode::Grid { columns: Some(4) },
+        ..LayoutOptions::default()
+    };
+    apply_layout(&mut streams, &options);
+
+    let bytes = streams.to_bytes().unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(bytes)).unwrap();
+    assert_diagram_layout(&file.diagram().unwrap());
+}
+
+#[test]
+fn apply_layout_keeps_lines_with_other_tooltips() {
+    let mut streams = common::geography().streams().unwrap();
+    let index = streams
+        .controls
+        .iter()
+        .position(|c| matches!(c, Control::Polyline(_)))
+        .unwrap();
+    streams.form.sites[index].tooltip = String::from("Beziehung 'FK_DimCustomer_DimGeography'");
+    let points = |streams: &SysDiagramStreams| match &streams.controls[index] {
+        Control::Polyline(polyline) => polyline
+            .positions
+            .iter()
+            .map(|p| (p.left, p.top))
+            .collect::<Vec<_>>(),
+        _ => unreachable!(),
+    };
+    let before = points(&streams);
+    apply_layout(&mut streams, &LayoutOptions::default());
+    assert_eq!(points(&streams), before);
+}
+
+#[test]
+fn reroute_keeps_bend_points() {
+    let src = rect(0, 4000, 4000, 10000);
+    let dest = rect(10000, 0, 14000, 3000);
+    let line = [
+        pos(4000, 6000),
+        pos(7000, 6000),
+        pos(7000, 2000),
+        pos(10000, 2000),
+    ];
+    let points = reroute(&line, &src, &dest, &[src, dest]);
+    assert_eq!(xy(&points), xy(&line));
+
+    // Only the last bend point moves along with the table
+    let moved = rect(10000, -2000, 14000, 1000);
+    let points = reroute(&line, &src, &moved, &[src, moved]);
+    assert_eq!(xy(&points[..2]), xy(&line[..2]));
+    assert_eq!(points[2].left, 7000);
+    assert_eq!(points[3].left, 10000);
+    assert_eq!(points[2].top, points[3].top);
+    assert_route(&points, &src, &moved, &[]);
+
+    // A table in the way makes for a new line
+    let wall = rect(6000, 1000, 8000, 3000);
+    let points = reroute(&line, &src, &dest, &[src, dest, wall]);
+    assert_route(&points, &src, &dest, &[wall]);
+}
+
+#[test]
+fn update_relationships_after_move() {
+    let mut streams = common::geography().streams().unwrap();
+
+    // Nothing moved, nothing changes
+    let points = |streams: &SysDiagramStreams| -> Vec<Vec<(i32, i32)>> {
+        let sites = streams.form.sites.iter().map(|site| xy(&[site.pos]));
+        let lines = streams.controls.iter().filter_map(|control| match control {
+            Control::Polyline(polyline) => Some(xy(&polyline.positions)),
+            _ => None,
+        });
+        sites.chain(lines).collect()
+    };
+    let before = points(&streams);
+    update_relationships(&mut streams);
+    assert_eq!(points(&streams), before);
+
+    let site = &mut streams.form.sites[0];
+    assert_eq!(site.tooltip, "DimGeography");
+    site.pos.top += 3000;
+    update_relationships(&mut streams);
+
+    let bytes = streams.to_bytes().unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(bytes)).unwrap();
+    let diagram = file.diagram().unwrap();
+    assert_diagram_layout(&diagram);
+    for relationship in &diagram.relationships {
+        for (label_ref, label) in relationship.control.labels.iter().zip(&relationship.labels) {
+            assert_eq!(xy(&[label_ref.pos]), xy(&[label.pos]));
+        }
+    }
+}
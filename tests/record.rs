@@ -0,0 +1,68 @@
+use std::fs;
+
+use sysdiagram::{parse_sysdiagrams_csv, DiagramRecord, Error};
+
+mod common;
+
+#[test]
+fn parse_sysdiagrams_export() {
+    let input = fs::read(common::res("sysdiagrams.csv")).unwrap();
+    let records = parse_sysdiagrams_csv(&input).unwrap();
+    let rows: Vec<_> = records
+        .iter()
+        .map(|r| (r.name.as_str(), r.principal_id, r.diagram_id, r.version))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ("ProductInventory", 1, 7, Some(1)),
+            ("Sales: \"Q1\" <draft>?", 1, 12, None),
+            ("..", 5, 13, None),
+        ]
+    );
+    assert_eq!(
+        records[0].definition,
+        fs::read(common::res("ProductInventory.sysdiagram")).unwrap()
+    );
+    let mut file = records[0].open().unwrap();
+    assert!(!file.diagram().unwrap().tables.is_empty());
+    assert!(records[1].definition.is_empty());
+
+    let names: Vec<_> = records.iter().map(DiagramRecord::file_name).collect();
+    assert_eq!(
+        names,
+        [
+            "7_ProductInventory.sysdiagram",
+            "12_Sales_ _Q1_ _draft__.sysdiagram",
+            "13_...sysdiagram",
+        ]
+    );
+}
+
+#[test]
+fn file_names_stay_in_the_directory() {
+    let record = |name: &str| DiagramRecord {
+        name: name.to_string(),
+        principal_id: 1,
+        diagram_id: 2,
+        version: Some(1),
+        definition: Vec::new(),
+    };
+    assert_eq!(record("../a\\b").file_name(), "2_.._a_b.sysdiagram");
+    assert_eq!(record("").file_name(), "2_.sysdiagram");
+    assert_eq!(record("nul").file_name(), "2_nul.sysdiagram");
+    assert_eq!(record("a*b\tc").file_name(), "2_a_b_c.sysdiagram");
+}
+
+#[test]
+fn invalid_rows_are_reported_with_their_line() {
+    let input = b"name|principal_id|diagram_id|version|definition\nA|1|x|1|00\n";
+    match parse_sysdiagrams_csv(input) {
+        Err(Error::Record(2, e)) => assert!(matches!(*e, Error::InvalidInteger(_))),
+        other => panic!("expected an invalid integer, got {:?}", other),
+    }
+    match parse_sysdiagrams_csv(b"A|1|2|1\n") {
+        Err(Error::Record(1, e)) => assert!(matches!(*e, Error::FieldCount(5, 4))),
+        other => panic!("expected a field count error, got {:?}", other),
+    }
+}
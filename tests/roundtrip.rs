@@ -0,0 +1,246 @@
+use std::{
+    fs,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use bstr::BString;
+use sysdiagram::{
+    dds::{CLSID_DDSLABEL, CLSID_POLYLINE},
+    mdtdb::CLSID_SCHGRID,
+    write_control, Control, Error, SysDiagramFile, SysDiagramStreams, Variant,
+};
+
+mod common;
+
+fn assert_same_streams(path: &Path, original: &[u8], written: &[u8]) {
+    let (expected, actual) = (
+        common::read_streams(original),
+        common::read_streams(written),
+    );
+    let names = |streams: &[(PathBuf, Vec<u8>)]| -> Vec<PathBuf> {
+        streams.iter().map(|(name, _)| name.clone()).collect()
+    };
+    assert_eq!(names(&expected), names(&actual), "{}", path.display());
+    for ((name, expected), (_, actual)) in expected.iter().zip(&actual) {
+        assert!(
+            expected == actual,
+            "{}: stream {:?} differs",
+            path.display(),
+            name
+        );
+    }
+}
+
+#[test]
+fn parse_write_reproduces_files() {
+    for path in common::samples() {
+        let original = fs::read(&path).unwrap();
+        let streams = SysDiagramStreams::from_bytes(original.clone()).unwrap();
+        let written = streams.to_bytes().unwrap();
+        assert!(written == original, "{}: file differs", path.display());
+    }
+}
+
+#[test]
+fn parse_write_reproduces_streams() {
+    for path in common::samples() {
+        let original = fs::read(&path).unwrap();
+        let mut file = SysDiagramFile::open(Cursor::new(&original[..])).unwrap();
+        let streams = file.streams().unwrap();
+        let written = streams.to_bytes().unwrap();
+        assert_same_streams(&path, &original, &written);
+    }
+}
+
+#[test]
+fn changed_streams_are_replaced_in_the_container() {
+    let path = common::res("Geography.sysdiagram");
+    let mut streams = SysDiagramStreams::from_bytes(fs::read(&path).unwrap()).unwrap();
+    streams.form.sites[0].tooltip.push_str(" (renamed)");
+    let written = streams.to_bytes().unwrap();
+    streams.container = None;
+    assert_same_streams(&path, &streams.to_bytes().unwrap(), &written);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_reproduces_diagrams() {
+    let mut fonts = 0;
+    for path in common::samples() {
+        let original = fs::read(&path).unwrap();
+        let mut file = SysDiagramFile::open(Cursor::new(&original[..])).unwrap();
+        let diagram = file.diagram().unwrap();
+        fonts += diagram.labels.len();
+        fonts += diagram
+            .relationships
+            .iter()
+            .map(|r| r.labels.len())
+            .sum::<usize>();
+
+        // `StdFont` and `OleColor` have no `PartialEq`, so compare the debug output
+        let json = serde_json::to_value(&diagram).unwrap();
+        let read: sysdiagram::SysDiagram = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(
+            format!("{:?}", read),
+            format!("{:?}", diagram),
+            "{}",
+            path.display()
+        );
+        assert_eq!(serde_json::to_value(&read).unwrap(), json);
+    }
+    assert!(fonts > 0);
+}
+
+#[test]
+fn unknown_controls_are_written_as_read() {
+    let path = common::res("Geography.sysdiagram");
+    let original = fs::read(&path).unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(&original[..])).unwrap();
+    let mut streams = file.streams().unwrap();
+    for control in &mut streams.controls {
+        let clsid = match control {
+            Control::SchGrid(_) => CLSID_SCHGRID,
+            Control::Polyline(_) => CLSID_POLYLINE,
+            Control::Label(_) => CLSID_DDSLABEL,
+            Control::Unknown { clsid, .. } => *clsid,
+        };
+        let mut data = Vec::new();
+        write_control(&mut data, control).unwrap();
+        *control = Control::Unknown {
+            clsid,
+            data: BString::from(data),
+        };
+    }
+    let written = streams.to_bytes().unwrap();
+    assert_same_streams(&path, &original, &written);
+}
+
+#[test]
+fn site_that_is_too_long_is_an_error() {
+    let path = common::res("Geography.sysdiagram");
+    let mut file = SysDiagramFile::open(fs::File::open(path).unwrap()).unwrap();
+    let mut streams = file.streams().unwrap();
+    streams.form.sites[0].tooltip = "x".repeat(0x10000);
+    match streams.to_bytes() {
+        Err(Error::BufTooLong(_)) => {}
+        other => panic!("expected a BufTooLong error, got {:?}", other),
+    }
+}
+
+#[test]
+fn polyline_with_too_many_points_is_an_error() {
+    let bytes = fs::read(common::res("Geography.sysdiagram")).unwrap();
+    let mut streams = SysDiagramStreams::from_bytes(bytes).unwrap();
+    let polyline = streams
+        .controls
+        .iter_mut()
+        .find_map(|control| match control {
+            Control::Polyline(polyline) => Some(polyline),
+            _ => None,
+        })
+        .unwrap();
+    let pos = polyline.positions.last().cloned().unwrap();
+    polyline.positions.resize(0x10000, pos);
+    match streams.to_bytes() {
+        Err(Error::BufTooLong(_)) => {}
+        other => panic!("expected a BufTooLong error, got {:?}", other),
+    }
+}
+
+#[test]
+fn dds_control_with_too_many_properties_is_an_error() {
+    let bytes = fs::read(common::res("Geography.sysdiagram")).unwrap();
+    let mut streams = SysDiagramStreams::from_bytes(bytes).unwrap();
+    let properties = &mut streams.dds_stream.controls[0].properties;
+    for i in 0..0x10000 {
+        properties.insert(format!("p{}", i), Variant::Bool(false));
+    }
+    match streams.to_bytes() {
+        Err(Error::BufTooLong(_)) => {}
+        other => panic!("expected a BufTooLong error, got {:?}", other),
+    }
+}
+
+#[test]
+fn extract_streams_keeps_storages() {
+    let original = fs::read(common::res("Geography.sysdiagram")).unwrap();
+    let mut cfb = cfb::CompoundFile::open(Cursor::new(original)).unwrap();
+    cfb.create_storage("/sub").unwrap();
+    let mut stream = cfb.create_stream("/sub/f").unwrap();
+    stream.write_all(b"nested").unwrap();
+    drop(stream);
+    cfb.flush().unwrap();
+    let bytes = cfb.into_inner().into_inner();
+
+    let dir = std::env::temp_dir().join(format!("sysdiagram-extract-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(bytes)).unwrap();
+    let mut written = file.extract_streams(&dir, &["f"]).unwrap();
+    written.sort();
+    assert_eq!(written, [dir.join("f"), dir.join("sub").join("f")]);
+    assert_eq!(
+        fs::read(&written[0]).unwrap(),
+        file.read_stream("/f").unwrap()
+    );
+    assert_eq!(fs::read(&written[1]).unwrap(), b"nested");
+
+    let written = file.extract_streams(&dir, &["DdsStream"]).unwrap();
+    assert_eq!(written, [dir.join("_03DdsStream")]);
+    let expected = file.read_stream("/\x03DdsStream").unwrap();
+    assert_eq!(fs::read(&written[0]).unwrap(), expected);
+
+    match file.extract_streams(&dir, &["missing"]) {
+        Err(Error::UnknownStream(name)) => assert_eq!(name, "missing"),
+        other => panic!("expected an unknown stream, got {:?}", other),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn site_objects_split_the_o_stream() {
+    for path in common::samples() {
+        let original = fs::read(&path).unwrap();
+        let mut file = SysDiagramFile::open(Cursor::new(&original[..])).unwrap();
+        let objects = file.site_objects().unwrap();
+        let joined: Vec<u8> = objects.iter().flat_map(|o| o.data.clone()).collect();
+        assert_eq!(joined, file.read_stream("/o").unwrap());
+
+        let form = file.form_stream().unwrap();
+        let ids: Vec<_> = form.sites.iter().map(|s| s.id).collect();
+        assert_eq!(ids, objects.iter().map(|o| o.id).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn annotated_fields_cover_the_streams() {
+    for path in common::samples() {
+        let original = fs::read(&path).unwrap();
+        let mut file = SysDiagramFile::open(Cursor::new(&original[..])).unwrap();
+        let streams = common::read_streams(&original);
+        for name in ["f", "o", "DdsStream", "DSREF-SCHEMA-CONTENTS"] {
+            // Fails with `AnnotationMismatch` if the fields don't record the stream
+            let (bytes, fields) = match file.annotated_stream(name) {
+                Ok(annotated) => annotated,
+                Err(e) => panic!("{}: {}", path.display(), e),
+            };
+            let file_name = |p: &Path| p.file_name().unwrap().to_string_lossy().into_owned();
+            let (_, stream) = streams
+                .iter()
+                .find(|(p, _)| file_name(p.as_path()).trim_start_matches(char::is_control) == name)
+                .unwrap();
+            assert!(bytes == *stream, "{}: {}", path.display(), name);
+
+            let mut offset = 0;
+            for field in &fields {
+                assert_eq!(field.range.start, offset, "{}: {}", name, field.name);
+                offset = field.range.end;
+            }
+            assert_eq!(offset, bytes.len(), "{}: {}", path.display(), name);
+
+            let mut dump = Vec::new();
+            sysdiagram::write_hexdump(&mut dump, &bytes, &fields, false).unwrap();
+            assert!(!dump.is_empty());
+        }
+    }
+}
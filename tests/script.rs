@@ -0,0 +1,45 @@
+use sysdiagram::{decode_hex, render_sql_script, DiagramRecord, ScriptOptions, ScriptStatement};
+
+fn record(definition: Vec<u8>) -> DiagramRecord {
+    DiagramRecord {
+        name: String::from("Bob's Diagram"),
+        principal_id: 1,
+        diagram_id: 0,
+        version: Some(1),
+        definition,
+    }
+}
+
+#[test]
+fn insert_with_inline_literal() {
+    let sql = render_sql_script(&record(vec![0xD0, 0xCF, 0x11]), &ScriptOptions::default());
+    assert_eq!(
+        sql,
+        "INSERT INTO [dbo].[sysdiagrams] ([name], [principal_id], [version], [definition])\n\
+         VALUES (N'Bob''s Diagram', 1, 1, 0xD0CF11);\n"
+    );
+}
+
+#[test]
+fn chunks_concatenate_to_the_definition() {
+    let definition: Vec<u8> = (0..=255).cycle().take(1000).collect();
+    let options = ScriptOptions {
+        statement: ScriptStatement::CreateDiagram,
+        chunk_size: 300,
+    };
+    let sql = render_sql_script(&record(definition.clone()), &options);
+
+    let chunks: Vec<&str> = sql
+        .lines()
+        .filter(|l| l.starts_with("SET @definition"))
+        .map(|l| &l[l.find("0x").unwrap()..l.len() - 1])
+        .collect();
+    assert_eq!(chunks.len(), 4);
+    let mut joined = Vec::new();
+    for chunk in chunks {
+        joined.extend(decode_hex(chunk.as_bytes()).unwrap());
+    }
+    assert_eq!(joined, definition);
+    assert!(sql.contains("EXEC [dbo].[sp_creatediagram]"));
+    assert!(sql.contains("    @definition = @definition;\n"));
+}
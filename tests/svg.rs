@@ -0,0 +1,148 @@
+use std::{fs, io::Cursor, path::Path};
+
+use sysdiagram::{
+    dds::DdsPolylineEndType,
+    mdtdb::{PropViewColumn, TableView, TableViewMode},
+    render::{
+        rgb,
+        svg::{render_svg, SvgOptions, Theme},
+    },
+    Column, ColumnCatalog, SysDiagram, SysDiagramFile,
+};
+
+fn diagram(name: &str) -> SysDiagram {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join(name);
+    let bytes = fs::read(path).unwrap();
+    let mut file = SysDiagramFile::open(Cursor::new(bytes)).unwrap();
+    file.diagram().unwrap()
+}
+
+#[test]
+fn svg_has_all_tables_and_relationships() {
+    let diagram = diagram("Geography.sysdiagram");
+    let svg = render_svg(&diagram, &SvgOptions::default());
+    assert!(svg.starts_with("<?xml"));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    for table in &diagram.tables {
+        assert!(svg.contains(&format!("<g id=\"c{}\">", table.id)));
+    }
+    for relationship in &diagram.relationships {
+        assert!(svg.contains(&format!(
+            "<polyline stroke-width=\"1\" id=\"c{}\"",
+            relationship.id
+        )));
+    }
+}
+
+#[test]
+fn light_theme_uses_the_back_color_of_the_form() {
+    let diagram = diagram("Geography.sysdiagram");
+    let expected = rgb(diagram.back_color).map_or(String::from("#ffffff"), |c| c.to_string());
+    let svg = render_svg(&diagram, &SvgOptions::default());
+    assert!(svg.contains(&format!("style=\"background-color: {}\"", expected)));
+
+    let options = SvgOptions {
+        theme: Theme::dark(),
+        ..SvgOptions::default()
+    };
+    let svg = render_svg(&diagram, &options);
+    assert!(svg.contains("style=\"background-color: #1e1e1e\""));
+}
+
+#[test]
+fn cascade_ends_have_the_many_and_key_colors() {
+    let mut diagram = diagram("Geography.sysdiagram");
+    for relationship in &mut diagram.relationships {
+        relationship.control.end_type_src = DdsPolylineEndType::KeyUpdateDelete;
+        relationship.control.end_type_dest = DdsPolylineEndType::ManyDelete;
+    }
+    let svg = render_svg(&diagram, &SvgOptions::default());
+    let count = diagram.relationships.len();
+    assert_eq!(svg.matches(r#"r="2" fill="orange""#).count(), count);
+    assert_eq!(svg.matches(r#"r="2" fill="yellow""#).count(), count);
+}
+
+/// Render all tables of a sample in `view`, with a key and a non-key column each
+fn render_view(view: TableView, view_mode: Option<TableViewMode>) -> String {
+    let mut diagram = diagram("Geography.sysdiagram");
+    let mut columns = ColumnCatalog::new();
+    for table in &mut diagram.tables {
+        table.view = Some(view);
+        table.view_mode = view_mode.clone();
+        table.sch_grid.extent.height = 10_000;
+        let data_source = &table.sch_grid.data_source;
+        for (name, primary_key) in [("KeyColumn", true), ("OtherColumn", false)] {
+            let column = Column {
+                name: name.to_string(),
+                data_type: String::from("int"),
+                nullable: !primary_key,
+                primary_key,
+            };
+            columns.push(&data_source.schema, &data_source.table, column);
+        }
+    }
+    let options = SvgOptions {
+        columns,
+        ..SvgOptions::default()
+    };
+    render_svg(&diagram, &options)
+}
+
+#[test]
+fn standard_view_has_type_and_nulls() {
+    let svg = render_view(TableView::Standard, None);
+    assert!(svg.contains(">Column Name</text>"));
+    assert!(svg.contains(">Data Type</text>"));
+    assert!(svg.contains(">Allow Nulls</text>"));
+    assert!(svg.contains(">KeyColumn</text>"));
+    assert!(svg.contains(">OtherColumn</text>"));
+}
+
+#[test]
+fn column_names_view_has_no_header() {
+    let svg = render_view(TableView::ColumnNames, None);
+    assert!(!svg.contains(">Column Name</text>"));
+    assert!(svg.contains(">KeyColumn</text>"));
+    assert!(svg.contains(">OtherColumn</text>"));
+}
+
+#[test]
+fn keys_view_has_only_key_columns() {
+    let svg = render_view(TableView::Keys, None);
+    assert!(svg.contains(">KeyColumn</text>"));
+    assert!(!svg.contains(">OtherColumn</text>"));
+}
+
+#[test]
+fn name_only_view_has_no_columns() {
+    let svg = render_view(TableView::NameOnly, None);
+    assert!(!svg.contains(">KeyColumn</text>"));
+    assert!(!svg.contains(">OtherColumn</text>"));
+}
+
+#[test]
+fn custom_view_uses_the_table_view_mode() {
+    let svg = render_view(TableView::Custom, None);
+    assert!(svg.contains(">Condensed Type</text>"));
+    assert!(svg.contains(">NOT NULL</text>"));
+
+    let mode = TableViewMode::parse("3,0,284,0,1980,16,3000").unwrap();
+    assert_eq!(
+        mode.columns,
+        [
+            (PropViewColumn::ColumnName, 1980),
+            (PropViewColumn::Description, 3000)
+        ]
+    );
+    assert_eq!(mode.to_string(), "3,0,284,0,1980,16,3000");
+    let svg = render_view(TableView::Custom, Some(mode));
+    assert!(svg.contains(">Description</text>"));
+    assert!(!svg.contains(">Condensed Type</text>"));
+}
+
+#[test]
+fn huge_column_widths_do_not_overflow() {
+    let mode = TableViewMode::parse(&format!("2,0,{0},0,{0}", u32::MAX)).unwrap();
+    let svg = render_view(TableView::Custom, Some(mode));
+    assert!(svg.contains(">KeyColumn</text>"));
+}
@@ -0,0 +1,41 @@
+use sysdiagram::{parse_variant, write_variant, Variant};
+use uuid::uuid;
+
+#[test]
+fn variant_roundtrip() {
+    let values = [
+        (Variant::Empty, 2),
+        (Variant::Null, 2),
+        (Variant::I1(-2), 3),
+        (Variant::I2(-300), 4),
+        (Variant::I4(-70_000), 6),
+        (Variant::I8(-5_000_000_000), 10),
+        (Variant::Ui1(200), 3),
+        (Variant::Ui2(60_000), 4),
+        (Variant::Ui4(4_000_000_000), 6),
+        (Variant::Ui8(u64::MAX), 10),
+        (Variant::R4(1.5), 6),
+        (Variant::R8(-0.25), 10),
+        (Variant::Cy(12_345_000), 10),
+        (Variant::Date(45_000.5), 10),
+        (Variant::BStr("Table".to_string()), 18),
+        (Variant::Error(0x8000_4005), 6),
+        (Variant::Bool(true), 4),
+        (
+            Variant::Clsid(uuid!("e9b0e6db-811c-11d0-ad51-00a0c90f5739")),
+            18,
+        ),
+    ];
+    for (value, len) in values {
+        let mut buf = Vec::new();
+        write_variant(&mut buf, &value).unwrap();
+        assert_eq!(buf.len(), len, "{:?}", value);
+        assert_eq!(
+            u16::from_le_bytes([buf[0], buf[1]]),
+            value.var_type().bits()
+        );
+        let (rest, parsed) = parse_variant::<nom::error::Error<_>>(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed, value);
+    }
+}